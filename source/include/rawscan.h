@@ -20,6 +20,15 @@
 
 #include <stdbool.h>
 
+// sys/types.h: needed for "ssize_t", returned by rs_read_fn's,
+// and "off_t", input byte offsets
+
+#include <sys/types.h>
+
+// stdint.h: needed for "uint64_t", line numbers
+
+#include <stdint.h>
+
 typedef struct RAWSCAN RAWSCAN; // support opaque pointers to RAWSCAN structs
 
 // Enumerate the various kinds of RAWSCAN_RESULT's that rs_getline returns.
@@ -38,6 +47,10 @@ enum rs_result_type {
 
     // The RAWSCAN_RESULT->errnum field is valid:
     rt_err,                // end of data due to read error
+
+    // No further RAWSCAN_RESULT fields are valid (added last, so as
+    // not to renumber the above):
+    rt_would_block,        // no input ready yet; poll fd, call again
 };
 
 /*
@@ -63,12 +76,126 @@ func_static RAWSCAN *rs_open (
   char delimiterbyte   // newline '\n' or other byte marking end of "lines"
 );
 
+// rs_open(), with flags.  RS_SENTINEL_IN_BUFFER keeps the sentinel copy
+// of the delimiterbyte in the last, writable, buffer page, rather than
+// in a page of its own made read-only with mprotect(2), saving a page.
+
+#define RS_SENTINEL_IN_BUFFER 0x1
+
+func_static RAWSCAN *rs_open_flags (
+  int fd,              // read input from this (already open) file descriptor
+  size_t bufsz,        // main input buffer size
+  char delimiterbyte,  // newline '\n' or other byte marking end of "lines"
+  unsigned int flags   // RS_SENTINEL_IN_BUFFER, or 0
+);
+
+/*
+ * rs_open_with_buffer() opens a stream in memory supplied by the
+ * caller, rather than allocating any, so that programs that must not
+ * allocate memory after startup can still use rawscan.  That memory
+ * holds the RAWSCAN structure, the buffer, and a sentinel copy of the
+ * delimiterbyte, so it must be RS_BUFFER_OVERHEAD bytes larger than
+ * the buffer wanted.  To get a "bufsz" byte buffer, for example:
+ *
+ *      static char mem[RS_BUFFER_SPACE(bufsz)];
+ *      RAWSCAN *rsp = rs_open_with_buffer(fd, mem, sizeof(mem), '\n');
+ */
+
+#define RS_BUFFER_OVERHEAD 512
+#define RS_BUFFER_SPACE(bufsz) ((bufsz) + RS_BUFFER_OVERHEAD)
+
+func_static RAWSCAN *rs_open_with_buffer (
+  int fd,              // read input from this (already open) file descriptor
+  void *mem,           // caller supplied memory, for stream and buffer
+  size_t len,          // size of mem: RS_BUFFER_SPACE(bufsz) for bufsz buffer
+  char delimiterbyte   // newline '\n' or other byte marking end of "lines"
+);
+
+// Scan the lines already in memory, in place, instead of reading a file
+// descriptor.  That memory may be read-only, as are the lines returned.
+
+func_static RAWSCAN *rs_open_memory (
+  const void *mem,     // scan lines in this memory, which may be read-only
+  size_t len,          // size of mem, in bytes
+  char delimiterbyte   // newline '\n' or other byte marking end of "lines"
+);
+
 func_static void rs_close(RAWSCAN *rsp);
+func_static RAWSCAN *rs_resize(RAWSCAN *rsp, size_t new_bufsz);
+
+/*
+ * By default, rawscan streams read their input with read(2), from
+ * the file descriptor passed to rs_open().  rs_set_read_fn() replaces
+ * that with a call to the caller's own input routine, such as a
+ * decompressor or an in-process generator, which rawscan will call
+ * as readfn(context, buf, count), expecting the same results as from
+ * read(2): the number of bytes (at most count) put in buf, else 0 at
+ * end of input, else -1 with errno set.  A NULL readfn restores read(2).
+ */
+
+typedef ssize_t (*rs_read_fn)(void *context, void *buf, size_t count);
+
+func_static void rs_set_read_fn(RAWSCAN *rsp, rs_read_fn readfn, void *context);
+
+// Follow mode, as for "tail -f": at end of input, sleep interval_ms
+// and read again, rather than returning rt_eof.  0 turns it off.
+
+func_static void rs_set_follow(RAWSCAN *rsp, unsigned int interval_ms);
+func_static unsigned int rs_get_follow(RAWSCAN *rsp);
+
 func_static void rs_enable_pause(RAWSCAN *rsp);
 func_static void rs_disable_pause(RAWSCAN *rsp);
 func_static void rs_resume_from_pause(RAWSCAN *rsp);
 func_static RAWSCAN_RESULT rs_getline (RAWSCAN *rsp);
+func_static int rs_set_delimiterbyte(RAWSCAN *rsp, char delimiterbyte);
+func_static char rs_get_delimiterbyte(RAWSCAN *rsp);
+func_static RAWSCAN_RESULT rs_extend_record(RAWSCAN *rsp);
+func_static int rs_unget(RAWSCAN *rsp, size_t offset);
+func_static void rs_set_paragraph_mode(RAWSCAN *rsp, bool paragraph_mode);
+func_static bool rs_get_paragraph_mode(RAWSCAN *rsp);
 func_static int rs_set_min1stchunklen(RAWSCAN *rsp, size_t min1stchunklen);
 func_static size_t rs_get_min1stchunklen(RAWSCAN *rsp);
+func_static size_t rs_get_buffered_bytes(RAWSCAN *rsp);
+func_static size_t rs_get_bufsz(RAWSCAN *rsp);
+func_static bool rs_eof_seen(RAWSCAN *rsp);
+func_static bool rs_err_seen(RAWSCAN *rsp);
+func_static bool rs_in_longline(RAWSCAN *rsp);
+func_static bool rs_is_paused(RAWSCAN *rsp);
+func_static off_t rs_get_line_offset(RAWSCAN *rsp);
+func_static off_t rs_get_chunk_offset(RAWSCAN *rsp);
+func_static uint64_t rs_get_line_number(RAWSCAN *rsp);
+func_static uint64_t rs_get_delimiter_count(RAWSCAN *rsp);
+func_static off_t rs_seek(RAWSCAN *rsp, off_t offset);
+func_static off_t rs_tell(RAWSCAN *rsp);
+
+
+/*
+ * Helpers for splitting a line returned by rs_getline() into fields,
+ * at each "sep" byte (such as '\t' or ','), or at runs of "sep" bytes
+ * (such as spaces), without copying.  Each field is a pointer into
+ * the line, and a length, as fields may be empty.
+ */
+
+typedef struct {
+    const char *begin;          // ptr to first byte in field
+    size_t len;                 // number of bytes in field, maybe 0
+} RAWSCAN_FIELD;
+
+typedef struct {
+    const char *next;           // start of next field
+    const char *end;            // just past the last byte to split
+    char sep;                   // field separator byte
+    bool runs;                  // runs of sep are one separator
+    bool done;                  // no more fields
+} RAWSCAN_FIELDS;
+
+func_static void rs_fields_init(RAWSCAN_FIELDS *fsp, RAWSCAN_RESULT rt,
+                                                    char sep, bool runs);
+func_static bool rs_next_field(RAWSCAN_FIELDS *fsp, RAWSCAN_FIELD *fieldp);
+func_static size_t rs_field_count(RAWSCAN_RESULT rt, char sep, bool runs);
+func_static bool rs_nth_field(RAWSCAN_RESULT rt, char sep, bool runs,
+                                        size_t n, RAWSCAN_FIELD *fieldp);
+func_static int rs_field_to_long(RAWSCAN_FIELD field, long *valp);
+func_static int rs_field_to_double(RAWSCAN_FIELD field, double *valp);
 
 #endif /* _RAWSCAN_H */
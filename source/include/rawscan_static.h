@@ -44,7 +44,7 @@
 #include <errno.h>
 #include <fcntl.h>
 #include <limits.h>
-#include <sys/mman.h>
+#include <math.h>
 #include <signal.h>
 #include <stdint.h>
 #include <stdio.h>
@@ -58,8 +58,9 @@
 #define __USE_GNU
 #include <string.h>
 
- /* Need glibc Feature Test Macro __USE_MISC to pick up sbrk(), brk() */
+ /* Need glibc Feature Test Macro __USE_MISC to pick up MAP_ANONYMOUS */
 #define __USE_MISC
+#include <sys/mman.h>
 #include <unistd.h>
 
 // cmake debug builds enable asserts (NDEBUG not defined),
@@ -292,9 +293,13 @@ typedef struct RAWSCAN {
     const char *p, *q;      // [begin, end) of not yet returned chars in buf
 
     int fd;                 // open file descriptor to read rawscan input from
+    rs_read_fn readfn;      // if not NULL, call this instead of read(2) on fd
+    void *readfn_context;   // first argument to each readfn call
     int errnum;             // stashed errno from failed system calls
+    volatile sig_atomic_t follow_ms;  // if > 0, at eof, sleep this long, retry
 
     size_t pgsz;            // hardware memory page size
+    size_t mapsz;           // size of our mmap'd region, for munmap
     size_t bufsz;           // main input buffer size
     size_t min1stchunklen;  // guaranteed min len of first chunk of long line
 
@@ -315,14 +320,32 @@ typedef struct RAWSCAN {
 
     RAWSCAN_RESULT result;  // rs_getline() returns a copy of this result
 
+    // Where we are in the input, for rs_get_line_offset() and
+    // rs_get_line_number().  Lines are found by scanning from p, so
+    // it's the delimiterbytes before p that are counted, adding those
+    // in each line or chunk as it is returned, except for the first
+    // counted_len bytes at p, which rs_extend_record() has already
+    // counted, as part of the record it's extending.
+
+    off_t q_offset;         // input byte offset of *q: bytes read so far
+    off_t longline_offset;  // input byte offset of start of long line
+    uint64_t ndelims;       // delimiterbytes in input before p
+    size_t counted_len;     // bytes at p whose delimiterbytes are counted
+
     char delimiterbyte;     // byte @ end of "lines" (e.g. '\n' or '\0')
     bool in_longline;       // seen begin of too long line, but not yet end
     bool terminate_current_pause;  // resume from current pause
+    bool paused;            // returned rt_paused, not yet resumed
 
     bool longline_ended;    // end of long line seen
     bool eof_seen;          // eof seen - can read no more into buffer
     bool err_seen;          // read err seen - can read no more into buffer
     bool pause_on_inval;    // pause when need to invalidate buffer
+    bool bounded_search;    // no sentinel at buftop: memchr(), not rawmemchr()
+    bool sentinel_in_buffer;    // sentinel at buftop is writable, not mprotect'd
+    bool paragraph_mode;    // records end at two consecutive delimiterbytes
+    bool chunk_ended_in_delim;  // last longline chunk ended in delimiterbyte
+    bool would_block;       // last read found no input ready (EAGAIN)
 } RAWSCAN;
 
 // We must allocate enough memory to hold:
@@ -333,10 +356,13 @@ typedef struct RAWSCAN {
 // The sentinel page will have copy of the delimiterbyte in its
 // first byte, with its permissions changed to read-only.
 //
-// There are various ways we could do this, using the various malloc,
-// memalign, brk, and sbrk calls available to us.
+// We allocate all of this with a single anonymous mmap(2) call,
+// rather than using malloc, or moving the data break with brk and
+// sbrk, so that rawscan streams neither depend on nor disturb
+// whatever else in the process is using malloc or the data break,
+// and so that rs_close() can hand all of it back with one munmap(2).
 //
-// We will allocate N+2 pages for this, with the input buffer
+// We will map N+2 pages for this, with the input buffer
 // occupying the upper end of the middle N pages, placed so that
 // its top ends exactly at the top of those N pages, where N ==
 // (buffer_pg_size_in_bytes/pgsz).
@@ -344,7 +370,9 @@ typedef struct RAWSCAN {
 // The RAWSCAN *rsp structure will be occupying a small portion
 // of the bottom most page, and the read-only sentinel byte, a copy
 // of the specified delimiterbyte, will be occupying the first byte
-// at the very beginning of the top most page.
+// at the very beginning of the top most page.  (Or, opened by
+// rs_open_flags() with RS_SENTINEL_IN_BUFFER, we map just N+1 pages,
+// with the sentinel byte at the end of the top buffer page.)
 //
 // Our reads into this "bufsz" buffer, and the return of lines by
 // rs_getline() from that buffer, will walk their way up that buffer,
@@ -362,90 +390,310 @@ typedef struct RAWSCAN {
 //     line, which is equivalent to (1) if min1stchunklen has its
 //     default bufsiz value.
 
+// Initialize the RAWSCAN structure at rsp, for a bufsz buffer ending
+// just below buftop, where the caller has already put the sentinel
+// copy of the delimiterbyte, if there's to be one.  Common to rs_open(),
+// rs_open_with_buffer() and rs_open_memory().
+
+static void rawscan_init(RAWSCAN *rsp, int fd, const char *buftop,
+                                        size_t bufsz, char delimiterbyte)
+{
+    memset(rsp, 0, sizeof(*rsp));
+
+    rsp->buf = buftop - bufsz;
+    rsp->buftop = buftop;
+
+    // Initializing p and q to buftop, not to buf, tricks rs_getline()
+    // into calling rawscan_read() before rawmemchr() on first call.
+    rsp->p = rsp->q = rsp->buftop;
+
+    rsp->fd = fd;
+    rsp->bufsz = bufsz;
+    rsp->min1stchunklen = bufsz;
+    rsp->delimiterbyte = delimiterbyte;
+    rsp->next_delim_ptr_peek = rsp->buftop;
+
+    // Above memset() handles following:
+    // rsp->pgsz = 0;
+    // rsp->mapsz = 0;
+    // rsp->readfn = NULL;
+    // rsp->readfn_context = NULL;
+    // rsp->follow_ms = 0;
+    // rsp->end_this_chunk = NULL;
+    // rsp->next_val_p = NULL;
+    // rsp->result = ...;
+    // rsp->q_offset = 0;
+    // rsp->longline_offset = 0;
+    // rsp->ndelims = 0;
+    // rsp->counted_len = 0;
+    // rsp->in_longline = false;
+    // rsp->terminate_current_pause = false;
+    // rsp->paused = false;
+    // rsp->longline_ended = false;
+    // rsp->eof_seen = false;
+    // rsp->err_seen = false;
+    // rsp->pause_on_inval = false;
+    // rsp->bounded_search = false;
+    // rsp->sentinel_in_buffer = false;
+    // rsp->paragraph_mode = false;
+    // rsp->chunk_ended_in_delim = false;
+    // rsp->would_block = false;
+
+    assert (rsp->buf + rsp->bufsz == rsp->buftop);
+}
+
 func_static RAWSCAN *rs_open (
   int fd,              // read input from this already open file descriptor
   size_t bufsz,        // handle lines at least this many bytes in one chunk
   char delimiterbyte)  // newline '\n' or other char marking end of "lines"
+{
+    return rs_open_flags(fd, bufsz, delimiterbyte, 0);
+}
+
+/*
+ * rs_open_flags() is rs_open(), with "flags" to vary how the stream
+ * is set up.  rs_open(fd, bufsz, delimiterbyte) is the same as
+ * rs_open_flags(fd, bufsz, delimiterbyte, 0).  The one flag so far:
+ *
+ * RS_SENTINEL_IN_BUFFER: Don't spend a whole read-only page just to
+ *      hold the sentinel copy of the delimiterbyte.  Rather, put the
+ *      sentinel, and its nul guard rail, in the last two bytes of the
+ *      topmost buffer page, just above the buffer, and leave them
+ *      writable, as rs_open_with_buffer() does.  This saves a page
+ *      per stream, which adds up for applications running hundreds
+ *      of small streams in little memory, and it never calls
+ *      mprotect(2), which some sandboxes forbid.
+ *
+ *      Nothing in rawscan ever writes to buftop[0], save for
+ *      rs_set_delimiterbyte(), so (barring bugs in the code) the
+ *      sentinel is as good here as in its own page, and rawmemchr()
+ *      still always stops at or before it.  What's lost is the
+ *      SIGSEGV that would catch a caller writing past the end of a
+ *      line returned by rs_getline(), clobbering that sentinel.
+ *
+ * Fails, returning NULL with errno set to EINVAL, for unknown flags,
+ * or else as rs_open() fails.
+ */
+
+func_static RAWSCAN *rs_open_flags (
+  int fd,              // read input from this already open file descriptor
+  size_t bufsz,        // handle lines at least this many bytes in one chunk
+  char delimiterbyte,  // newline '\n' or other char marking end of "lines"
+  unsigned int flags)  // RS_SENTINEL_IN_BUFFER, or 0
 {
     size_t pgsz;                // runtime hardware memory page size
-    void *old_sbrk;             // initial data break (top of data seg)
-    void *new_sbrk;             // new data break after our allocations
+    size_t mapsz;               // total size of our mmap'd pages
     void *start_our_pages;      // start of full pages we'll allocate
 
     RAWSCAN *rsp;          // build new RAWSCAN here
 
     size_t buffer_pg_size_in_bytes;  // num bytes to allocate to buffer pages
 
-    char *buf;                  // input buffer starts here
     // size_t bufsz   ...       buffer size in bytes, above input parameter
     char *buftop;               // l.u.b. (top) of buffer
 
+    bool sentinel_in_buffer = (flags & RS_SENTINEL_IN_BUFFER) != 0;
+
+    if ((flags & ~RS_SENTINEL_IN_BUFFER) != 0) {
+        errno = EINVAL;
+        return NULL;
+    }
+
     pgsz = sysconf(_SC_PAGESIZE);
 
 // Round up x to next pgsz boundary
 #   define PageSzRnd(x)  (((((unsigned long)(x))+(pgsz)-1)/(pgsz))*(pgsz))
 
-    buffer_pg_size_in_bytes = PageSzRnd(bufsz);
+    if (sentinel_in_buffer)
+        buffer_pg_size_in_bytes = PageSzRnd(bufsz + 2);
+    else
+        buffer_pg_size_in_bytes = PageSzRnd(bufsz);
 
 #   undef PageSzRnd
 
-    old_sbrk = sbrk(0);
-    assert (((uintptr_t)(old_sbrk) % pgsz) == 0);
-    start_our_pages = old_sbrk;
-
-    new_sbrk =
-        (char *)start_our_pages +   // start of next page at or above old_sbrk
+    mapsz =
         1*pgsz +                    // one page for RAWSCAN *rsp structure
         buffer_pg_size_in_bytes +   // size in bytes of pages for input buffer
-        1*pgsz;                     // one page for read-only sentinel page
+        (sentinel_in_buffer ? 0 :
+        1*pgsz);                    // one page for read-only sentinel page
 
-    if ((brk(new_sbrk)) != 0)
+    start_our_pages = mmap(NULL, mapsz, PROT_READ|PROT_WRITE,
+                                        MAP_PRIVATE|MAP_ANONYMOUS, -1, 0);
+    if (start_our_pages == MAP_FAILED)
         return NULL;
 
     rsp = (RAWSCAN *)start_our_pages;
 
+    if (sentinel_in_buffer) {
+        // The sentinel and nul end our topmost buffer page, which
+        // is the last page mapped, so that rawmemchr() running past
+        // them would run off the end of our mapping.
+
+        buftop = (char *)start_our_pages + mapsz - 2;
+        buftop[0] = delimiterbyte;
+        buftop[1] = '\0';
+
+        rawscan_init(rsp, fd, buftop, bufsz, delimiterbyte);
+        rsp->pgsz = pgsz;
+        rsp->mapsz = mapsz;
+        rsp->sentinel_in_buffer = true;
+
+        assert ((char *)rsp + pgsz <= rsp->buf);
+        assert (sizeof(*rsp) <= pgsz);
+
+        return rsp;
+    }
+
     buftop = (char *)start_our_pages + 1*pgsz + buffer_pg_size_in_bytes;
-    assert(buftop == (char *)new_sbrk - 1*pgsz);
+    assert(buftop == (char *)start_our_pages + mapsz - 1*pgsz);
     buftop[0] = delimiterbyte;
     buftop[1] = '\0';   // guard rail for functions of nul-terminated strings
-    buf = buftop - bufsz;
 
     // Protect our sentinel delimiterbyte from stray writes:
-    if (mprotect (buftop, pgsz, PROT_READ) < 0)
+    if (mprotect (buftop, pgsz, PROT_READ) < 0) {
+        int saved_errno = errno;
+        munmap(start_our_pages, mapsz);
+        errno = saved_errno;
         return NULL;
+    }
 
-    memset(rsp, 0, sizeof(*rsp));
+    rawscan_init(rsp, fd, buftop, bufsz, delimiterbyte);
+    rsp->pgsz = pgsz;
+    rsp->mapsz = mapsz;
 
-    rsp->buf = buf;
-    rsp->buftop = buftop;
+    assert (*rsp->buftop == delimiterbyte);
+    assert (((uintptr_t)(rsp->buftop) % pgsz) == 0);
+    assert ((char *)rsp + pgsz <= rsp->buf);
+    assert (sizeof(*rsp) <= pgsz);
 
-    // Initializing p and q to buftop, not to buf, tricks rs_getline()
-    // into calling rawscan_read() before rawmemchr() on first call.
-    rsp->p = rsp->q = rsp->buftop;
+    return rsp;
+}
 
-    rsp->fd = fd;
-    rsp->pgsz = pgsz;
-    rsp->bufsz = bufsz;
-    rsp->min1stchunklen = bufsz;
-    rsp->delimiterbyte = delimiterbyte;
-    rsp->next_delim_ptr_peek = rsp->buftop;
+/*
+ * rs_open_with_buffer() is rs_open() for callers that must not have
+ * any memory allocated at runtime, such as daemons with a fixed
+ * memory budget, that set aside all the memory they will ever use
+ * at startup.  The caller supplies "len" bytes at "mem", and the
+ * stream lives entirely within that memory, as follows:
+ *
+ *  1) our RAWSCAN *rsp structure, suitably aligned, near the bottom,
+ *  2) the input buffer, of len - RS_BUFFER_OVERHEAD bytes, and
+ *  3) a sentinel copy of the delimiterbyte, plus a nul guard rail,
+ *     in the last two bytes, just above the buffer.
+ *
+ * We can't mprotect() a sentinel byte that's in the middle of some
+ * page belonging to the caller, so here the sentinel is writable.
+ * That costs nothing in speed, and (barring bugs in the code) costs
+ * nothing in safety either, as nothing we do ever writes to buftop[0].
+ * But it does mean that a caller that writes past the end of a line
+ * returned by rs_getline() could overwrite that sentinel, and then
+ * rawmemchr() could run off the end of the buffer, rather than
+ * taking the SIGSEGV that a read-only sentinel would have caught.
+ *
+ * rs_close() of such a stream leaves the memory alone; it remains
+ * the caller's, to reuse for another stream or for anything else.
+ *
+ * Fails, returning NULL with errno set to EINVAL, if "mem" is NULL
+ * or if "len" doesn't leave room for at least a one byte buffer.
+ */
 
-    // Above memset() handles following:
-    // rsp->end_this_chunk = NULL;
-    // rsp->next_val_p = NULL;
-    // rsp->result = ...;
-    // rsp->in_longline = false;
-    // rsp->terminate_current_pause = false;
-    // rsp->longline_ended = false;
-    // rsp->eof_seen = false;
-    // rsp->err_seen = false;
-    // rsp->pause_on_inval = false;
+func_static RAWSCAN *rs_open_with_buffer (
+  int fd,              // read input from this already open file descriptor
+  void *mem,           // caller supplied memory, for stream and buffer
+  size_t len,          // size of mem: RS_BUFFER_SPACE(bufsz) for bufsz buffer
+  char delimiterbyte)  // newline '\n' or other char marking end of "lines"
+{
+    char *start = mem;          // caller's memory starts here
+    RAWSCAN *rsp;               // build new RAWSCAN here
+    size_t bufsz;               // buffer size in bytes
+    char *buftop;               // l.u.b. (top) of buffer
 
-    assert (((uintptr_t)(rsp->buftop) % pgsz) == 0);
-    assert (rsp->buf >= (const char *)buf);
-    assert (rsp->buf + rsp->bufsz == rsp->buftop);
-    assert ((char *)rsp + pgsz <= rsp->buf);
-    assert (sizeof(rsp) <= pgsz);
+    _Static_assert (sizeof(RAWSCAN) + _Alignof(RAWSCAN) - 1 + 2 <=
+                        RS_BUFFER_OVERHEAD, "RS_BUFFER_OVERHEAD too small");
+
+    if (mem == NULL || len <= RS_BUFFER_OVERHEAD) {
+        errno = EINVAL;
+        return NULL;
+    }
+
+    rsp = (RAWSCAN *)(start +
+                (-(uintptr_t)start & (_Alignof(RAWSCAN) - 1)));
+
+    bufsz = len - RS_BUFFER_OVERHEAD;
+    buftop = start + len - 2;
+    buftop[0] = delimiterbyte;
+    buftop[1] = '\0';   // guard rail for functions of nul-terminated strings
+
+    rawscan_init(rsp, fd, buftop, bufsz, delimiterbyte);
+    rsp->pgsz = sysconf(_SC_PAGESIZE);
+
+    // rsp->mapsz = 0 (from memset), so rs_close() won't munmap this.
+
+    assert (*rsp->buftop == delimiterbyte);
+    assert ((const char *)(rsp + 1) <= rsp->buf);
+
+    return rsp;
+}
+
+/*
+ * rs_open_memory() opens a stream that scans the "len" bytes already
+ * at "mem", in place, rather than reading from a file descriptor.
+ * Useful for parsing such things as embedded configuration blobs,
+ * test fixtures, or a table in ROM, without pushing them through a
+ * pipe or copying them into a buffer.
+ *
+ * The memory at "mem" serves as the buffer, already full, with end
+ * of file just past its last byte.  So rs_getline() returns exactly
+ * the RAWSCAN_RESULT sequence that rs_open() would, reading these
+ * same bytes with a buffer big enough to hold them all: a full line
+ * for each delimiterbyte, then perhaps one full line without eol,
+ * and then rt_eof.  Nothing is ever shifted, copied, or read, as the
+ * whole input is already in place.
+ *
+ * We never write to that memory, and it may be read-only.  Since that
+ * means there's no sentinel copy of the delimiterbyte at buftop,
+ * where rawmemchr() would stop, such streams search for the next
+ * delimiterbyte with the bounded memchr() instead.  But note that the
+ * lines returned, pointing into that memory, are then just as
+ * read-only as it is; callers must not write the byte at line.end,
+ * as they may with other streams.
+ *
+ * The memory at "mem" must remain valid and unchanged until rs_close().
+ * The one page holding our RAWSCAN structure is mmap'd, and unmapped
+ * again by rs_close().  Fails, returning NULL with errno set to
+ * EINVAL, if "mem" is NULL but "len" isn't zero.
+ */
+
+func_static RAWSCAN *rs_open_memory (
+  const void *mem,     // scan lines in this memory, which may be read-only
+  size_t len,          // size of mem, in bytes
+  char delimiterbyte)  // newline '\n' or other char marking end of "lines"
+{
+    size_t pgsz;                // runtime hardware memory page size
+    RAWSCAN *rsp;               // build new RAWSCAN here
+
+    if (mem == NULL && len > 0) {
+        errno = EINVAL;
+        return NULL;
+    }
+
+    pgsz = sysconf(_SC_PAGESIZE);
+    assert (sizeof(*rsp) <= pgsz);
+
+    rsp = mmap(NULL, pgsz, PROT_READ|PROT_WRITE,
+                                MAP_PRIVATE|MAP_ANONYMOUS, -1, 0);
+    if (rsp == MAP_FAILED)
+        return NULL;
+
+    rawscan_init(rsp, -1, (const char *)mem + len, len, delimiterbyte);
+    rsp->pgsz = pgsz;
+    rsp->mapsz = pgsz;
+
+    // The whole input is already in the buffer, as if just read.
+    rsp->p = rsp->buf;
+    rsp->q_offset = (off_t)len;
+    rsp->eof_seen = true;
+    rsp->bounded_search = true;
 
     return rsp;
 }
@@ -453,18 +701,159 @@ func_static RAWSCAN *rs_open (
 // Suppress warnings if the pause functions, or some parameters, aren't used.
 #define __unused__ __attribute__((unused))
 
-func_static void rs_close(RAWSCAN *rsp __unused__)
+func_static void rs_close(RAWSCAN *rsp)
 {
-    // We don' t close rsp->fd ... we got it open and so we leave it open.
-    //
-    // We could get fancy and see if the current data break, sbrk(0),
-    // has not moved any further up since we moved it upward in the
-    // above rs_open() call, and if it has not moved up further, move
-    // it back down to the "old_sbrk" we had before the rs_open().
+    // We don't close rsp->fd ... we got it open and so we leave it open.
     //
-    // But I'm not presently sufficiently motivated to do that.
+    // The RAWSCAN structure, the buffer, and the sentinel page are
+    // all in the one region that rs_open() mmap'd, so unmapping that
+    // region frees them all, invalidating any lines still held.
     //
-    // So this routine is currently a no-op.
+    // A stream from rs_open_with_buffer() lives in memory that
+    // belongs to the caller, with mapsz zero, so we leave it be.
+
+    if (rsp == NULL || rsp->mapsz == 0)
+        return;
+    munmap(rsp, rsp->mapsz);
+}
+
+/*
+ * Resize the buffer of a stream from rs_open(), to new_bufsz bytes,
+ * keeping our place in the input.  Useful to start out with a small
+ * buffer, then grow it once lines turn out to be long, or to shrink
+ * it again after.
+ *
+ * The RAWSCAN structure lives in the same mmap'd region as the buffer
+ * and sentinel page, so like realloc(3), rs_resize() maps a new region,
+ * copies over the buffered bytes not yet returned, [p, q), along with
+ * the state of the stream (its input, delimiterbyte, pause settings,
+ * and whether it's in the middle of a long line or has seen the end
+ * of input), then unmaps the old region, and returns the new "rsp".
+ * The old "rsp", and all lines returned from it, are then invalid.
+ *
+ * min1stchunklen carries over, unless it was the whole buffer (the
+ * default), or is larger than the new buffer, in which case it
+ * becomes the whole new buffer.
+ *
+ * Fails, returning NULL with errno set, and leaving the stream just
+ * as it was, if mmap(2) fails, or with EINVAL if new_bufsz is zero,
+ * or is too small to hold the bytes not yet returned, or if the
+ * stream is from rs_open_with_buffer() or rs_open_memory(), whose
+ * memory isn't ours to replace.
+ */
+
+func_static RAWSCAN *rs_resize(RAWSCAN *rsp, size_t new_bufsz)
+{
+    size_t len = (size_t)(rsp->q - rsp->p);     // bytes not yet returned
+    RAWSCAN *nrsp;
+
+    if (rsp->mapsz == 0 || rsp->bounded_search ||
+                                new_bufsz == 0 || len > new_bufsz) {
+        errno = EINVAL;
+        return NULL;
+    }
+
+    nrsp = rs_open_flags(rsp->fd, new_bufsz, rsp->delimiterbyte,
+                        rsp->sentinel_in_buffer ? RS_SENTINEL_IN_BUFFER : 0);
+    if (nrsp == NULL)
+        return NULL;
+
+    memcpy((char *)nrsp->buf, rsp->p, len);
+    nrsp->p = nrsp->buf;
+    nrsp->q = nrsp->buf + len;
+    if (nrsp->q < nrsp->buftop)     // as rawscan_read() leaves it
+        *(char *)(nrsp->q) = nrsp->delimiterbyte;
+
+    nrsp->readfn = rsp->readfn;
+    nrsp->readfn_context = rsp->readfn_context;
+    nrsp->errnum = rsp->errnum;
+    nrsp->follow_ms = rsp->follow_ms;
+    if (rsp->min1stchunklen < rsp->bufsz && rsp->min1stchunklen <= new_bufsz)
+        nrsp->min1stchunklen = rsp->min1stchunklen;
+
+    // The lines that rsp->result pointed to are gone, so leave the new
+    // result's pointers NULL, giving rs_extend_record() and rs_unget()
+    // nothing to act on.
+    nrsp->result.type = rsp->result.type;
+    if (rsp->result.type == rt_err)
+        nrsp->result.errnum = rsp->result.errnum;
+
+    // [p, q) moved, but is at the same place in the input as before.
+    nrsp->q_offset = rsp->q_offset;
+    nrsp->longline_offset = rsp->longline_offset;
+    nrsp->ndelims = rsp->ndelims;
+    nrsp->counted_len = rsp->counted_len;
+
+    nrsp->in_longline = rsp->in_longline;
+    nrsp->terminate_current_pause = rsp->terminate_current_pause;
+    nrsp->paused = rsp->paused;
+    nrsp->longline_ended = rsp->longline_ended;
+    nrsp->eof_seen = rsp->eof_seen;
+    nrsp->err_seen = rsp->err_seen;
+    nrsp->pause_on_inval = rsp->pause_on_inval;
+    nrsp->paragraph_mode = rsp->paragraph_mode;
+    nrsp->chunk_ended_in_delim = rsp->chunk_ended_in_delim;
+
+    rs_close(rsp);
+    return nrsp;
+}
+
+/*
+ * Read this stream's input by calling readfn(context, buf, count),
+ * rather than read(2) on its file descriptor, starting with the next
+ * read.  Anything already read into the buffer is kept, and all
+ * the usual chunking, min1stchunklen, and pause handling applies
+ * just the same to whatever readfn returns, however few bytes it
+ * hands back per call.  Pass a NULL readfn to go back to read(2).
+ *
+ * There's no need to pass rs_open() a real file descriptor, if it's
+ * going to be replaced straight away; -1 will do.  rs_open_memory()
+ * streams are already at end of input, so never call any readfn.
+ */
+
+__unused__ func_static void rs_set_read_fn(RAWSCAN *rsp, rs_read_fn readfn,
+                                                            void *context)
+{
+    rsp->readfn = readfn;
+    rsp->readfn_context = context;
+}
+
+/*
+ * Follow mode, as for "tail -f": rs_set_follow(rsp, interval_ms) with
+ * a non-zero interval has the stream, on reaching the end of its
+ * input, sleep interval_ms milliseconds and try reading again, rather
+ * than seeing end of file, for as long as it takes for more input to
+ * be appended, such as to a live application log.  So rs_getline()
+ * never returns rt_eof, and a partial last line is held back, not
+ * returned as an rt_full_line_without_eol, until its delimiterbyte
+ * arrives.  (Unless the partial line fills the buffer, in which case
+ * it's returned in chunks, as any long line is.)  Read errors still
+ * end the input as usual.
+ *
+ * An interval of 0 turns follow mode back off.  The setting is
+ * checked again after each sleep, so a signal handler may call
+ * rs_set_follow(rsp, 0), and the rs_getline() waiting for input
+ * will then see the end of input, and return what it has.  Turning
+ * follow mode on after rs_getline() has already seen end of input
+ * forgets that, so the next rs_getline() tries reading again.
+ *
+ * Every read that returns no bytes counts as end of input here, so
+ * following a pipe or socket whose writer has gone just sleeps and
+ * retries forever.  Nor is a file that's truncated or replaced, as
+ * when logs are rotated, noticed; rs_seek() back to 0 to start over.
+ * rs_open_memory() streams never read, so never follow.
+ */
+
+__unused__ func_static void rs_set_follow(RAWSCAN *rsp, unsigned int interval_ms)
+{
+    rsp->follow_ms = interval_ms > INT_MAX ? INT_MAX : (int)interval_ms;
+    if (interval_ms > 0 && !rsp->bounded_search)
+        rsp->eof_seen = false;
+}
+
+__unused__ func_static unsigned int rs_get_follow(RAWSCAN *rsp)
+{
+    return (unsigned int)rsp->follow_ms;
 }
 
 __unused__ func_static void rs_enable_pause(RAWSCAN *rsp)
@@ -476,11 +865,13 @@ __unused__ func_static void rs_disable_pause(RAWSCAN *rsp)
 {
     rsp->pause_on_inval = false;
     rsp->terminate_current_pause = false;
+    rsp->paused = false;
 }
 
 __unused__ func_static void rs_resume_from_pause(RAWSCAN *rsp)
 {
     rsp->terminate_current_pause = true;
+    rsp->paused = false;
 }
 
 /*
@@ -510,7 +901,7 @@ __unused__ func_static void rs_resume_from_pause(RAWSCAN *rsp)
  * byte past a returned byte array will be in the read-only
  * sentinel page, just above the main buffer.
  *
- * The heap memory holding the returned character array ("line")
+ * The mmap'd memory holding the returned character array ("line")
  * will remain valid at least until the next rs_getline() or
  * rs_close() call on that same RAWSCAN stream, but not
  * necessarily longer.  The rs_getline() caller may modify
@@ -553,6 +944,44 @@ __unused__ func_static void rs_resume_from_pause(RAWSCAN *rsp)
 
 // Private helper routines used by rs_getline():
 
+#define likely(x)     __builtin_expect((x), 1)
+
+// Count the delimiterbytes in the line or chunk [p, end] about to be
+// returned, less the first counted_len bytes, already counted.  Past
+// those, lines and chunks hold at most one delimiterbyte, at the end,
+// except in paragraph mode, where each paragraph holds several.
+
+static void rawscan_count_delims(RAWSCAN *rsp, const char *end)
+{
+    const char *s = rsp->p + rsp->counted_len;
+
+    rsp->counted_len = 0;
+    if (likely(!rsp->paragraph_mode)) {
+        if (s <= end && *end == rsp->delimiterbyte)
+            rsp->ndelims++;
+        return;
+    }
+    for (; s <= end; s++)
+        if (*s == rsp->delimiterbyte)
+            rsp->ndelims++;
+}
+
+// Count all the delimiterbytes in [from, to), for rs_unget() and
+// rs_get_line_number(), which can't know how many a line holds.
+
+static uint64_t rawscan_count_delims_in(RAWSCAN *rsp, const char *from,
+                                                        const char *to)
+{
+    uint64_t n = 0;
+
+    while (from < to &&
+            (from = memchr(from, rsp->delimiterbyte, (size_t)(to - from)))) {
+        n++;
+        from++;
+    }
+    return n;
+}
+
 static RAWSCAN_RESULT rawscan_full_line(RAWSCAN *rsp)
 {
     // The "normal" case - return another full line all at once.
@@ -570,12 +999,17 @@ static RAWSCAN_RESULT rawscan_full_line(RAWSCAN *rsp)
     assert(rsp->end_this_chunk <= rsp->buftop);
     assert(*rsp->end_this_chunk == rsp->delimiterbyte || rsp->eof_seen);
 
-    if (*rsp->end_this_chunk == rsp->delimiterbyte)
+    // In paragraph mode, only a pair of delimiterbytes ends a full
+    // paragraph; one lone delimiterbyte at the end of input doesn't.
+    if (*rsp->end_this_chunk == rsp->delimiterbyte &&
+            (!rsp->paragraph_mode || (rsp->end_this_chunk > rsp->p &&
+                        rsp->end_this_chunk[-1] == rsp->delimiterbyte)))
         rsp->result.type = rt_full_line;
     else
         rsp->result.type = rt_full_line_without_eol;
     rsp->result.line.begin = rsp->p;
     rsp->result.line.end = rsp->end_this_chunk;
+    rawscan_count_delims(rsp, rsp->end_this_chunk);
 
     rsp->p = rsp->next_val_p;
 
@@ -599,21 +1033,51 @@ static RAWSCAN_RESULT rawscan_err(RAWSCAN *rsp)
     return rsp->result;
 }
 
+// In follow mode, sleep a while, awaiting more input.  A signal may
+// cut the sleep short, perhaps having turned follow mode off.
+
+static void rawscan_follow_wait(RAWSCAN *rsp)
+{
+    int ms = rsp->follow_ms;
+    struct timespec ts;
+
+    if (ms <= 0)
+        return;
+    ts.tv_sec = ms / 1000;
+    ts.tv_nsec = (long)(ms % 1000) * 1000000L;
+    nanosleep(&ts, NULL);
+}
+
 static const char *rawscan_read (RAWSCAN *rsp)
 {
-    int cnt;
+    ssize_t cnt;
 
-    cnt = read (rsp->fd, (void *)(rsp->q), rsp->buftop - rsp->q);
+  retry:
+    if (rsp->readfn != NULL)
+        cnt = rsp->readfn(rsp->readfn_context, (void *)(rsp->q),
+                                                rsp->buftop - rsp->q);
+    else
+        cnt = read (rsp->fd, (void *)(rsp->q), rsp->buftop - rsp->q);
 
     if (cnt > 0) {
         const char *pre_read_q = rsp->q;
         rsp->q += cnt;
+        rsp->q_offset += cnt;
         if (rsp->q < rsp->buftop)   // reduce useless rawmemchr scanning
             *(char *)(rsp->q) = rsp->delimiterbyte;
         return pre_read_q;          // returns to start_next_rawmemchr_here
     } else if (cnt == 0) {
+        if (rsp->follow_ms > 0) {       // follow mode: wait for more
+            rawscan_follow_wait(rsp);
+            goto retry;
+        }
         rsp->eof_seen = true;
         return NULL;
+    } else if (errno == EINTR) {
+        goto retry;
+    } else if (errno == EAGAIN || errno == EWOULDBLOCK) {
+        rsp->would_block = true;        // not an error: try again later
+        return NULL;
     } else {
         rsp->errnum = errno;
         rsp->err_seen = true;
@@ -631,6 +1095,9 @@ static RAWSCAN_RESULT rawscan_start_of_longline(RAWSCAN *rsp)
     rsp->result.type = rt_start_longline;
     rsp->result.line.begin = rsp->p;
     rsp->result.line.end = rsp->end_this_chunk;
+    rsp->chunk_ended_in_delim = *rsp->end_this_chunk == rsp->delimiterbyte;
+    rsp->longline_offset = rsp->q_offset - (rsp->q - rsp->p);
+    rawscan_count_delims(rsp, rsp->end_this_chunk);
 
     rsp->p = rsp->q;
     rsp->in_longline = true;
@@ -653,6 +1120,8 @@ static RAWSCAN_RESULT rawscan_within_longline(RAWSCAN *rsp)
     rsp->result.type = rt_within_longline;
     rsp->result.line.begin = rsp->p;
     rsp->result.line.end = rsp->end_this_chunk;
+    rsp->chunk_ended_in_delim = *rsp->end_this_chunk == rsp->delimiterbyte;
+    rawscan_count_delims(rsp, rsp->end_this_chunk);
     rsp->p = rsp->next_val_p;
 
     rsp->end_this_chunk = NULL;     // force rs_getline to set again
@@ -693,6 +1162,37 @@ static RAWSCAN_RESULT rawscan_terminate_longline(RAWSCAN *rsp)
 static RAWSCAN_RESULT rawscan_paused(RAWSCAN *rsp)
 {
     rsp->result.type = rt_paused;
+    rsp->paused = true;
+
+    return rsp->result;
+}
+
+/*
+ * Non-blocking input: a read(2) (or readfn) on an O_NONBLOCK pipe
+ * or socket with no input ready yet fails with EAGAIN (or EWOULDBLOCK).
+ * That's no error, and isn't latched as one.  Rather, rs_getline()
+ * returns rt_would_block, leaving the stream just as it was, with
+ * any partial line still in the buffer, so that an event loop can
+ * poll(2) or epoll_wait(2) for the fd to become readable, and then
+ * call rs_getline() again, picking up where it left off.  The lines
+ * returned before an rt_would_block remain valid until that next
+ * call, as always.
+ *
+ * rs_extend_record() may return rt_would_block too, when the next
+ * line isn't all in yet.  The record so far then remains the last
+ * line returned, as if the rs_extend_record() call had never been
+ * made, except that it may have been shifted down in the buffer, so
+ * call rs_extend_record() again, once there's more input, to extend it.
+ *
+ * A read interrupted by a signal, failing with EINTR, is simply
+ * retried.
+ */
+
+static RAWSCAN_RESULT rawscan_would_block(RAWSCAN *rsp)
+{
+    rsp->would_block = false;
+    rsp->result.type = rt_would_block;
+    rsp->result.line.begin = rsp->result.line.end = NULL;
 
     return rsp->result;
 }
@@ -757,15 +1257,78 @@ static RAWSCAN_RESULT rawscan_handle_end_of_longline(RAWSCAN *rsp)
     }
 }
 
+// Return ptr to the first delimiterbyte at or above "start", or else to
+// buftop, where the sentinel copy of the delimiterbyte would have
+// stopped rawmemchr().  Streams from rs_open_memory() have no such
+// sentinel, so for them, we do a bounded memchr() up to buftop.
+
+static inline const char *rawscan_find_delim(RAWSCAN *rsp, const char *start)
+{
+    const char *delim;
+
+    if (likely(!rsp->bounded_search))
+        return (const char *)rawmemchr(start, rsp->delimiterbyte);
+
+    delim = memchr(start, rsp->delimiterbyte, (size_t)(rsp->buftop - start));
+    return delim != NULL ? delim : rsp->buftop;
+}
+
+// In paragraph mode, records end at two consecutive delimiterbytes
+// (a blank line, for newline delimiters), rather than at one.  Return
+// ptr to the second delimiterbyte of the first such pair that ends
+// at or above "start", else ptr to where rawscan_find_delim() stopped,
+// at or above q.
+//
+// We look for the first delimiterbyte of each pair just below each
+// delimiterbyte found, not for the second one just above, so that a
+// pair split across a read(), or across two chunks of a long line,
+// is still found, once the second delimiterbyte of it is read.  The
+// byte below p has been returned already, so whether it was a
+// delimiterbyte is recorded in chunk_ended_in_delim.
+//
+// Delimiterbytes at the start of a paragraph, such as more blank lines
+// after the pair ending the prior paragraph, are skipped by moving p
+// up past them, as awk does with RS="", so that any number of blank
+// lines between paragraphs separates them the same as one.
+
+static const char *rawscan_find_paragraph_end(RAWSCAN *rsp, const char *start)
+{
+    const char delim = rsp->delimiterbyte;
+    const char *d;
+
+    if (!rsp->in_longline) {
+        while (rsp->p < rsp->q && *rsp->p == delim) {
+            rsp->p++;
+            rsp->ndelims++;
+        }
+        if (start < rsp->p)
+            start = rsp->p;
+    }
+
+    for (d = start; ; d++) {
+        d = rawscan_find_delim(rsp, d);
+        if (d >= rsp->q)
+            return d;
+        if (d > rsp->p ? d[-1] == delim : rsp->chunk_ended_in_delim)
+            return d;
+    }
+}
+
+static inline const char *rawscan_find_end(RAWSCAN *rsp, const char *start)
+{
+    if (likely(!rsp->paragraph_mode))
+        return rawscan_find_delim(rsp, start);
+    return rawscan_find_paragraph_end(rsp, start);
+}
+
 func_static RAWSCAN_RESULT rs_getline (RAWSCAN *rsp) __attribute__ ((hot));
-static RAWSCAN_RESULT rs_getline_morecode (RAWSCAN *rsp);
+static RAWSCAN_RESULT rs_getline_morecode (RAWSCAN *rsp,
+                                        const char *scan_from);
 
 func_static RAWSCAN_RESULT rs_getline (RAWSCAN *rsp)
 {
     // Optimized for short lines in long buffer.
 
-#   define likely(x)     __builtin_expect((x), 1)
-
     if (likely(rsp->p <= rsp->next_delim_ptr_peek &&
         rsp->next_delim_ptr_peek < rsp->q)) {
 
@@ -775,14 +1338,20 @@ func_static RAWSCAN_RESULT rs_getline (RAWSCAN *rsp)
             rsp->result.line.begin = rsp->p;
             rsp->result.line.end = rsp->next_delim_ptr_peek;
             rsp->p = rsp->next_delim_ptr_peek + 1;
-            rsp->next_delim_ptr_peek = (const char *)rawmemchr(rsp->p,
-                                                    rsp->delimiterbyte);
+            rsp->ndelims++;
+            rsp->next_delim_ptr_peek = rawscan_find_delim(rsp, rsp->p);
             return rsp->result;
     }
-    return rs_getline_morecode(rsp);
+    return rs_getline_morecode(rsp, rsp->p);
 }
 
-static RAWSCAN_RESULT rs_getline_morecode (RAWSCAN *rsp)
+// The rest of rs_getline(), for when "peek" can't simply return the
+// next line.  The search for the next delimiterbyte starts at scan_from,
+// which is rsp->p except when rs_extend_record() has already scanned
+// [p, scan_from), and knows it to hold a record of whole lines.
+
+static RAWSCAN_RESULT rs_getline_morecode (RAWSCAN *rsp,
+                                        const char *scan_from)
 {
     const char *next_delim_ptr;
     const char *start_next_rawmemchr_here;
@@ -801,7 +1370,7 @@ static RAWSCAN_RESULT rs_getline_morecode (RAWSCAN *rsp)
     // Disables "peek".  Only successful fast_loop re-enables.
     rsp->next_delim_ptr_peek = rsp->buftop;
 
-    start_next_rawmemchr_here = rsp->p;
+    start_next_rawmemchr_here = scan_from;
 
   fast_loop:
 
@@ -809,8 +1378,7 @@ static RAWSCAN_RESULT rs_getline_morecode (RAWSCAN *rsp)
     // but we try to avoid calling it more often than we have to,
     // and we try to avoid rescanning any data twice.
 
-    next_delim_ptr = (const char *)rawmemchr(start_next_rawmemchr_here,
-                                            rsp->delimiterbyte);
+    next_delim_ptr = rawscan_find_end(rsp, start_next_rawmemchr_here);
     assert(next_delim_ptr != NULL);
 
     // fastpath the two common cases, where performance counts most:
@@ -822,12 +1390,14 @@ static RAWSCAN_RESULT rs_getline_morecode (RAWSCAN *rsp)
             rsp->result.type = rt_full_line;
             rsp->result.line.begin = rsp->p;
             rsp->result.line.end = next_delim_ptr;
+            rawscan_count_delims(rsp, next_delim_ptr);
             rsp->p = next_delim_ptr + 1;
 
             // If there is another delimiter between rsp->p and rsp->q,
             // then the next line will re-enable above "peek" code.
-            rsp->next_delim_ptr_peek = (const char *)rawmemchr(rsp->p,
-                                                rsp->delimiterbyte);
+            // Not in paragraph mode, where "peek" would find lines.
+            if (likely(!rsp->paragraph_mode))
+                rsp->next_delim_ptr_peek = rawscan_find_delim(rsp, rsp->p);
             return rsp->result;
         } else if (rsp->q < rsp->buftop) {
             // have space above q: read more and try again
@@ -845,11 +1415,13 @@ static RAWSCAN_RESULT rs_getline_morecode (RAWSCAN *rsp)
 
   slow_loop:
 
+    if (rsp->would_block)               // no input ready: come back later
+        return rawscan_would_block(rsp);
+
     assert(rsp->buf <= start_next_rawmemchr_here);
     assert(start_next_rawmemchr_here <= rsp->buftop);
 
-    next_delim_ptr = (const char *)rawmemchr(start_next_rawmemchr_here,
-                                                rsp->delimiterbyte);
+    next_delim_ptr = rawscan_find_end(rsp, start_next_rawmemchr_here);
     assert(next_delim_ptr >= rsp->p);
     len = (size_t)(rsp->q - rsp->p);
 
@@ -864,11 +1436,13 @@ static RAWSCAN_RESULT rs_getline_morecode (RAWSCAN *rsp)
         }
     } else if (rsp->eof_seen || rsp->err_seen) {    // end of input seen
         if (len > 0) {                              // have more chars in buf
-            assert (rsp->q < rsp->buftop);          // have space above q
+            assert (rsp->q < rsp->buftop || rsp->bounded_search);
             // We know we have buffer space above q because we've
             // seen the end of the input, which only happens after
             // a call to rawscan_read() has tried, but failed,
             // to read more bytes into some empty space above q.
+            // (Except for rs_open_memory() streams, which start out
+            // at end of input, with q at buftop.)
             rsp->end_this_chunk = rsp->q - 1;
             rsp->next_val_p = rsp->q;
             if (rsp->in_longline) {
@@ -1024,12 +1598,10 @@ static RAWSCAN_RESULT rs_getline_morecode (RAWSCAN *rsp)
  * rs_set_min1stchunklen() will successfully change that stream's
  * min1stchunklen value and return 0.
  *
- * Potential Future Extension: I can imagine adding another rs_*()
- * routine that will, on a one time basis, have the rawscan code
- * shift a partial line (or even multiple consecutive lines) lower
- * in the buffer, in order to fit more of that line (or of a several
- * line sequence) into the buffer, for more convenient parsing
- * from a single consecutive byte sequence.
+ * See also rs_extend_record(), below, which has the rawscan code
+ * shift several consecutive lines lower in the buffer, in order
+ * to fit that several line sequence into the buffer, for more
+ * convenient parsing from a single consecutive byte sequence.
  */
 
 func_static int rs_set_min1stchunklen(RAWSCAN *rsp, size_t min1stchunklen)
@@ -1045,3 +1617,689 @@ func_static size_t rs_get_min1stchunklen(RAWSCAN *rsp)
 {
     return rsp->min1stchunklen;
 }
+
+/*
+ * Change the delimiterbyte on the fly, so that the next rs_getline()
+ * call, and all after it, end lines at the new delimiterbyte, without
+ * losing any data already read into the buffer.  Useful for input
+ * formats such as a newline delimited header followed by a nul
+ * delimited body, which would otherwise need the stream closed and
+ * reopened, losing whatever had been read ahead.
+ *
+ * The sentinel copy of the delimiterbyte just above the buffer must
+ * change too, or rawmemchr() could run right past it.  For streams
+ * from rs_open(), that sentinel is in a read-only page, which we make
+ * writable just long enough to change that one byte.  Streams from
+ * rs_open_with_buffer(), or from rs_open_flags() with
+ * RS_SENTINEL_IN_BUFFER, have their sentinel in writable memory, and
+ * streams from rs_open_memory() have no sentinel at all.
+ *
+ * Any peeked ahead position of the next old delimiterbyte is dropped,
+ * so the next rs_getline() scans afresh for the new one.  If we're in
+ * the middle of returning a long line, the next new delimiterbyte
+ * found ends that long line.
+ *
+ * Returns 0 on success.  Returns -1, with errno set and nothing
+ * changed, if mprotect(2) fails to make the sentinel page writable.
+ */
+
+func_static int rs_set_delimiterbyte(RAWSCAN *rsp, char delimiterbyte)
+{
+    char *sentinel = (char *)rsp->buftop;
+
+    if (rsp->mapsz != 0 && !rsp->bounded_search && !rsp->sentinel_in_buffer) {
+        if (mprotect (sentinel, rsp->pgsz, PROT_READ|PROT_WRITE) < 0)
+            return -1;
+        *sentinel = delimiterbyte;
+        if (mprotect (sentinel, rsp->pgsz, PROT_READ) < 0) {
+            // Leave things consistent: the sentinel is changed, just
+            // not protected from stray writes anymore.
+            rsp->delimiterbyte = delimiterbyte;
+            rsp->next_delim_ptr_peek = rsp->buftop;
+            return -1;
+        }
+    } else if (!rsp->bounded_search) {
+        *sentinel = delimiterbyte;
+    }
+
+    rsp->delimiterbyte = delimiterbyte;
+    rsp->next_delim_ptr_peek = rsp->buftop;   // disable "peek"
+    return 0;
+}
+
+func_static char rs_get_delimiterbyte(RAWSCAN *rsp)
+{
+    return rsp->delimiterbyte;
+}
+
+/*
+ * Limited support for multiline records, such as stack traces, or
+ * log entries with indented continuation lines.
+ *
+ * After rs_getline() returns a full line (rt_full_line), calling
+ * rs_extend_record() returns that same line, with the next line
+ * appended to it, as a single [begin, end] record.  Call it again
+ * to append another line, and so on.  The start of the record is
+ * held fixed in the buffer for as long as possible, and only shifted
+ * down, along with the rest of the record, when there's no room left
+ * above it to read the next line.  So, as with any rs_getline()
+ * result, the record might not be where the prior call returned it;
+ * use only the "begin" and "end" pointers from the latest call.
+ *
+ * A record is returned in one piece, as rt_full_line (or as
+ * rt_full_line_without_eol, if the appended line was the last line
+ * of input, lacking its delimiterbyte), so long as it fits in the
+ * buffer.  Records that would be longer than min1stchunklen (which
+ * defaults to the whole buffer) are returned just as a long line
+ * would be, with this call returning rt_start_longline and the first
+ * chunk of the record, and with following rs_getline() calls returning
+ * the rest of the appended line in rt_within_longline chunks, then
+ * rt_longline_ended.
+ *
+ * If there's no next line to append, because the input has ended,
+ * the record is returned again, the same length as before, and the
+ * next rs_getline() call will return rt_eof (or rt_err).  If the
+ * record would have to be shifted down, but rs_enable_pause() has
+ * been called, then rt_paused is returned, the record is left just
+ * as it was, and rs_extend_record() can be called again after
+ * rs_resume_from_pause().  If the prior result was not an rt_full_line,
+ * there's nothing to extend, and rs_extend_record() just does an
+ * ordinary rs_getline().
+ *
+ * Often the caller can only tell whether a line belongs to the
+ * current record by looking at it.  For that, see rs_unget() below,
+ * which hands a line that has been appended back to the stream, to
+ * be returned again, to start the next record.
+ */
+
+func_static RAWSCAN_RESULT rs_extend_record(RAWSCAN *rsp)
+{
+    RAWSCAN_RESULT record = rsp->result;
+    const char *scan_from;
+    RAWSCAN_RESULT rt;
+
+    // record.line.begin is NULL before the first rs_getline(), and
+    // after an rs_unget(), when there's no line returned to extend.
+    if (record.type != rt_full_line || record.line.begin == NULL ||
+                                                    rsp->in_longline)
+        return rs_getline(rsp);
+
+    scan_from = record.line.end + 1;
+    assert(rsp->p == scan_from);
+
+    // "Unreturn" the record, then scan on from just past it, as if
+    // the record and the line after it were one line.  The record
+    // is then shifted down, or returned in chunks, as needed, by the
+    // same code that handles any long partial line.
+
+    rsp->p = record.line.begin;
+    rsp->counted_len = (size_t)(scan_from - record.line.begin);
+    rsp->next_delim_ptr_peek = rsp->buftop;     // disable "peek"
+
+    rt = rs_getline_morecode(rsp, scan_from);
+
+    if (rt.type == rt_would_block) {
+        // The record may have been shifted down, but is still at p,
+        // with the start of the line to append after it.  Re-return
+        // it, from there.
+        record.line.begin = rsp->p;
+        record.line.end = rsp->p + rsp->counted_len - 1;
+        rsp->p = record.line.end + 1;
+        rsp->counted_len = 0;
+        rsp->result = record;
+    }
+    if (rt.type == rt_paused) {
+        rsp->counted_len = 0;
+        // Nothing shifted yet: re-return the record, so that calling
+        // rs_extend_record() again, after rs_resume_from_pause(), tries again.
+        rsp->p = scan_from;
+        rsp->result = record;
+    }
+    return rt;
+}
+
+/*
+ * Hand back the tail end of the line (or chunk of a long line) just
+ * returned, starting "offset" bytes into it, to be scanned again
+ * and returned by the next rs_getline() call.  An offset of zero
+ * hands back the entire line.
+ *
+ * This is how a caller reading multiline records with
+ * rs_extend_record() stops a record before a line that turns
+ * out, once looked at, to be the start of the next record: it
+ * calls rs_unget() with the length the record had before that line
+ * was appended, and uses just that much of the record.
+ *
+ * Handing back a chunk of a long line ends that long line, with no
+ * rt_longline_ended; the next rs_getline() will scan the handed back
+ * bytes as the start of a new line.
+ *
+ * Returns 0 on success.  Returns -1, and does nothing, if the last
+ * result was not a line or chunk of a line, or if offset is past
+ * the end of that line or chunk.
+ */
+
+func_static int rs_unget(RAWSCAN *rsp, size_t offset)
+{
+    const char *begin = rsp->result.line.begin;
+    const char *end = rsp->result.line.end;
+
+    switch (rsp->result.type) {
+    case rt_full_line:
+    case rt_full_line_without_eol:
+    case rt_start_longline:
+    case rt_within_longline:
+        break;
+    default:
+        return -1;
+    }
+    if (begin == NULL || offset > (size_t)(end - begin) + 1)
+        return -1;
+
+    rsp->p = begin + offset;
+    rsp->ndelims -= rawscan_count_delims_in(rsp, rsp->p, end + 1);
+    rsp->counted_len = 0;
+    rsp->in_longline = false;
+    rsp->longline_ended = false;
+    rsp->end_this_chunk = NULL;
+    rsp->next_val_p = NULL;
+    rsp->next_delim_ptr_peek = rsp->buftop;     // disable "peek"
+
+    // Don't let a second rs_unget() or rs_extend_record() act on this
+    // line again: it's no longer returned.
+    rsp->result.line.begin = rsp->result.line.end = NULL;
+    return 0;
+}
+
+/*
+ * Paragraph mode: rs_set_paragraph_mode(rsp, true) has rs_getline()
+ * return paragraphs, rather than lines, where a paragraph ends at
+ * two consecutive delimiterbytes, which is to say, at a blank line,
+ * for newline delimiters.  This is the record format of mail spools,
+ * of RFC822 header blocks, and of many config files, and is what
+ * awk calls RS="".
+ *
+ * Each paragraph is returned with the pair of delimiterbytes that
+ * ended it, as an rt_full_line, or at the end of input, without such
+ * a pair, as an rt_full_line_without_eol.  Any further delimiterbytes
+ * after that pair are skipped, so that several blank lines separate
+ * paragraphs just the same as one, and blank lines at the start of
+ * input are skipped too.  Paragraphs that don't fit in the buffer are
+ * returned in chunks, just as long lines are, with rt_start_longline,
+ * rt_within_longline and rt_longline_ended.
+ *
+ * Paragraph mode may be turned on or off at any time, taking effect
+ * with the next rs_getline() call, as with rs_set_delimiterbyte().
+ * Since the "peek" fast path in rs_getline() only knows how to find
+ * single delimiterbytes, it's bypassed in paragraph mode.
+ */
+
+func_static void rs_set_paragraph_mode(RAWSCAN *rsp, bool paragraph_mode)
+{
+    rsp->paragraph_mode = paragraph_mode;
+    rsp->next_delim_ptr_peek = rsp->buftop;     // disable "peek"
+}
+
+func_static bool rs_get_paragraph_mode(RAWSCAN *rsp)
+{
+    return rsp->paragraph_mode;
+}
+
+/*
+ * Accessors for observing the state of a stream, whether paused or
+ * not, such as for making flow control decisions, or for reporting
+ * diagnostics, without the application having to peek inside the
+ * RAWSCAN structure, which is opaque to applications linking with
+ * librawscan.so, and which may change from one release to the next.
+ *
+ * rs_get_buffered_bytes() - bytes read into the buffer, but not yet
+ *      returned by rs_getline().  For rs_open_memory() streams, the
+ *      rest of the caller's memory, not yet returned.
+ * rs_get_bufsz() - size of the buffer, as passed to rs_open() or
+ *      rs_resize(), or the space left for the buffer by
+ *      rs_open_with_buffer(), or the size of the caller's memory
+ *      for rs_open_memory().
+ * rs_eof_seen() - end of input has been seen, so no more will be
+ *      read into the buffer, though lines may remain to be returned.
+ *      Always true for rs_open_memory() streams.
+ * rs_err_seen() - a read error has been seen, so no more will be
+ *      read into the buffer.
+ * rs_in_longline() - rs_getline() has returned the rt_start_longline
+ *      first chunk of a long line, but not yet its rt_longline_ended.
+ * rs_is_paused() - rs_getline() has returned rt_paused, and the
+ *      caller has not yet called rs_resume_from_pause() (or
+ *      rs_disable_pause()).
+ */
+
+func_static size_t rs_get_buffered_bytes(RAWSCAN *rsp)
+{
+    return (size_t)(rsp->q - rsp->p);
+}
+
+func_static size_t rs_get_bufsz(RAWSCAN *rsp)
+{
+    return rsp->bufsz;
+}
+
+func_static bool rs_eof_seen(RAWSCAN *rsp)
+{
+    return rsp->eof_seen;
+}
+
+func_static bool rs_err_seen(RAWSCAN *rsp)
+{
+    return rsp->err_seen;
+}
+
+func_static bool rs_in_longline(RAWSCAN *rsp)
+{
+    return rsp->in_longline;
+}
+
+func_static bool rs_is_paused(RAWSCAN *rsp)
+{
+    return rsp->paused;
+}
+
+/*
+ * Where the last line returned is in the input, for error messages
+ * such as "line 12345, byte 987654", and for building indexes, with
+ * no need for the caller to count lines and bytes itself.
+ *
+ * rs_get_line_offset() - the input byte offset, from 0, of the first
+ *      byte of the line or chunk last returned by rs_getline() or
+ *      rs_extend_record().  After a result without a line, such as
+ *      rt_longline_ended or rt_eof, or after rs_unget(), the offset
+ *      of the next byte to be returned.
+ * rs_get_chunk_offset() - for an rt_within_longline chunk, the offset
+ *      of its first byte from the start of its long line, else 0.
+ * rs_get_line_number() - the line number, from 1, of the line or
+ *      chunk last returned, being one more than the number of
+ *      delimiterbytes before it in the input.  In paragraph mode,
+ *      the line number of the first line of the paragraph.  After a
+ *      result without a line, the line number of the next line.
+ * rs_get_delimiter_count() - the number of delimiterbytes in the
+ *      input before the next byte to be returned, which is to say,
+ *      the number of whole lines returned so far.
+ *
+ * Offsets count from where the input was when the stream was opened,
+ * which is not necessarily the start of a file.  All of these are
+ * kept up to date as lines are returned, at the cost of one increment
+ * per line, except that rs_get_line_number() counts the
+ * delimiterbytes in the line last returned, to know how many came
+ * before it.  Changing the delimiterbyte part way through the input
+ * counts only the delimiterbytes in use at the time.
+ */
+
+// Whether the last result was a line or chunk still in the buffer.
+
+static bool rawscan_has_line(RAWSCAN *rsp)
+{
+    switch (rsp->result.type) {
+    case rt_full_line:
+    case rt_full_line_without_eol:
+    case rt_start_longline:
+    case rt_within_longline:
+        return rsp->result.line.begin != NULL;
+    default:
+        return false;
+    }
+}
+
+func_static off_t rs_get_line_offset(RAWSCAN *rsp)
+{
+    const char *at = rawscan_has_line(rsp) ? rsp->result.line.begin : rsp->p;
+
+    return rsp->q_offset - (rsp->q - at);
+}
+
+func_static off_t rs_get_chunk_offset(RAWSCAN *rsp)
+{
+    if (!rawscan_has_line(rsp) || rsp->result.type != rt_within_longline)
+        return 0;
+    return rs_get_line_offset(rsp) - rsp->longline_offset;
+}
+
+func_static uint64_t rs_get_line_number(RAWSCAN *rsp)
+{
+    if (!rawscan_has_line(rsp))
+        return rsp->ndelims + 1;
+    return rsp->ndelims + 1 -
+            rawscan_count_delims_in(rsp, rsp->result.line.begin, rsp->p);
+}
+
+func_static uint64_t rs_get_delimiter_count(RAWSCAN *rsp)
+{
+    return rsp->ndelims;
+}
+
+/*
+ * Reposition a stream, to resume an interrupted scan, or to jump to
+ * a record at an offset found in an index, without the cost of
+ * closing and reopening the stream, and mapping a new buffer.
+ *
+ * rs_seek(rsp, offset) lseek(2)'s the stream's file descriptor to
+ * "offset" bytes from the start of the file, then discards whatever
+ * is in the buffer, so that the next rs_getline() reads from there,
+ * as if the stream had just been opened at that offset.  Any long
+ * line, end of input or read error seen so far is forgotten, any
+ * pause is over, and the lines already returned are no longer
+ * valid, nor can they be extended or handed back.  Returns the new
+ * offset, or -1 with errno set, leaving the stream just as it was,
+ * if lseek(2) fails, as with ESPIPE on pipes, sockets and terminals,
+ * or EINVAL for a negative offset.  Streams with a readfn, from
+ * rs_set_read_fn(), have no file offset of their own to seek, and
+ * fail with ESPIPE.
+ *
+ * rs_open_memory() streams can be repositioned too, with no lseek(2),
+ * anywhere from 0 to "len", else failing with EINVAL.
+ *
+ * After rs_seek(), the offsets returned by rs_tell() and
+ * rs_get_line_offset() are file offsets (for rs_open_memory(), offsets
+ * into its memory), but the line numbers from rs_get_line_number()
+ * start over at 1, counting from the new offset, as the lines before
+ * it are never read.
+ *
+ * rs_tell(rsp) returns the offset of the next byte that rs_getline()
+ * would return, having read, but not yet returned, everything before
+ * it.  So long as the stream was opened at the start of the file (or
+ * rs_seek() has been called), passing that offset to rs_seek() later,
+ * on this stream or a new one, resumes the scan just where it left off.
+ */
+
+func_static off_t rs_seek(RAWSCAN *rsp, off_t offset)
+{
+    if (rsp->bounded_search) {              // rs_open_memory() stream
+        if (offset < 0 || offset > rsp->q - rsp->buf) {
+            errno = EINVAL;
+            return -1;
+        }
+        rsp->p = rsp->buf + offset;
+    } else {
+        if (rsp->readfn != NULL) {
+            errno = ESPIPE;
+            return -1;
+        }
+        offset = lseek(rsp->fd, offset, SEEK_SET);
+        if (offset == -1)
+            return -1;
+
+        // An empty buffer, with room to read, at the bottom of the
+        // buffer, and a delimiterbyte at q, as rawscan_read() leaves it.
+        rsp->p = rsp->q = rsp->buf;
+        *(char *)(rsp->q) = rsp->delimiterbyte;
+        rsp->q_offset = offset;
+        rsp->eof_seen = false;
+        rsp->err_seen = false;
+        rsp->errnum = 0;
+    }
+
+    memset(&rsp->result, 0, sizeof(rsp->result));
+    rsp->end_this_chunk = NULL;
+    rsp->next_val_p = NULL;
+    rsp->next_delim_ptr_peek = rsp->buftop;     // disable "peek"
+    rsp->longline_offset = 0;
+    rsp->ndelims = 0;
+    rsp->counted_len = 0;
+    rsp->in_longline = false;
+    rsp->terminate_current_pause = false;
+    rsp->paused = false;
+    rsp->longline_ended = false;
+    rsp->chunk_ended_in_delim = false;
+
+    return offset;
+}
+
+func_static off_t rs_tell(RAWSCAN *rsp)
+{
+    return rsp->q_offset - (rsp->q - rsp->p);
+}
+
+/*
+ * Field splitting: nearly every program reading lines with rawscan
+ * goes on to pick those lines apart into fields, so here are some
+ * helpers for doing that without copying, or writing nuls into, the
+ * line, which may well be in read-only memory.
+ *
+ * rs_fields_init(fsp, rt, sep, runs) sets up *fsp to split the line
+ * or chunk in "rt" into fields, less the delimiterbyte that ends an
+ * rt_full_line.  Then each rs_next_field(fsp, &field) call sets
+ * "field" to the next field, returning true, until there are no
+ * more fields, when it returns false.  Results without a line, such
+ * as rt_eof, have no fields.
+ *
+ * With "runs" false, as for tab or comma separated values, each sep
+ * byte ends a field, so "a,,b" has three fields, the second empty,
+ * and "a," has two.  With "runs" true, as for space separated words,
+ * runs of sep bytes are one separator, and sep bytes at the start
+ * and end of the line are ignored, as awk does with its default FS,
+ * so " a  b " has two fields.  Either way, an empty line has none.
+ *
+ * rs_field_count() and rs_nth_field() are shorthand for walking the
+ * fields of a line, to count them, or to get field "n", numbered
+ * from 0.  rs_nth_field() returns false if there aren't that many.
+ *
+ * A long line that comes in chunks comes in chunks of fields too: a
+ * field spanning two chunks is returned as two fields, one from the
+ * end of one chunk, the other from the start of the next.
+ *
+ * rs_field_to_long() and rs_field_to_double() convert a field to a
+ * number, storing it in *valp and returning 0, if the whole field is
+ * one number, as for strtol(3) in base 10, or strtod(3), but with no
+ * leading white space.  Otherwise, they return -1 with errno set to
+ * EINVAL, or to ERANGE if the number is too large.
+ *
+ * rs_field_to_double() only takes plain decimal numbers, with an
+ * optional sign, fraction and exponent, such as "-12.5e3", ".5" or
+ * "7.", and not the hexadecimal floats, "inf" or "nan" that strtod()
+ * also takes, nor a locale's decimal point other than '.'.  strtod()
+ * needs a nul-terminated string, so the field is copied to a buffer
+ * on the stack, rather than a malloc'd one, for the same reasons that
+ * rs_open() doesn't malloc, rewritten as "<digits>e<exponent>", with
+ * no decimal point to depend on the locale.  Only the first
+ * RS_DOUBLE_DIGITS significant digits are copied, well more than the
+ * 767 it can take to round to the nearest double, with a final '1'
+ * standing in for any nonzero digits after them, so that fields of
+ * any length convert just as strtod() would convert them whole.
+ */
+
+func_static void rs_fields_init(RAWSCAN_FIELDS *fsp, RAWSCAN_RESULT rt,
+                                                    char sep, bool runs)
+{
+    bool has_line = rt.type == rt_full_line ||
+                    rt.type == rt_full_line_without_eol ||
+                    rt.type == rt_start_longline ||
+                    rt.type == rt_within_longline;
+
+    fsp->sep = sep;
+    fsp->runs = runs;
+    if (!has_line || rt.line.begin == NULL) {
+        fsp->next = fsp->end = NULL;
+        fsp->done = true;
+        return;
+    }
+    fsp->next = rt.line.begin;
+    fsp->end = rt.line.end + 1;
+    if (rt.type == rt_full_line)
+        fsp->end--;                     // less its delimiterbyte
+    fsp->done = fsp->next == fsp->end;  // empty line: no fields
+}
+
+func_static bool rs_next_field(RAWSCAN_FIELDS *fsp, RAWSCAN_FIELD *fieldp)
+{
+    const char *s, *d;
+
+    if (fsp->runs) {
+        while (fsp->next < fsp->end && *fsp->next == fsp->sep)
+            fsp->next++;
+        if (fsp->next == fsp->end)
+            fsp->done = true;
+    }
+    if (fsp->done)
+        return false;
+
+    s = fsp->next;
+    d = memchr(s, fsp->sep, (size_t)(fsp->end - s));
+    if (d == NULL) {
+        d = fsp->end;
+        fsp->done = true;               // no more seps: last field
+    }
+    fieldp->begin = s;
+    fieldp->len = (size_t)(d - s);
+    fsp->next = d < fsp->end ? d + 1 : d;
+    return true;
+}
+
+func_static size_t rs_field_count(RAWSCAN_RESULT rt, char sep, bool runs)
+{
+    RAWSCAN_FIELDS fields;
+    RAWSCAN_FIELD field;
+    size_t n = 0;
+
+    rs_fields_init(&fields, rt, sep, runs);
+    while (rs_next_field(&fields, &field))
+        n++;
+    return n;
+}
+
+func_static bool rs_nth_field(RAWSCAN_RESULT rt, char sep, bool runs,
+                                        size_t n, RAWSCAN_FIELD *fieldp)
+{
+    RAWSCAN_FIELDS fields;
+
+    rs_fields_init(&fields, rt, sep, runs);
+    do {
+        if (!rs_next_field(&fields, fieldp))
+            return false;
+    } while (n-- > 0);
+    return true;
+}
+
+func_static int rs_field_to_long(RAWSCAN_FIELD field, long *valp)
+{
+    const char *s = field.begin, *end = field.begin + field.len;
+    bool negative = false;
+    unsigned long val = 0;
+    unsigned long limit;
+
+    if (s < end && (*s == '-' || *s == '+'))
+        negative = *s++ == '-';
+    if (s == end) {
+        errno = EINVAL;
+        return -1;
+    }
+    limit = negative ? (unsigned long)LONG_MAX + 1 : (unsigned long)LONG_MAX;
+    for (; s < end; s++) {
+        unsigned digit = (unsigned char)*s - '0';
+
+        if (digit > 9) {
+            errno = EINVAL;
+            return -1;
+        }
+        if (val > (limit - digit) / 10) {
+            errno = ERANGE;
+            return -1;
+        }
+        val = val * 10 + digit;
+    }
+    // Negate in unsigned arithmetic, so LONG_MIN doesn't overflow.
+    *valp = negative ? (long)(0 - val) : (long)val;
+    return 0;
+}
+
+#define RS_DOUBLE_DIGITS 800     // significant digits kept for strtod()
+
+func_static int rs_field_to_double(RAWSCAN_FIELD field, double *valp)
+{
+    // sign, digits, a '1' for those dropped, 'e', exponent sign,
+    // up to 5 exponent digits, and the nul
+    char num[1 + RS_DOUBLE_DIGITS + 1 + 1 + 1 + 5 + 1];
+    char *n = num;
+    const char *s = field.begin, *end = field.begin + field.len;
+    size_t ndigits = 0;         // significant digits copied to num[]
+    bool seen_digit = false, seen_point = false, dropped_nonzero = false;
+    long long scale = 0;        // value is num[] digits times 10^scale
+    long long exp = 0;          // explicit exponent, saturated
+    double val;
+
+    if (s < end && (*s == '-' || *s == '+')) {
+        if (*s++ == '-')
+            *n++ = '-';
+    }
+    for (; s < end; s++) {
+        unsigned digit = (unsigned char)*s - '0';
+
+        if (*s == '.' && !seen_point) {
+            seen_point = true;
+            continue;
+        }
+        if (digit > 9)
+            break;
+        seen_digit = true;
+        if (seen_point)
+            scale--;
+        if (ndigits == 0 && digit == 0)
+            continue;                   // leading zero
+        if (ndigits < RS_DOUBLE_DIGITS) {
+            *n++ = *s;
+            ndigits++;
+        } else {
+            scale++;                    // dropped, past the ones kept
+            dropped_nonzero |= digit != 0;
+        }
+    }
+    if (!seen_digit) {
+        errno = EINVAL;
+        return -1;
+    }
+
+    if (s < end && (*s == 'e' || *s == 'E')) {
+        bool negative = false;
+
+        if (++s < end && (*s == '-' || *s == '+'))
+            negative = *s++ == '-';
+        if (s == end) {
+            errno = EINVAL;
+            return -1;
+        }
+        for (; s < end; s++) {
+            unsigned digit = (unsigned char)*s - '0';
+
+            if (digit > 9)
+                break;
+            if (exp < 1000000000)
+                exp = exp * 10 + digit;
+        }
+        if (negative)
+            exp = -exp;
+    }
+    if (s != end) {
+        errno = EINVAL;
+        return -1;
+    }
+
+    if (ndigits == 0) {
+        *n++ = '0';                     // zero, keeping any '-'
+    } else {
+        if (dropped_nonzero) {
+            *n++ = '1';
+            scale--;
+        }
+        // Past +-99999, with at most 801 digits, every value
+        // overflows, or underflows to 0, so go no further.
+        exp += scale;
+        if (exp > 99999)
+            exp = 99999;
+        if (exp < -99999)
+            exp = -99999;
+        n += sprintf(n, "e%d", (int)exp);
+    }
+    *n = '\0';
+
+    errno = 0;
+    val = strtod(num, NULL);
+    if (errno == ERANGE && (val == HUGE_VAL || val == -HUGE_VAL))
+        return -1;              // too large; underflow to 0 is fine
+    *valp = val;
+    return 0;
+}
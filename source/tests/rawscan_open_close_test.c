@@ -0,0 +1,131 @@
+#include <rawscan.h>
+
+/*
+ * rawscan_open_close_test [-n nstreams] [-b bufsz]
+ *
+ * Open, read from, and close thousands of rawscan streams in one
+ * process, with a second stream and some malloc'd memory interleaved,
+ * and check that the process memory size stays flat.  Before rs_open()
+ * used mmap and rs_close() unmapped it again, each stream leaked its
+ * buffer pages.
+ *
+ * Exits 0 and prints PASS if memory stayed flat, else exits 1.
+ */
+
+#include <stdio.h>
+#include <getopt.h>
+#include <string.h>
+#include <stdlib.h>
+#include <sys/types.h>
+#include <sys/stat.h>
+#include <fcntl.h>
+#include <unistd.h>
+#include <errno.h>
+
+func_static int error_exit(const char *msg) __attribute__((__noreturn__));
+
+func_static int error_exit(const char *msg)
+{
+    if (errno != 0)
+        perror(msg);
+    else
+        fprintf (stderr, "%s\n", msg);;
+
+    exit(1);
+}
+
+// Virtual memory size of this process, in pages, from /proc/self/statm.
+
+func_static long vm_pages(void)
+{
+    FILE *fp;
+    long size;
+
+    if ((fp = fopen("/proc/self/statm", "r")) == NULL)
+        error_exit("rawscan_open_close_test: /proc/self/statm");
+    if (fscanf(fp, "%ld", &size) != 1)
+        error_exit("rawscan_open_close_test: reading /proc/self/statm");
+    fclose(fp);
+    return size;
+}
+
+// Read a few "lines" (single nul bytes, from /dev/zero), enough to
+// touch the buffer, then close the stream.
+
+func_static void scan_some(RAWSCAN *rsp)
+{
+    int i;
+
+    for (i = 0; i < 3; i++) {
+        RAWSCAN_RESULT rt = rs_getline(rsp);
+        if (rt.type != rt_full_line)
+            error_exit("rawscan_open_close_test: unexpected rs_getline result");
+    }
+}
+
+#define default_nstreams 10000
+#define default_buffer_size (64*1024)
+#define warmup_streams 100
+#define slack_pages 64
+
+int main (int argc, char **argv)
+{
+    size_t bufsz = default_buffer_size;
+    long nstreams = default_nstreams;
+    long before, after, i;
+    RAWSCAN *second;
+    int fd, c;
+
+    while ((c = getopt(argc, argv, "b:n:")) != EOF) {
+        switch (c) {
+            case 'b':
+                bufsz = strtoul(optarg, NULL, 0);
+                break;
+            case 'n':
+                nstreams = strtol(optarg, NULL, 0);
+                break;
+            default:
+                fprintf(stderr, "Usage: rawscan_open_close_test "
+                                "[-n nstreams] [-b bufsz]\n");
+                exit(1);
+        }
+    }
+
+    if ((fd = open("/dev/zero", O_RDONLY)) < 0)
+        error_exit("rawscan_open_close_test: /dev/zero");
+
+    // A second stream, open the whole time, that all the others
+    // must not disturb.
+    if ((second = rs_open(fd, bufsz, '\0')) == NULL)
+        error_exit("rawscan_open_close_test: rs_open");
+
+    before = 0;
+    for (i = 0; i < warmup_streams + nstreams; i++) {
+        RAWSCAN *rsp;
+        char *junk;
+
+        if (i == warmup_streams)
+            before = vm_pages();
+
+        if ((rsp = rs_open(fd, bufsz, '\0')) == NULL)
+            error_exit("rawscan_open_close_test: rs_open");
+        junk = malloc(1 + i % 4096);
+        scan_some(rsp);
+        scan_some(second);
+        rs_close(rsp);
+        free(junk);
+    }
+    after = vm_pages();
+
+    rs_close(second);
+    close(fd);
+
+    if (after - before > slack_pages) {
+        printf("FAIL: %ld streams grew memory from %ld to %ld pages\n",
+                                            nstreams, before, after);
+        exit(1);
+    }
+    printf("PASS: %ld streams, memory %ld pages before, %ld after\n",
+                                            nstreams, before, after);
+    exit(0);
+}
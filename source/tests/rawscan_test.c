@@ -1,7 +1,15 @@
 #include <rawscan.h>
 
 /*
- * < input rawscan_test > output
+ * < input rawscan_test [-b bufsz] [-m | -r | -s] > output
+ *
+ * Copies lines starting with "abc" to output, as "sed -n /^abc/p"
+ * would.  The -m option opens the stream in memory allocated once,
+ * up front, using rs_open_with_buffer(), instead of using rs_open().
+ * The -r option first slurps all the input into read-only memory,
+ * then scans it in place, using rs_open_memory().  The -s option
+ * keeps the sentinel delimiterbyte in the last buffer page, using
+ * rs_open_flags() with RS_SENTINEL_IN_BUFFER.
  *
  * Paul Jackson
  * pj@usa.net
@@ -14,6 +22,7 @@
 #include <stdlib.h>
 #include <sys/types.h>
 #include <sys/stat.h>
+#include <sys/mman.h>
 #include <fcntl.h>
 #include <unistd.h>
 #include <inttypes.h>
@@ -43,18 +52,49 @@ func_static void emit(RAWSCAN_RESULT rt)
             error_exit("rawscan write failed");
 }
 
-func_static void rawscan_test(int fd, size_t bufsz)
+// Read all of fd into freshly mmap'd memory, then make that memory
+// read-only, much as if the input were a table in ROM.
+
+func_static const char *slurp_readonly(int fd, size_t *lenp)
+{
+    size_t len = 0, maxlen = 1<<16;
+    char *mem, *tmp;
+    ssize_t cnt;
+
+    if ((mem = malloc(maxlen)) == NULL)
+        error_exit("rawscan_test malloc failure");
+    while ((cnt = read(fd, mem + len, maxlen - len)) > 0) {
+        len += cnt;
+        if (len == maxlen) {
+            maxlen *= 2;
+            if ((mem = realloc(mem, maxlen)) == NULL)
+                error_exit("rawscan_test realloc failure");
+        }
+    }
+    if (cnt < 0)
+        error_exit("rawscan_test read failure");
+
+    tmp = mmap(NULL, len + 1, PROT_READ|PROT_WRITE,
+                                MAP_PRIVATE|MAP_ANONYMOUS, -1, 0);
+    if (tmp == MAP_FAILED)
+        error_exit("rawscan_test mmap failure");
+    memcpy(tmp, mem, len);
+    free(mem);
+    if (mprotect(tmp, len + 1, PROT_READ) < 0)
+        error_exit("rawscan_test mprotect failure");
+
+    *lenp = len;
+    return tmp;
+}
+
+func_static void rawscan_test(RAWSCAN *rsp)
 {
-    RAWSCAN *rsp;
     RAWSCAN_RESULT rt;
     bool good_long_line = false;
     const char *abc_pattern = "abc";
     const int abc_len = strlen(abc_pattern);
     typedef unsigned short ushort;
 
-    if ((rsp = rs_open(fd, bufsz, '\n')) == NULL)
-        error_exit("rawscan rs_open memory allocation failure");
-
     rs_set_min1stchunklen(rsp, abc_len);
 
     for (;;) {
@@ -112,11 +152,16 @@ func_static void rawscan_test(int fd, size_t bufsz)
 int main (int argc, char **argv)
 {
     size_t bufsz = default_buffer_size;
+    bool use_own_memory = false;
+    bool scan_readonly_memory = false;
+    bool sentinel_in_buffer = false;
+    void *mem = NULL;
+    RAWSCAN *rsp;
     extern int optind;
     extern char *optarg;
     int c;
 
-    while ((c = getopt(argc, argv, "b:")) != EOF) {
+    while ((c = getopt(argc, argv, "b:mrs")) != EOF) {
         char *optend;
 
         switch (c) {
@@ -128,12 +173,44 @@ int main (int argc, char **argv)
                     exit(1);
                 }
                 break;
+            case 'm':
+                use_own_memory = true;
+                break;
+            case 'r':
+                scan_readonly_memory = true;
+                break;
+            case 's':
+                sentinel_in_buffer = true;
+                break;
             default:
-                fprintf(stderr, "Usage: rawscan_static_test [-b bufsz]\n");
+                fprintf(stderr, "Usage: rawscan_static_test "
+                                "[-b bufsz] [-m | -r | -s]\n");
                 exit(1);
         }
     }
 
-    rawscan_test(0, bufsz);     // 0: read input file descriptor
+    // 0: read input file descriptor
+
+    if (scan_readonly_memory) {
+        size_t len;
+        const char *input = slurp_readonly(0, &len);
+
+        rsp = rs_open_memory(input, len, '\n');
+    } else if (use_own_memory) {
+        // -m: allocate all memory up front, as an application with a
+        // fixed memory budget would, and hand it to rs_open_with_buffer().
+        if ((mem = malloc(RS_BUFFER_SPACE(bufsz))) == NULL)
+            error_exit("rawscan_test malloc failure");
+        rsp = rs_open_with_buffer(0, mem, RS_BUFFER_SPACE(bufsz), '\n');
+    } else if (sentinel_in_buffer) {
+        rsp = rs_open_flags(0, bufsz, '\n', RS_SENTINEL_IN_BUFFER);
+    } else {
+        rsp = rs_open(0, bufsz, '\n');
+    }
+    if (rsp == NULL)
+        error_exit("rawscan rs_open memory allocation failure");
+
+    rawscan_test(rsp);
+    free(mem);
     exit(0);                    // 0: exit successfully
 }
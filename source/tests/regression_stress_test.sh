@@ -13,6 +13,21 @@
 # Test passes only if the rawscan reader output matches the
 # "sed -n /^abc/p" output, for all tests.
 #
+# The rawscan reader tested defaults to rawscan_static_test.  Name
+# another reader command as the first argument to test it instead,
+# such as rawscan_test, linked with whatever librawscan.so comes first
+# on LD_LIBRARY_PATH.  For example, to test the Rust implementation of
+# librawscan.so built in source/tests/rust_librawscan:
+#
+#   LD_LIBRARY_PATH=/path/to/source/tests/target/release \
+#       regression_stress_test rawscan_test
+#
+# The reader command may include options, such as
+# "rawscan_static_test -m" to test rs_open_with_buffer(),
+# "rawscan_static_test -r" to test rs_open_memory(), or
+# "rawscan_static_test -s" to test rs_open_flags() with the
+# RS_SENTINEL_IN_BUFFER flag.
+#
 # Focus on smaller inputs, with fewer lines (down to zero), shorter
 # lines (down to zero length), and smaller rawscan buffers, as
 # the tricky code, hence the bug risk, is mostly at the edge and
@@ -27,6 +42,7 @@
 setopt multios
 
 PATH=.:$PATH
+reader=${1:-rawscan_static_test}
 random=$RANDOM
 progress="not started yet"
 
@@ -68,7 +84,7 @@ do
                     bufsz=$((2**rawscan_buf_sz_log2))
 
                     ( ( { cat $shm.1 } \
-                        > >(rawscan_static_test -b $bufsz | md5sum 1>&3 ) \
+                        > >(${=reader} -b $bufsz | md5sum 1>&3 ) \
                         > >(sed -n /^abc/p | md5sum 1>&3 )
                     ) 1>/dev/null ) 3>&1 |
                     uniq -c |
@@ -79,7 +95,7 @@ do
                             echo '\n'FAILED: '                       '
                             echo '  ' ./random_line_generator -n $nlines \
                               -m $minlen -M $maxlen -S $finaleol '|' \
-                              ./rawscan_static_test -b $bufsz
+                              ./$reader -b $bufsz
                             exit 1
                         fi
                     done
@@ -0,0 +1,1257 @@
+//! A drop-in replacement for the C librawscan.so, implemented in Rust.
+//!
+//! This library exports exactly the symbols declared in
+//! `source/include/rawscan.h`, with the same struct layouts, so that
+//! existing C programs, such as `rawscan_test.c`, can link against it
+//! in place of the C librawscan.so without source changes.  The work
+//! is done by the native Rust `rawscan` crate; this crate just adapts
+//! its results to the C ABI.
+//!
+//! Differences from the C library, none of which a correct C caller
+//! should notice:
+//!
+//!  - `rs_close()` frees the stream and its buffer, unless they are
+//!    in memory the caller handed to `rs_open_with_buffer()`.
+//!  - `rs_open()` fails, returning NULL with errno set to EINVAL, for
+//!    a zero `bufsz`.
+//!  - `rs_set_min1stchunklen()` fails, returning -1, for a zero
+//!    `min1stchunklen`, as well as for one larger than the buffer.
+//!  - `rs_resize()` swaps in a new buffer and returns the same `rsp`,
+//!    where the C library maps a new region and returns a new `rsp`.
+//!  - There's no read-only sentinel page after the buffer, so the
+//!    byte after a returned line is never in read-only memory.
+//!  - `rs_set_delimiterbyte()` always returns 0, as there's no
+//!    sentinel page for it to mprotect(2), and so nothing to fail.
+//!  - `rs_set_follow(rsp, 0)` may not be called from a signal handler
+//!    to stop an `rs_getline()` that's waiting for more input.
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem;
+use std::num::IntErrorKind;
+use std::os::raw::{c_char, c_double, c_int, c_long, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::str;
+use std::time::Duration;
+
+use rawscan_native::{parse_decimal, RawScan, RawScanResult, SliceScan};
+
+// Read rawscan input from a file descriptor that the caller opened,
+// and that the caller will close, unless the caller has set an input
+// routine of its own to call instead, with rs_set_read_fn().
+
+#[allow(non_camel_case_types)]
+type rs_read_fn =
+    Option<unsafe extern "C" fn(context: *mut c_void, buf: *mut c_void, count: usize) -> isize>;
+
+struct Fd {
+    fd: c_int,
+    readfn: rs_read_fn,
+    context: *mut c_void,
+}
+
+impl Fd {
+    fn new(fd: c_int) -> Fd {
+        Fd { fd, readfn: None, context: ptr::null_mut() }
+    }
+}
+
+impl Read for Fd {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let cnt = match self.readfn {
+            Some(readfn) => unsafe { readfn(self.context, buf.as_mut_ptr().cast(), buf.len()) },
+            None => unsafe { libc::read(self.fd, buf.as_mut_ptr().cast(), buf.len()) },
+        };
+
+        if cnt < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(cnt as usize)
+    }
+}
+
+// A readfn has no file offset of its own for rs_seek() to move.
+
+impl Seek for Fd {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        if self.readfn.is_some() {
+            return Err(io::Error::from_raw_os_error(libc::ESPIPE));
+        }
+        let (offset, whence) = match pos {
+            SeekFrom::Start(offset) => (libc::off_t::try_from(offset).ok(), libc::SEEK_SET),
+            SeekFrom::End(offset) => (libc::off_t::try_from(offset).ok(), libc::SEEK_END),
+            SeekFrom::Current(offset) => (libc::off_t::try_from(offset).ok(), libc::SEEK_CUR),
+        };
+        let Some(offset) = offset else {
+            return Err(io::Error::from_raw_os_error(libc::EOVERFLOW));
+        };
+
+        let offset = unsafe { libc::lseek(self.fd, offset, whence) };
+        if offset < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(offset as u64)
+    }
+}
+
+// The stream's buffer, either allocated by rs_open(), or carved out
+// of the memory passed to rs_open_with_buffer().
+
+enum Buffer {
+    Owned(Box<[u8]>),
+    Caller(&'static mut [u8]),
+}
+
+impl Buffer {
+    // Allocate a zeroed buffer, or fail with errno set to ENOMEM, as the
+    // C library's mmap(2) would, rather than abort the whole process.
+
+    fn alloc(bufsz: usize) -> Option<Buffer> {
+        let mut buf = Vec::new();
+        if buf.try_reserve_exact(bufsz).is_err() {
+            unsafe { *libc::__errno_location() = libc::ENOMEM };
+            return None;
+        }
+        buf.resize(bufsz, 0u8);
+        Some(Buffer::Owned(buf.into_boxed_slice()))
+    }
+}
+
+impl AsRef<[u8]> for Buffer {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Buffer::Owned(buf) => buf,
+            Buffer::Caller(buf) => buf,
+        }
+    }
+}
+
+impl AsMut<[u8]> for Buffer {
+    fn as_mut(&mut self) -> &mut [u8] {
+        match self {
+            Buffer::Owned(buf) => buf,
+            Buffer::Caller(buf) => buf,
+        }
+    }
+}
+
+// Either a stream reading a file descriptor into its buffer, or one
+// from rs_open_memory(), scanning the caller's memory in place.
+
+enum Stream {
+    Read(RawScan<Fd, Buffer>),
+    Memory {
+        ss: SliceScan<'static>,
+        len: usize,             // size of the caller's memory
+        min1stchunklen: usize,  // only kept to report back
+        follow_ms: c_uint,      // likewise
+    },
+}
+
+/// The opaque `RAWSCAN` stream handed back to C callers.
+pub struct RAWSCAN {
+    stream: Stream,
+    in_caller_memory: bool, // from rs_open_with_buffer(), not Box'd
+}
+
+// Must match rawscan.h.  The C rs_open_with_buffer() puts the RAWSCAN
+// structure at the bottom of the caller's memory, the buffer above
+// that, and the sentinel delimiterbyte and nul in the last two bytes.
+// We keep the same layout, though we have no use for the sentinel.
+
+const RS_BUFFER_OVERHEAD: usize = 512;
+
+const _: () = assert!(
+    mem::size_of::<RAWSCAN>() + mem::align_of::<RAWSCAN>() - 1 + 2 <= RS_BUFFER_OVERHEAD
+);
+
+// Enumerate the various kinds of RAWSCAN_RESULT's that rs_getline
+// returns, with the values of the C enum rs_result_type.
+
+#[allow(non_camel_case_types)]
+type rs_result_type = c_uint;
+
+const RT_FULL_LINE: rs_result_type = 0;
+const RT_FULL_LINE_WITHOUT_EOL: rs_result_type = 1;
+const RT_START_LONGLINE: rs_result_type = 2;
+const RT_WITHIN_LONGLINE: rs_result_type = 3;
+const RT_LONGLINE_ENDED: rs_result_type = 4;
+const RT_PAUSED: rs_result_type = 5;
+const RT_EOF: rs_result_type = 6;
+const RT_ERR: rs_result_type = 7;
+const RT_WOULD_BLOCK: rs_result_type = 8;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RawscanLine {
+    begin: *const c_char,       // ptr to first byte in line or chunk
+    end: *const c_char,         // ptr to last byte in line or chunk
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union RawscanResultUnion {
+    line: RawscanLine,
+    errnum: c_int,              // errno of last read if failed
+}
+
+/// rs_getline() returns a copy of this structure, laid out as the C
+/// `RAWSCAN_RESULT` with its anonymous union.
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub struct RAWSCAN_RESULT {
+    type_: rs_result_type,
+    u: RawscanResultUnion,
+}
+
+impl RAWSCAN_RESULT {
+    fn line(type_: rs_result_type, line: &[u8]) -> RAWSCAN_RESULT {
+        let begin = line.as_ptr() as *const c_char;
+        let end = unsafe { begin.add(line.len() - 1) };
+
+        RAWSCAN_RESULT { type_, u: RawscanResultUnion { line: RawscanLine { begin, end } } }
+    }
+
+    fn bare(type_: rs_result_type) -> RAWSCAN_RESULT {
+        let line = RawscanLine { begin: ptr::null(), end: ptr::null() };
+
+        RAWSCAN_RESULT { type_, u: RawscanResultUnion { line } }
+    }
+}
+
+/// # Safety
+///
+/// `fd` must stay open until `rs_close()`.
+#[no_mangle]
+pub unsafe extern "C" fn rs_open(fd: c_int, bufsz: usize, delimiterbyte: c_char) -> *mut RAWSCAN {
+    if bufsz == 0 {
+        *libc::__errno_location() = libc::EINVAL;
+        return ptr::null_mut();
+    }
+    let Some(buf) = Buffer::alloc(bufsz) else {
+        return ptr::null_mut();
+    };
+
+    let stream = Stream::Read(RawScan::with_buffer(Fd::new(fd), buf, delimiterbyte as u8));
+
+    Box::into_raw(Box::new(RAWSCAN { stream, in_caller_memory: false }))
+}
+
+// Must match rawscan.h.  We have no sentinel, in its own page or
+// otherwise, so this flag changes nothing here.
+
+const RS_SENTINEL_IN_BUFFER: c_uint = 0x1;
+
+/// # Safety
+///
+/// `fd` must stay open until `rs_close()`.
+#[no_mangle]
+pub unsafe extern "C" fn rs_open_flags(fd: c_int, bufsz: usize, delimiterbyte: c_char, flags: c_uint) -> *mut RAWSCAN {
+    if flags & !RS_SENTINEL_IN_BUFFER != 0 {
+        *libc::__errno_location() = libc::EINVAL;
+        return ptr::null_mut();
+    }
+    rs_open(fd, bufsz, delimiterbyte)
+}
+
+/// # Safety
+///
+/// `fd` must stay open until `rs_close()`, and `mem` must be valid for
+/// `len` bytes, and not otherwise used, until `rs_close()`.
+#[no_mangle]
+pub unsafe extern "C" fn rs_open_with_buffer(
+    fd: c_int,
+    mem: *mut c_void,
+    len: usize,
+    delimiterbyte: c_char,
+) -> *mut RAWSCAN {
+    if mem.is_null() || len <= RS_BUFFER_OVERHEAD {
+        *libc::__errno_location() = libc::EINVAL;
+        return ptr::null_mut();
+    }
+
+    let start = mem as *mut u8;
+    let rsp = start.add(start.align_offset(mem::align_of::<RAWSCAN>())) as *mut RAWSCAN;
+    let bufsz = len - RS_BUFFER_OVERHEAD;
+    let buftop = start.add(len - 2);
+
+    *buftop = delimiterbyte as u8;
+    *buftop.add(1) = 0;
+
+    let buf = Buffer::Caller(slice::from_raw_parts_mut(buftop.sub(bufsz), bufsz));
+    let stream = Stream::Read(RawScan::with_buffer(Fd::new(fd), buf, delimiterbyte as u8));
+
+    rsp.write(RAWSCAN { stream, in_caller_memory: true });
+    rsp
+}
+
+/// # Safety
+///
+/// `mem` must be valid for `len` bytes, and unchanged, until `rs_close()`.
+#[no_mangle]
+pub unsafe extern "C" fn rs_open_memory(
+    mem: *const c_void,
+    len: usize,
+    delimiterbyte: c_char,
+) -> *mut RAWSCAN {
+    let mem: &'static [u8] = match (mem.is_null(), len) {
+        (_, 0) => &[],
+        (true, _) => {
+            *libc::__errno_location() = libc::EINVAL;
+            return ptr::null_mut();
+        }
+        (false, _) => slice::from_raw_parts(mem.cast(), len),
+    };
+    let ss = SliceScan::new(mem, delimiterbyte as u8);
+    let stream = Stream::Memory { ss, len, min1stchunklen: len, follow_ms: 0 };
+
+    Box::into_raw(Box::new(RAWSCAN { stream, in_caller_memory: false }))
+}
+
+/// # Safety
+///
+/// `rsp` must be NULL or come from one of the `rs_open*()` calls, and
+/// not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn rs_close(rsp: *mut RAWSCAN) {
+    // We don't close the fd ... the caller got it open and so we leave it open.
+    if rsp.is_null() {
+        return;
+    }
+    // A stream in the caller's memory holds nothing needing freeing,
+    // and the memory itself stays the caller's.
+    if (*rsp).in_caller_memory {
+        ptr::drop_in_place(rsp);
+    } else {
+        drop(Box::from_raw(rsp));
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_resize(rsp: *mut RAWSCAN, new_bufsz: usize) -> *mut RAWSCAN {
+    // Our RAWSCAN is Box'd apart from its buffer, so unlike the C
+    // rs_resize(), we only swap in a new buffer, and hand back the
+    // same rsp.  Only buffers from rs_open() are ours to replace.
+    if let Stream::Read(rs) = &mut (*rsp).stream {
+        if !(*rsp).in_caller_memory {
+            let Some(buf) = Buffer::alloc(new_bufsz) else {
+                return ptr::null_mut();             // stream left as it was
+            };
+            if rs.replace_buffer(buf).is_ok() {
+                return rsp;
+            }
+        }
+    }
+    *libc::__errno_location() = libc::EINVAL;
+    ptr::null_mut()
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls, and `readfn`
+/// must be safe to call with `context` until `rs_close()`.
+#[no_mangle]
+pub unsafe extern "C" fn rs_set_read_fn(rsp: *mut RAWSCAN, readfn: rs_read_fn, context: *mut c_void) {
+    // Streams scanning memory in place are already at end of input.
+    if let Stream::Read(rs) = &mut (*rsp).stream {
+        let input = rs.get_mut();
+        input.readfn = readfn;
+        input.context = context;
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_set_follow(rsp: *mut RAWSCAN, interval_ms: c_uint) {
+    let interval_ms = interval_ms.min(c_int::MAX as c_uint);     // as the C sig_atomic_t
+
+    match &mut (*rsp).stream {
+        Stream::Read(rs) if interval_ms == 0 => rs.set_follow(None),
+        Stream::Read(rs) => rs.set_follow(Some(Duration::from_millis(interval_ms.into()))),
+        Stream::Memory { follow_ms, .. } => *follow_ms = interval_ms,
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_get_follow(rsp: *mut RAWSCAN) -> c_uint {
+    match &(*rsp).stream {
+        Stream::Read(rs) => rs.follow().map_or(0, |interval| interval.as_millis() as c_uint),
+        Stream::Memory { follow_ms, .. } => *follow_ms,
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_enable_pause(rsp: *mut RAWSCAN) {
+    // Streams scanning memory in place never overwrite a line, so never pause.
+    if let Stream::Read(rs) = &mut (*rsp).stream {
+        rs.enable_pause();
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_disable_pause(rsp: *mut RAWSCAN) {
+    if let Stream::Read(rs) = &mut (*rsp).stream {
+        rs.disable_pause();
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_resume_from_pause(rsp: *mut RAWSCAN) {
+    if let Stream::Read(rs) = &mut (*rsp).stream {
+        rs.resume_from_pause();
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.  The returned line is only valid
+/// until the next call on `rsp`, as for the C library.
+#[no_mangle]
+pub unsafe extern "C" fn rs_getline(rsp: *mut RAWSCAN) -> RAWSCAN_RESULT {
+    let rt = match &mut (*rsp).stream {
+        Stream::Read(rs) => rs.getline(),
+        Stream::Memory { ss, .. } => ss.getline(),
+    };
+    c_result(rt)
+}
+
+// RawScanResult to RAWSCAN_RESULT, for rs_getline() and friends.
+
+fn c_result(rt: RawScanResult<'_>) -> RAWSCAN_RESULT {
+    match rt {
+        RawScanResult::FullLine(line) => RAWSCAN_RESULT::line(RT_FULL_LINE, line),
+        RawScanResult::FullLineWithoutEol(line) => {
+            RAWSCAN_RESULT::line(RT_FULL_LINE_WITHOUT_EOL, line)
+        }
+        RawScanResult::StartLongline(line) => RAWSCAN_RESULT::line(RT_START_LONGLINE, line),
+        RawScanResult::WithinLongline(line) => RAWSCAN_RESULT::line(RT_WITHIN_LONGLINE, line),
+        RawScanResult::LonglineEnded => RAWSCAN_RESULT::bare(RT_LONGLINE_ENDED),
+        RawScanResult::Paused => RAWSCAN_RESULT::bare(RT_PAUSED),
+        RawScanResult::Eof => RAWSCAN_RESULT::bare(RT_EOF),
+        RawScanResult::WouldBlock => RAWSCAN_RESULT::bare(RT_WOULD_BLOCK),
+        RawScanResult::Err(e) => RAWSCAN_RESULT {
+            type_: RT_ERR,
+            u: RawscanResultUnion { errnum: e.raw_os_error().unwrap_or(libc::EIO) },
+        },
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_extend_record(rsp: *mut RAWSCAN) -> RAWSCAN_RESULT {
+    let rt = match &mut (*rsp).stream {
+        Stream::Read(rs) => rs.extend_record(),
+        Stream::Memory { ss, .. } => ss.extend_record(),
+    };
+    c_result(rt)
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_unget(rsp: *mut RAWSCAN, offset: usize) -> c_int {
+    let res = match &mut (*rsp).stream {
+        Stream::Read(rs) => rs.unget(offset),
+        Stream::Memory { ss, .. } => ss.unget(offset),
+    };
+    match res {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_set_paragraph_mode(rsp: *mut RAWSCAN, paragraph_mode: bool) {
+    match &mut (*rsp).stream {
+        Stream::Read(rs) => rs.set_paragraph_mode(paragraph_mode),
+        Stream::Memory { ss, .. } => ss.set_paragraph_mode(paragraph_mode),
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_get_paragraph_mode(rsp: *mut RAWSCAN) -> bool {
+    match &(*rsp).stream {
+        Stream::Read(rs) => rs.paragraph_mode(),
+        Stream::Memory { ss, .. } => ss.paragraph_mode(),
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_set_delimiterbyte(rsp: *mut RAWSCAN, delimiterbyte: c_char) -> c_int {
+    match &mut (*rsp).stream {
+        Stream::Read(rs) => rs.set_delimiterbyte(delimiterbyte as u8),
+        Stream::Memory { ss, .. } => ss.set_delimiterbyte(delimiterbyte as u8),
+    }
+    0                                   // no sentinel page to mprotect()
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_get_delimiterbyte(rsp: *mut RAWSCAN) -> c_char {
+    let delimiterbyte = match &(*rsp).stream {
+        Stream::Read(rs) => rs.delimiterbyte(),
+        Stream::Memory { ss, .. } => ss.delimiterbyte(),
+    };
+    delimiterbyte as c_char
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_set_min1stchunklen(rsp: *mut RAWSCAN, min1stchunklen: usize) -> c_int {
+    match &mut (*rsp).stream {
+        Stream::Read(rs) => match rs.set_min1stchunklen(min1stchunklen) {
+            Ok(()) => 0,
+            Err(_) => -1,
+        },
+        Stream::Memory { len, min1stchunklen: m, .. } => {
+            if min1stchunklen == 0 || min1stchunklen > *len {
+                return -1;
+            }
+            *m = min1stchunklen;
+            0
+        }
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_get_min1stchunklen(rsp: *mut RAWSCAN) -> usize {
+    match &(*rsp).stream {
+        Stream::Read(rs) => rs.min1stchunklen(),
+        Stream::Memory { min1stchunklen, .. } => *min1stchunklen,
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_get_buffered_bytes(rsp: *mut RAWSCAN) -> usize {
+    match &(*rsp).stream {
+        Stream::Read(rs) => rs.buffered_bytes(),
+        Stream::Memory { ss, .. } => ss.remaining().len(),
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_get_bufsz(rsp: *mut RAWSCAN) -> usize {
+    match &(*rsp).stream {
+        Stream::Read(rs) => rs.bufsz(),
+        Stream::Memory { len, .. } => *len,
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_eof_seen(rsp: *mut RAWSCAN) -> bool {
+    match &(*rsp).stream {
+        Stream::Read(rs) => rs.eof_seen(),
+        Stream::Memory { .. } => true,
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_err_seen(rsp: *mut RAWSCAN) -> bool {
+    match &(*rsp).stream {
+        Stream::Read(rs) => rs.err_seen(),
+        Stream::Memory { .. } => false,
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_in_longline(rsp: *mut RAWSCAN) -> bool {
+    match &(*rsp).stream {
+        Stream::Read(rs) => rs.in_longline(),
+        Stream::Memory { .. } => false,
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_is_paused(rsp: *mut RAWSCAN) -> bool {
+    match &(*rsp).stream {
+        Stream::Read(rs) => rs.is_paused(),
+        Stream::Memory { .. } => false,
+    }
+}
+
+// An input offset as the C off_t, or -1 with errno set to EOVERFLOW
+// if it's too large for one, as it may be where off_t is 32 bits.
+
+fn to_off_t(offset: u64) -> libc::off_t {
+    libc::off_t::try_from(offset).unwrap_or_else(|_| {
+        unsafe { *libc::__errno_location() = libc::EOVERFLOW };
+        -1
+    })
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_get_line_offset(rsp: *mut RAWSCAN) -> libc::off_t {
+    match &(*rsp).stream {
+        Stream::Read(rs) => to_off_t(rs.line_offset()),
+        Stream::Memory { ss, .. } => to_off_t(ss.line_offset()),
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_get_chunk_offset(rsp: *mut RAWSCAN) -> libc::off_t {
+    match &(*rsp).stream {
+        Stream::Read(rs) => to_off_t(rs.chunk_offset()),
+        Stream::Memory { .. } => 0,
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_get_line_number(rsp: *mut RAWSCAN) -> u64 {
+    match &(*rsp).stream {
+        Stream::Read(rs) => rs.line_number(),
+        Stream::Memory { ss, .. } => ss.line_number(),
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_get_delimiter_count(rsp: *mut RAWSCAN) -> u64 {
+    match &(*rsp).stream {
+        Stream::Read(rs) => rs.delimiter_count(),
+        Stream::Memory { ss, .. } => ss.delimiter_count(),
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_seek(rsp: *mut RAWSCAN, offset: libc::off_t) -> libc::off_t {
+    let sought = match &mut (*rsp).stream {
+        _ if offset < 0 => Err(io::Error::from_raw_os_error(libc::EINVAL)),
+        Stream::Read(rs) => rs.seek(offset as u64),
+        Stream::Memory { ss, .. } => ss.seek(offset as u64),
+    };
+    match sought {
+        Ok(offset) => to_off_t(offset),
+        Err(e) => {
+            *libc::__errno_location() = e.raw_os_error().unwrap_or(libc::EINVAL);
+            -1
+        }
+    }
+}
+
+/// # Safety
+///
+/// `rsp` must come from one of the `rs_open*()` calls.
+#[no_mangle]
+pub unsafe extern "C" fn rs_tell(rsp: *mut RAWSCAN) -> libc::off_t {
+    match &(*rsp).stream {
+        Stream::Read(rs) => to_off_t(rs.tell()),
+        Stream::Memory { ss, .. } => to_off_t(ss.tell()),
+    }
+}
+
+/// One field of a line, from rs_next_field(), laid out as the C
+/// `RAWSCAN_FIELD`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub struct RAWSCAN_FIELD {
+    begin: *const c_char,       // ptr to first byte in field
+    len: usize,                 // number of bytes in field, maybe 0
+}
+
+/// The state of splitting a line into fields, laid out as the C
+/// `RAWSCAN_FIELDS`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub struct RAWSCAN_FIELDS {
+    next: *const c_char,        // start of next field
+    end: *const c_char,         // just past the last byte to split
+    sep: c_char,                // field separator byte
+    runs: bool,                 // runs of sep are one separator
+    done: bool,                 // no more fields
+}
+
+// The field helpers work on raw pointers, just as the C ones do,
+// rather than through rawscan_native::Fields, as the C caller keeps
+// the RAWSCAN_FIELDS state, and may look inside it.
+
+/// # Safety
+///
+/// `fsp` must be valid for writes, and `rt` must come from this
+/// library, with its line, if any, still in place.
+#[no_mangle]
+pub unsafe extern "C" fn rs_fields_init(
+    fsp: *mut RAWSCAN_FIELDS,
+    rt: RAWSCAN_RESULT,
+    sep: c_char,
+    runs: bool,
+) {
+    let has_line = matches!(
+        rt.type_,
+        RT_FULL_LINE | RT_FULL_LINE_WITHOUT_EOL | RT_START_LONGLINE | RT_WITHIN_LONGLINE
+    );
+
+    let fields = &mut *fsp;
+    fields.sep = sep;
+    fields.runs = runs;
+    if !has_line || rt.u.line.begin.is_null() {
+        fields.next = ptr::null();
+        fields.end = ptr::null();
+        fields.done = true;
+        return;
+    }
+    fields.next = rt.u.line.begin;
+    fields.end = rt.u.line.end.add(1);
+    if rt.type_ == RT_FULL_LINE {
+        fields.end = fields.end.sub(1);         // less its delimiterbyte
+    }
+    fields.done = fields.next == fields.end;    // empty line: no fields
+}
+
+/// # Safety
+///
+/// `fsp` must have been set up by `rs_fields_init()`, and `fieldp`
+/// must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rs_next_field(
+    fsp: *mut RAWSCAN_FIELDS,
+    fieldp: *mut RAWSCAN_FIELD,
+) -> bool {
+    let fields = &mut *fsp;
+
+    if fields.runs {
+        while fields.next < fields.end && *fields.next == fields.sep {
+            fields.next = fields.next.add(1);
+        }
+        if fields.next == fields.end {
+            fields.done = true;
+        }
+    }
+    if fields.done {
+        return false;
+    }
+
+    let rest_len = fields.end.offset_from(fields.next) as usize;
+    let rest = slice::from_raw_parts(fields.next as *const u8, rest_len);
+    let len = match rest.iter().position(|&b| b == fields.sep as u8) {
+        Some(d) => d,
+        None => {
+            fields.done = true;                 // no more seps: last field
+            rest.len()
+        }
+    };
+    *fieldp = RAWSCAN_FIELD { begin: fields.next, len };
+    fields.next = fields.next.add(len);
+    if fields.next < fields.end {
+        fields.next = fields.next.add(1);
+    }
+    true
+}
+
+/// # Safety
+///
+/// `rt` must come from this library, with its line, if any, still in
+/// place.
+#[no_mangle]
+pub unsafe extern "C" fn rs_field_count(rt: RAWSCAN_RESULT, sep: c_char, runs: bool) -> usize {
+    let mut fields = mem::zeroed();
+    let mut field = mem::zeroed();
+    let mut n = 0;
+
+    rs_fields_init(&mut fields, rt, sep, runs);
+    while rs_next_field(&mut fields, &mut field) {
+        n += 1;
+    }
+    n
+}
+
+/// # Safety
+///
+/// `rt` must come from this library, with its line, if any, still in
+/// place, and `fieldp` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rs_nth_field(
+    rt: RAWSCAN_RESULT,
+    sep: c_char,
+    runs: bool,
+    n: usize,
+    fieldp: *mut RAWSCAN_FIELD,
+) -> bool {
+    let mut fields = mem::zeroed();
+
+    rs_fields_init(&mut fields, rt, sep, runs);
+    for _ in 0..=n {
+        if !rs_next_field(&mut fields, fieldp) {
+            return false;
+        }
+    }
+    true
+}
+
+// The bytes of a field, which, if empty, may have no begin pointer.
+
+unsafe fn field_bytes<'a>(field: RAWSCAN_FIELD) -> &'a [u8] {
+    if field.len == 0 {
+        return &[];
+    }
+    slice::from_raw_parts(field.begin as *const u8, field.len)
+}
+
+// The text of a field, if it's UTF-8, as numbers always are.
+
+unsafe fn field_str<'a>(field: RAWSCAN_FIELD) -> Option<&'a str> {
+    str::from_utf8(field_bytes(field)).ok()
+}
+
+/// # Safety
+///
+/// `field` must come from `rs_next_field()` or `rs_nth_field()`, with
+/// its line still in place, and `valp` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rs_field_to_long(field: RAWSCAN_FIELD, valp: *mut c_long) -> c_int {
+    let errnum = match field_str(field).map(str::parse::<c_long>) {
+        Some(Ok(val)) => {
+            *valp = val;
+            return 0;
+        }
+        Some(Err(e))
+            if matches!(e.kind(), IntErrorKind::PosOverflow | IntErrorKind::NegOverflow) =>
+        {
+            libc::ERANGE
+        }
+        _ => libc::EINVAL,
+    };
+    *libc::__errno_location() = errnum;
+    -1
+}
+
+/// # Safety
+///
+/// `field` must come from `rs_next_field()` or `rs_nth_field()`, with
+/// its line still in place, and `valp` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rs_field_to_double(field: RAWSCAN_FIELD, valp: *mut c_double) -> c_int {
+    let errnum = match parse_decimal(field_bytes(field)) {
+        // A decimal number only parses as infinite if it's too large.
+        Some(val) if val.is_infinite() => libc::ERANGE,
+        Some(val) => {
+            *valp = val;
+            return 0;
+        }
+        None => libc::EINVAL,
+    };
+    *libc::__errno_location() = errnum;
+    -1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+    use std::slice;
+
+    #[test]
+    fn result_layout_matches_rawscan_h() {
+        assert_eq!(mem::size_of::<RAWSCAN_RESULT>(), 3 * mem::size_of::<usize>());
+        assert_eq!(mem::align_of::<RAWSCAN_RESULT>(), mem::align_of::<usize>());
+    }
+
+    #[test]
+    fn c_abi_round_trip() {
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write_all(b"abc\n0123456789\nxyz").unwrap();
+        drop(writer);
+
+        let mut got = Vec::new();
+        unsafe {
+            assert!(rs_open(reader.as_raw_fd(), 0, b'\n' as c_char).is_null());
+            assert!(rs_open(reader.as_raw_fd(), usize::MAX, b'\n' as c_char).is_null());
+            assert_eq!(*libc::__errno_location(), libc::ENOMEM);
+            assert!(rs_open_flags(reader.as_raw_fd(), 8, b'\n' as c_char, 0x2).is_null());
+
+            let rsp = rs_open_flags(reader.as_raw_fd(), 8, b'\n' as c_char, RS_SENTINEL_IN_BUFFER);
+            assert_eq!(rs_get_min1stchunklen(rsp), 8);
+            assert_eq!(rs_set_min1stchunklen(rsp, 9), -1);
+
+            loop {
+                let rt = rs_getline(rsp);
+                match rt.type_ {
+                    RT_FULL_LINE | RT_FULL_LINE_WITHOUT_EOL | RT_START_LONGLINE | RT_WITHIN_LONGLINE => {
+                        let len = rt.u.line.end.offset_from(rt.u.line.begin) as usize + 1;
+                        let line = slice::from_raw_parts(rt.u.line.begin as *const u8, len);
+                        got.push((rt.type_, line.to_vec()));
+                    }
+                    RT_EOF => break,
+                    other => got.push((other, Vec::new())),
+                }
+            }
+            rs_close(rsp);
+        }
+
+        assert_eq!(
+            got,
+            vec![
+                (RT_FULL_LINE, b"abc\n".to_vec()),
+                (RT_START_LONGLINE, b"01234567".to_vec()),
+                (RT_WITHIN_LONGLINE, b"89\n".to_vec()),
+                (RT_LONGLINE_ENDED, Vec::new()),
+                (RT_FULL_LINE_WITHOUT_EOL, b"xyz".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn open_in_caller_memory() {
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write_all(b"abc\nxyz").unwrap();
+        drop(writer);
+
+        // Odd start, to check that the RAWSCAN structure gets aligned.
+        let mut mem = vec![0u8; 1 + RS_BUFFER_OVERHEAD + 8];
+        let (mem_ptr, len) = (mem[1..].as_mut_ptr().cast(), mem.len() - 1);
+        unsafe {
+            assert!(rs_open_with_buffer(reader.as_raw_fd(), mem_ptr, RS_BUFFER_OVERHEAD, 0).is_null());
+
+            let rsp = rs_open_with_buffer(reader.as_raw_fd(), mem_ptr, len, b'\n' as c_char);
+            assert_eq!(rsp as usize % mem::align_of::<RAWSCAN>(), 0);
+            assert_eq!(rs_get_min1stchunklen(rsp), 8);
+            assert_eq!(rs_getline(rsp).type_, RT_FULL_LINE);
+            assert_eq!((rs_get_buffered_bytes(rsp), rs_get_bufsz(rsp)), (3, 8));
+            assert_eq!(rs_getline(rsp).type_, RT_FULL_LINE_WITHOUT_EOL);
+            assert!(rs_eof_seen(rsp));
+            assert_eq!(rs_getline(rsp).type_, RT_EOF);
+            rs_close(rsp);
+        }
+        // Buffer at the top, just below the sentinel delimiterbyte and nul.
+        assert_eq!(&mem[mem.len() - 10..], b"abc\nxyz\0\n\0");
+    }
+
+    #[test]
+    fn scan_memory_in_place() {
+        let input = b"abc\n\nxyz";
+        let mut got = Vec::new();
+        unsafe {
+            assert!(rs_open_memory(ptr::null(), 1, b'\n' as c_char).is_null());
+
+            let rsp = rs_open_memory(input.as_ptr().cast(), input.len(), b'\n' as c_char);
+            assert_eq!(rs_get_min1stchunklen(rsp), input.len());
+            assert_eq!((rs_get_buffered_bytes(rsp), rs_get_bufsz(rsp)), (8, 8));
+            assert!(rs_eof_seen(rsp) && !rs_err_seen(rsp) && !rs_in_longline(rsp) && !rs_is_paused(rsp));
+            assert_eq!(rs_set_delimiterbyte(rsp, b'z' as c_char), 0);
+            assert_eq!(rs_get_delimiterbyte(rsp), b'z' as c_char);
+            assert_eq!(rs_set_delimiterbyte(rsp, b'\n' as c_char), 0);
+            loop {
+                let rt = rs_getline(rsp);
+                if rt.type_ == RT_EOF {
+                    break;
+                }
+                got.push((rt.type_, rt.u.line.begin.offset_from(input.as_ptr().cast()), rt.u.line.end));
+                assert_eq!(rs_get_line_offset(rsp) as isize, got.last().unwrap().1);
+            }
+            assert_eq!((rs_get_line_offset(rsp), rs_get_chunk_offset(rsp)), (8, 0));
+            assert_eq!((rs_get_line_number(rsp), rs_get_delimiter_count(rsp)), (3, 2));
+            assert_eq!((rs_seek(rsp, 9), *libc::__errno_location()), (-1, libc::EINVAL));
+            assert_eq!((rs_seek(rsp, 5), rs_tell(rsp)), (5, 5));
+            assert_eq!(rs_getline(rsp).u.line.begin, input[5..].as_ptr().cast());
+            rs_close(rsp);
+
+            let empty = rs_open_memory(ptr::null(), 0, b'\n' as c_char);
+            assert_eq!(rs_getline(empty).type_, RT_EOF);
+            rs_close(empty);
+        }
+        let at = |i: usize| input[i..].as_ptr() as *const c_char;
+        assert_eq!(
+            got,
+            vec![(RT_FULL_LINE, 0, at(3)), (RT_FULL_LINE, 4, at(4)), (RT_FULL_LINE_WITHOUT_EOL, 5, at(7))]
+        );
+    }
+
+    unsafe extern "C" fn countdown(context: *mut c_void, buf: *mut c_void, count: usize) -> isize {
+        let left = &mut *context.cast::<u8>();
+        if *left == 0 || count < 2 {
+            return 0;
+        }
+        *buf.cast::<[u8; 2]>() = [b'0' + *left, b'\n'];
+        *left -= 1;
+        2
+    }
+
+    #[test]
+    fn read_through_callback() {
+        let mut left = 3u8;
+        let mut got = Vec::new();
+        unsafe {
+            let rsp = rs_open(-1, 8, b'\n' as c_char);
+            rs_set_read_fn(rsp, Some(countdown), (&mut left as *mut u8).cast());
+            loop {
+                let rt = rs_getline(rsp);
+                if rt.type_ != RT_FULL_LINE {
+                    assert_eq!(rt.type_, RT_EOF);
+                    break;
+                }
+                got.push(*rt.u.line.begin as u8);
+            }
+            rs_close(rsp);
+        }
+        assert_eq!(got, b"321");
+    }
+
+    #[test]
+    fn follow_after_eof() {
+        let mut left = 1u8;
+        unsafe {
+            let rsp = rs_open(-1, 8, b'\n' as c_char);
+            rs_set_read_fn(rsp, Some(countdown), (&mut left as *mut u8).cast());
+            assert_eq!(rs_getline(rsp).type_, RT_FULL_LINE);
+            assert_eq!(rs_getline(rsp).type_, RT_EOF);
+
+            // More input "appended": following forgets the end of input.
+            *(&mut left as *mut u8) = 2;
+            rs_set_follow(rsp, 1);
+            assert_eq!(rs_get_follow(rsp), 1);
+            assert!(!rs_eof_seen(rsp));
+            assert_eq!(*rs_getline(rsp).u.line.begin as u8, b'2');
+            assert_eq!(*rs_getline(rsp).u.line.begin as u8, b'1');
+            rs_set_follow(rsp, 0);
+            assert_eq!(rs_getline(rsp).type_, RT_EOF);
+            rs_close(rsp);
+        }
+    }
+
+    // Read "ab\ncd\n" in two, with reads failing with EAGAIN, as for a
+    // non-blocking fd with nothing ready yet, and then with EINTR, as
+    // for one interrupted by a signal, before each.
+    unsafe extern "C" fn balking(context: *mut c_void, buf: *mut c_void, _count: usize) -> isize {
+        let reads = &mut *context.cast::<u8>();
+        *reads += 1;
+        match *reads {
+            1 | 4 => *libc::__errno_location() = libc::EAGAIN,
+            2 | 5 => *libc::__errno_location() = libc::EINTR,
+            3 | 6 => {
+                ptr::copy_nonoverlapping(b"ab\ncd\n".as_ptr().add(*reads as usize - 3), buf.cast(), 3);
+                return 3;
+            }
+            _ => return 0,
+        }
+        -1
+    }
+
+    #[test]
+    fn would_block_and_call_again() {
+        let mut reads = 0u8;
+        unsafe {
+            let rsp = rs_open(-1, 8, b'\n' as c_char);
+            rs_set_read_fn(rsp, Some(balking), (&mut reads as *mut u8).cast());
+            assert_eq!(rs_getline(rsp).type_, RT_WOULD_BLOCK);
+            assert_eq!(*rs_getline(rsp).u.line.begin as u8, b'a');
+            assert_eq!(rs_getline(rsp).type_, RT_WOULD_BLOCK);
+            assert_eq!(*rs_getline(rsp).u.line.begin as u8, b'c');
+            assert!(!rs_err_seen(rsp));
+            assert_eq!(rs_getline(rsp).type_, RT_EOF);
+            rs_close(rsp);
+        }
+    }
+
+    // Read one record, "line" plus continuation lines starting with a
+    // space, through the C ABI, as a C caller would.
+    unsafe fn read_record(rsp: *mut RAWSCAN) -> Vec<u8> {
+        let bytes = |rt: &RAWSCAN_RESULT| {
+            let len = rt.u.line.end.offset_from(rt.u.line.begin) as usize + 1;
+            slice::from_raw_parts(rt.u.line.begin as *const u8, len).to_vec()
+        };
+        let mut record = bytes(&rs_getline(rsp));
+        loop {
+            let rt = rs_extend_record(rsp);
+            assert!(rt.type_ == RT_FULL_LINE || rt.type_ == RT_FULL_LINE_WITHOUT_EOL);
+            let extended = bytes(&rt);
+            if extended.len() == record.len() {
+                return record;
+            }
+            if extended[record.len()] != b' ' {
+                assert_eq!(rs_unget(rsp, record.len()), 0);
+                return record;
+            }
+            record = extended;
+            if rt.type_ == RT_FULL_LINE_WITHOUT_EOL {
+                return record;
+            }
+        }
+    }
+
+    #[test]
+    fn extend_records() {
+        let input = b"a\n b\n c\nd\n e";
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write_all(input).unwrap();
+        drop(writer);
+        unsafe {
+            let rsp = rs_open(reader.as_raw_fd(), 16, b'\n' as c_char);
+            assert_eq!(rs_unget(rsp, 0), -1);
+            assert_eq!(read_record(rsp), b"a\n b\n c\n");
+            assert_eq!(read_record(rsp), b"d\n e");
+            assert_eq!(rs_getline(rsp).type_, RT_EOF);
+            rs_close(rsp);
+
+            let rsp = rs_open_memory(input.as_ptr().cast(), input.len(), b'\n' as c_char);
+            assert_eq!(read_record(rsp), b"a\n b\n c\n");
+            assert_eq!(read_record(rsp), b"d\n e");
+            assert_eq!(rs_getline(rsp).type_, RT_EOF);
+            rs_close(rsp);
+        }
+    }
+
+    #[test]
+    fn resize_buffer() {
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write_all(b"abcdefghij\nk\n").unwrap();
+        drop(writer);
+
+        let mut mem = vec![0u8; RS_BUFFER_OVERHEAD + 8];
+        let input = b"abc\n";
+        unsafe {
+            let rsp = rs_open(reader.as_raw_fd(), 4, b'\n' as c_char);
+            assert_eq!(rs_getline(rsp).type_, RT_START_LONGLINE);
+            assert!(rs_resize(rsp, 0).is_null());
+            assert!(rs_resize(rsp, usize::MAX).is_null());
+            assert_eq!(*libc::__errno_location(), libc::ENOMEM);
+            let rsp = rs_resize(rsp, 16);
+            assert!(!rsp.is_null());
+            assert_eq!(rs_get_min1stchunklen(rsp), 16);
+            let rt = rs_getline(rsp);
+            assert_eq!(rt.type_, RT_WITHIN_LONGLINE);
+            assert_eq!(rt.u.line.end.offset_from(rt.u.line.begin), 6);
+            assert_eq!(rs_getline(rsp).type_, RT_LONGLINE_ENDED);
+            assert_eq!(rs_getline(rsp).type_, RT_FULL_LINE);
+            rs_close(rsp);
+
+            let rsp = rs_open_with_buffer(-1, mem.as_mut_ptr().cast(), mem.len(), b'\n' as c_char);
+            assert!(rs_resize(rsp, 64).is_null());
+            rs_close(rsp);
+
+            let rsp = rs_open_memory(input.as_ptr().cast(), input.len(), b'\n' as c_char);
+            assert!(rs_resize(rsp, 64).is_null());
+            rs_close(rsp);
+        }
+    }
+
+    #[test]
+    fn offsets_too_large_for_off_t() {
+        assert_eq!(to_off_t(12345), 12345);
+        assert_eq!(to_off_t(u64::MAX), -1);
+        assert_eq!(unsafe { *libc::__errno_location() }, libc::EOVERFLOW);
+        let err = Fd::new(-1).seek(SeekFrom::Start(u64::MAX)).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EOVERFLOW));
+    }
+
+    #[test]
+    fn split_fields() {
+        let input = b"a,,42\n 7  -1e400 inf 0x10 \n";
+        let field_text = |f: RAWSCAN_FIELD| unsafe {
+            slice::from_raw_parts(f.begin as *const u8, f.len).to_vec()
+        };
+
+        unsafe {
+            let rsp = rs_open_memory(input.as_ptr().cast(), input.len(), b'\n' as c_char);
+            let mut fields = mem::zeroed();
+            let mut field = mem::zeroed();
+            let mut val = 0;
+            let mut dval = 0.0;
+
+            let rt = rs_getline(rsp);
+            let mut got = Vec::new();
+            rs_fields_init(&mut fields, rt, b',' as c_char, false);
+            while rs_next_field(&mut fields, &mut field) {
+                got.push(field_text(field));
+            }
+            assert_eq!(got, vec![b"a".to_vec(), Vec::new(), b"42".to_vec()]);
+            assert!(rs_nth_field(rt, b',' as c_char, false, 2, &mut field));
+            assert_eq!(rs_field_to_long(field, &mut val), 0);
+            assert_eq!(val, 42);
+            assert!(!rs_nth_field(rt, b',' as c_char, false, 3, &mut field));
+            assert!(rs_nth_field(rt, b',' as c_char, false, 0, &mut field));
+            assert_eq!(rs_field_to_long(field, &mut val), -1);
+            assert_eq!(*libc::__errno_location(), libc::EINVAL);
+
+            let rt = rs_getline(rsp);
+            assert_eq!(rs_field_count(rt, b' ' as c_char, true), 4);
+            assert_eq!(rs_field_count(rt, b' ' as c_char, false), 7);
+            assert!(rs_nth_field(rt, b' ' as c_char, true, 0, &mut field));
+            assert_eq!(rs_field_to_double(field, &mut dval), 0);
+            assert_eq!(dval, 7.0);
+            assert!(rs_nth_field(rt, b' ' as c_char, true, 1, &mut field));
+            assert_eq!(rs_field_to_double(field, &mut dval), -1);
+            assert_eq!(*libc::__errno_location(), libc::ERANGE);
+            for n in 2..4 {
+                assert!(rs_nth_field(rt, b' ' as c_char, true, n, &mut field));
+                assert_eq!(rs_field_to_double(field, &mut dval), -1);
+                assert_eq!(*libc::__errno_location(), libc::EINVAL);
+            }
+
+            assert_eq!(rs_field_count(rs_getline(rsp), b' ' as c_char, true), 0);
+            rs_close(rsp);
+        }
+    }
+}
@@ -0,0 +1,375 @@
+// CsvReader, reading RFC 4180 comma separated values, whose quoted
+// fields may hold separators, doubled quotes, and delimiterbytes, so
+// that one record may span several lines.
+//
+// A record ends at the first delimiterbyte outside of quotes, which,
+// as quotes only appear in pairs around quoted fields, or doubled
+// within them, is the first delimiterbyte after an even number of
+// quotes.  So each line with an odd count of quotes so far is joined
+// to the next, using the RawScan extend_record() routine, until the
+// count comes out even, leaving the whole record in one span of the
+// buffer.
+//
+// Records too long for the buffer can't be returned that way, as the
+// fields of a record are only known once the whole record is seen.
+// Those are copied, chunk by chunk, into a side buffer, which is kept
+// for reuse by later long records.
+
+use std::borrow::Cow;
+use std::io::{self, Read};
+
+use memchr::memchr;
+
+use crate::reader::Span;
+use crate::{RawScan, RawScanResult, ResultType};
+
+/// Reads RFC 4180 CSV records from a [`RawScan`], with quoted fields
+/// that may contain separators, doubled quotes (`""`), and newlines.
+///
+/// Each record is returned as a [`CsvRecord`], whose fields are
+/// slices of the stream's buffer, except for quoted fields containing
+/// doubled quotes, which must be copied to be unescaped.  Records
+/// spanning several lines are returned whole, as one span of the
+/// buffer, so long as they fit in it.  Records that don't fit are
+/// copied into a side buffer, which grows as needed.
+///
+/// Lines may end in either "\n" or "\r\n".  Input that breaks the
+/// RFC 4180 rules, such as a quote in the middle of an unquoted
+/// field, is read without complaint, but the records and fields read
+/// are then only a best guess.
+///
+/// ```
+/// use rawscan::{CsvReader, CsvResult, RawScan};
+///
+/// let input = b"name,quote\r\nBob,\"He said \"\"hi\"\",\r\nthen left\"\r\n";
+/// let mut csv = CsvReader::new(RawScan::new(&input[..], 64, b'\n'), b',');
+///
+/// let mut records = Vec::new();
+/// while let CsvResult::Record(record) = csv.read_record() {
+///     records.push(record.fields().map(|f| f.into_owned()).collect::<Vec<_>>());
+/// }
+/// assert_eq!(records.len(), 2);
+/// assert_eq!(records[1][1], &b"He said \"hi\",\r\nthen left"[..]);
+/// ```
+pub struct CsvReader<R, B = Box<[u8]>> {
+    rs: RawScan<R, B>,
+    sep: u8,                // field separator, usually b','
+    spill: Vec<u8>,         // record too long for the buffer
+
+    // Where a read_record() that found no input ready left off, to
+    // carry on from there next call.
+
+    joining: bool,          // joining lines to the last line returned
+    spilling: Option<bool>, // spilling, with whether in quotes so far
+}
+
+/// The result of one [`CsvReader::read_record`] call.
+#[derive(Debug)]
+pub enum CsvResult<'a> {
+    /// One entire record.
+    Record(CsvRecord<'a>),
+    /// End of input, no more records.
+    Eof,
+    /// End of data due to a read error.
+    Err(&'a io::Error),
+    /// No input ready yet, from a non-blocking reader.  Call
+    /// `read_record()` again once there is, to carry on with the
+    /// record where it left off.
+    WouldBlock,
+}
+
+/// One CSV record, less its line ending, borrowing the [`CsvReader`].
+#[derive(Clone, Copy, Debug)]
+pub struct CsvRecord<'a> {
+    text: &'a [u8],
+    sep: u8,
+}
+
+/// An iterator over the fields of a [`CsvRecord`], unquoted and
+/// unescaped.
+#[derive(Clone, Debug)]
+pub struct CsvFields<'a> {
+    rest: &'a [u8],         // not yet split
+    sep: u8,                // field separator
+    done: bool,             // no more fields
+}
+
+// Where read_record() found the record, so that the borrow of the
+// buffer or side buffer can be taken once the searching is done.
+
+enum Found {
+    Span(Span),
+    Spill,
+    Eof,
+    Err(Span),
+    WouldBlock,
+}
+
+impl<R, B> CsvReader<R, B>
+where
+    R: Read,
+    B: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Read records from `rs`, with fields separated by `sep`, such as
+    /// `b','`, or `b'\t'`.  The delimiterbyte of `rs` ends records.
+    ///
+    /// Pausing is disabled on `rs`, as a record can't be left part
+    /// way through.
+    pub fn new(mut rs: RawScan<R, B>, sep: u8) -> CsvReader<R, B> {
+        rs.disable_pause();
+        CsvReader { rs, sep, spill: Vec::new(), joining: false, spilling: None }
+    }
+
+    /// Return the next record.
+    ///
+    /// A record whose closing quote is missing runs to the end of
+    /// input.  After [`Eof`] or [`Err`], further calls return the
+    /// same again.
+    ///
+    /// [`Eof`]: CsvResult::Eof
+    /// [`Err`]: CsvResult::Err
+    pub fn read_record(&mut self) -> CsvResult<'_> {
+        let found = self.find_record();
+        let text = match found {
+            Found::Span(span) => self.rs.span_bytes(span),
+            Found::Spill => &self.spill[..],
+            Found::Eof => return CsvResult::Eof,
+            Found::WouldBlock => return CsvResult::WouldBlock,
+            Found::Err(span) => match self.rs.to_result(span) {
+                RawScanResult::Err(e) => return CsvResult::Err(e),
+                _ => unreachable!("rawscan error result without error"),
+            },
+        };
+        let text = strip_line_ending(text, self.rs.delimiterbyte());
+
+        CsvResult::Record(CsvRecord { text, sep: self.sep })
+    }
+
+    fn find_record(&mut self) -> Found {
+        if let Some(in_quotes) = self.spilling.take() {
+            return self.spill_rest(in_quotes);
+        }
+        if self.joining {
+            self.joining = false;
+            let record = self.rs.last_span().expect("joined record lost");
+            return self.join_lines(record);
+        }
+
+        let first = self.rs.getline_span();
+
+        match first.kind {
+            ResultType::FullLine => {}
+            ResultType::FullLineWithoutEol => return Found::Span(first),
+            ResultType::StartLongline => {
+                self.spill.clear();
+                let in_quotes = self.spill_bytes(first, false);
+                return self.spill_rest(in_quotes);
+            }
+            ResultType::Err => return Found::Err(first),
+            ResultType::WouldBlock => return Found::WouldBlock,
+            _ => return Found::Eof,
+        }
+
+        if toggles_quotes(self.rs.span_bytes(first)) {
+            return self.join_lines(first);
+        }
+        Found::Span(first)
+    }
+
+    // Join lines to a record that ends inside quotes, until it doesn't.
+
+    fn join_lines(&mut self, mut record: Span) -> Found {
+        loop {
+            let len = record.len();
+            let ext = self.rs.rs_extend_record();
+
+            match ext.kind {
+                ResultType::FullLine | ResultType::FullLineWithoutEol => {
+                    if ext.len() == len {
+                        break;                          // input ended
+                    }
+                    let closed = toggles_quotes(&self.rs.span_bytes(ext)[len..]);
+                    record = ext;
+                    if closed || ext.kind == ResultType::FullLineWithoutEol {
+                        break;
+                    }
+                }
+                ResultType::StartLongline => {
+                    // The record no longer fits in the buffer.
+                    self.spill.clear();
+                    let in_quotes = self.spill_bytes(ext, false);
+                    return self.spill_rest(in_quotes);
+                }
+                ResultType::Err => return Found::Err(ext),
+                ResultType::WouldBlock => {
+                    self.joining = true;
+                    return Found::WouldBlock;
+                }
+                _ => break,
+            }
+        }
+        Found::Span(record)
+    }
+
+    // Copy the rest of a record too long for the buffer into the side
+    // buffer, following its first chunk there, which ended in_quotes.
+
+    fn spill_rest(&mut self, mut in_quotes: bool) -> Found {
+        loop {
+            let span = self.rs.getline_span();
+
+            match span.kind {
+                ResultType::WithinLongline | ResultType::StartLongline => {
+                    in_quotes = self.spill_bytes(span, in_quotes);
+                }
+                ResultType::FullLine => {
+                    in_quotes = self.spill_bytes(span, in_quotes);
+                    if !in_quotes {
+                        return Found::Spill;
+                    }
+                }
+                ResultType::FullLineWithoutEol => {
+                    self.spill_bytes(span, in_quotes);
+                    return Found::Spill;
+                }
+                ResultType::LonglineEnded if !in_quotes => return Found::Spill,
+                ResultType::LonglineEnded | ResultType::Paused => {}
+                ResultType::Eof => return Found::Spill,    // unterminated
+                ResultType::Err => return Found::Err(span),
+                ResultType::WouldBlock => {
+                    self.spilling = Some(in_quotes);
+                    return Found::WouldBlock;
+                }
+            }
+        }
+    }
+
+    fn spill_bytes(&mut self, span: Span, in_quotes: bool) -> bool {
+        let bytes = self.rs.span_bytes(span);
+
+        self.spill.extend_from_slice(bytes);
+        in_quotes ^ toggles_quotes(bytes)
+    }
+}
+
+impl<R, B> CsvReader<R, B> {
+    /// Gets a reference to the underlying stream.
+    pub fn get_ref(&self) -> &RawScan<R, B> {
+        &self.rs
+    }
+
+    /// Gets a mutable reference to the underlying stream.  Reading
+    /// lines directly from it will confuse the record splitting.
+    pub fn get_mut(&mut self) -> &mut RawScan<R, B> {
+        &mut self.rs
+    }
+
+    /// Unwraps this reader, returning the underlying stream.
+    pub fn into_inner(self) -> RawScan<R, B> {
+        self.rs
+    }
+}
+
+impl<'a> CsvRecord<'a> {
+    /// The raw text of this record, quotes and all, less its line
+    /// ending.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.text
+    }
+
+    /// The fields of this record.  An empty record has none.
+    pub fn fields(&self) -> CsvFields<'a> {
+        CsvFields { rest: self.text, sep: self.sep, done: self.text.is_empty() }
+    }
+}
+
+impl<'a> Iterator for CsvFields<'a> {
+    type Item = Cow<'a, [u8]>;
+
+    fn next(&mut self) -> Option<Cow<'a, [u8]>> {
+        if self.done {
+            return None;
+        }
+        let (field, len) = if self.rest.first() == Some(&b'"') {
+            quoted_field(self.rest, self.sep)
+        } else {
+            let len = memchr(self.sep, self.rest).unwrap_or(self.rest.len());
+            (Cow::Borrowed(&self.rest[..len]), len)
+        };
+
+        if len < self.rest.len() {
+            self.rest = &self.rest[len + 1..];  // past the sep
+        } else {
+            self.rest = &[];
+            self.done = true;                   // no more seps: last field
+        }
+        Some(field)
+    }
+}
+
+// Unquote the quoted field at the start of "text", returning it and
+// the length of text it took up, up to the next sep.  Anything after
+// the closing quote, before the next sep, isn't RFC 4180, but is kept,
+// as is everything after an opening quote that's never closed.
+
+fn quoted_field(text: &[u8], sep: u8) -> (Cow<'_, [u8]>, usize) {
+    let mut unescaped: Option<Vec<u8>> = None;
+    let mut start = 1;                          // past the opening quote
+
+    loop {
+        let quote = match memchr(b'"', &text[start..]) {
+            Some(i) => start + i,
+            None => {
+                let field = append(unescaped, &text[start..]);
+                return (field, text.len());
+            }
+        };
+        if text.get(quote + 1) == Some(&b'"') {
+            // Doubled quote: keep one of them.
+            unescaped.get_or_insert_with(Vec::new).extend_from_slice(&text[start..=quote]);
+            start = quote + 2;
+            continue;
+        }
+
+        let field = append(unescaped, &text[start..quote]);
+        let len = memchr(sep, &text[quote + 1..]).map_or(text.len(), |i| quote + 1 + i);
+        let trailing = &text[quote + 1..len];
+        if trailing.is_empty() {
+            return (field, len);
+        }
+        return (Cow::Owned([&field[..], trailing].concat()), len);
+    }
+}
+
+fn append(unescaped: Option<Vec<u8>>, bytes: &[u8]) -> Cow<'_, [u8]> {
+    match unescaped {
+        Some(mut field) => {
+            field.extend_from_slice(bytes);
+            Cow::Owned(field)
+        }
+        None => Cow::Borrowed(bytes),
+    }
+}
+
+// Whether "bytes" holds an odd number of quotes, so that the quoting
+// state after them is the opposite of that before.
+
+fn toggles_quotes(bytes: &[u8]) -> bool {
+    bytes.iter().filter(|&&b| b == b'"').count() & 1 == 1
+}
+
+// A record, less its delimiterbyte, and a carriage return before a
+// newline delimiter.
+
+fn strip_line_ending(text: &[u8], delimiterbyte: u8) -> &[u8] {
+    match text.split_last() {
+        Some((&last, rest)) if last == delimiterbyte => {
+            if delimiterbyte == b'\n' {
+                rest.strip_suffix(b"\r").unwrap_or(rest)
+            } else {
+                rest
+            }
+        }
+        _ => text,
+    }
+}
@@ -0,0 +1,135 @@
+// Field splitting, a port of rs_fields_init(), rs_next_field() and
+// friends in rawscan_static.h: split a line into fields at each sep
+// byte, or at runs of sep bytes, without copying.
+
+use std::str::{self, FromStr};
+
+use memchr::memchr;
+
+/// An iterator over the fields of a line, as slices of that line.
+///
+/// With `runs` false, as for tab or comma separated values, each `sep`
+/// byte ends a field, so `a,,b` has three fields, the second empty,
+/// and `a,` has two.  With `runs` true, as for space separated words,
+/// runs of `sep` bytes are one separator, and `sep` bytes at the start
+/// and end are ignored, as awk does with its default `FS`.  Either way,
+/// an empty line has no fields.  See the C `rs_fields_init()`.
+///
+/// The field count and nth field come from the [`Iterator`] methods
+/// `count()` and `nth()`.
+///
+/// ```
+/// use rawscan::{parse_field, Fields, RawScan};
+///
+/// let mut rs = RawScan::new(&b"alice\t42\t3.5\n"[..], 64, b'\n');
+/// let line = rs.getline();
+///
+/// assert_eq!(line.fields(b'\t', false).count(), 3);
+/// let age = line.fields(b'\t', false).nth(1).and_then(parse_field::<i64>);
+/// assert_eq!(age, Some(42));
+///
+/// let words: Vec<&[u8]> = Fields::new(b"  two   words ", b' ', true).collect();
+/// assert_eq!(words, [&b"two"[..], &b"words"[..]]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Fields<'a> {
+    rest: &'a [u8],         // not yet split
+    sep: u8,                // field separator byte
+    runs: bool,             // runs of sep are one separator
+    done: bool,             // no more fields
+}
+
+impl<'a> Fields<'a> {
+    /// Split `text` into fields at each `sep` byte, or, if `runs`, at
+    /// each run of `sep` bytes.
+    pub fn new(text: &'a [u8], sep: u8, runs: bool) -> Fields<'a> {
+        Fields { rest: text, sep, runs, done: text.is_empty() }
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.runs {
+            let skip = self.rest.iter().take_while(|&&b| b == self.sep).count();
+            self.rest = &self.rest[skip..];
+            if self.rest.is_empty() {
+                self.done = true;
+            }
+        }
+        if self.done {
+            return None;
+        }
+
+        match memchr(self.sep, self.rest) {
+            Some(d) => {
+                let field = &self.rest[..d];
+                self.rest = &self.rest[d + 1..];
+                Some(field)
+            }
+            None => {
+                self.done = true;       // no more seps: last field
+                Some(self.rest)
+            }
+        }
+    }
+}
+
+/// Parse a whole field as a number, or other [`FromStr`] type, such as
+/// `i64` or `f64`.  Returns `None` if the field isn't UTF-8, or if
+/// `parse()` fails.  The Rust equivalent of the C `rs_field_to_long()`.
+/// See [`parse_decimal()`] for the C `rs_field_to_double()`.
+pub fn parse_field<T: FromStr>(field: &[u8]) -> Option<T> {
+    str::from_utf8(field).ok()?.parse().ok()
+}
+
+/// Parse a whole field as a plain decimal number, with an optional
+/// sign, fraction and exponent, such as `-12.5e3`, `.5` or `7.`, as
+/// the C `rs_field_to_double()` does.  Returns `None` for anything
+/// else, including the `inf` and `nan` that `parse_field::<f64>()`
+/// takes.  Numbers too large for an `f64` come back infinite, as no
+/// field that parses is otherwise infinite.
+///
+/// ```
+/// use rawscan::parse_decimal;
+///
+/// assert_eq!(parse_decimal(b"-12.5e3"), Some(-12500.0));
+/// assert_eq!(parse_decimal(b"1e999"), Some(f64::INFINITY));
+/// assert_eq!(parse_decimal(b"inf"), None);
+/// ```
+pub fn parse_decimal(field: &[u8]) -> Option<f64> {
+    if !is_decimal(field) {
+        return None;
+    }
+    str::from_utf8(field).ok()?.parse().ok()
+}
+
+// Whether "field" is [+-]digits[.digits][(e|E)[+-]digits], where
+// either the digits before the '.' or those after may be left out.
+
+fn is_decimal(field: &[u8]) -> bool {
+    let digits = |s: &[u8]| s.iter().take_while(|b| b.is_ascii_digit()).count();
+    let sign = |s: &[u8]| if let [b'+' | b'-', ..] = s { 1 } else { 0 };
+
+    let mut s = &field[sign(field)..];
+    let whole = digits(s);
+    s = &s[whole..];
+    let mut frac = 0;
+    if let [b'.', rest @ ..] = s {
+        frac = digits(rest);
+        s = &rest[frac..];
+    }
+    if whole + frac == 0 {
+        return false;
+    }
+    if let [b'e' | b'E', rest @ ..] = s {
+        let rest = &rest[sign(rest)..];
+        let exp = digits(rest);
+        if exp == 0 {
+            return false;
+        }
+        s = &rest[exp..];
+    }
+    s.is_empty()
+}
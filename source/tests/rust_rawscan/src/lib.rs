@@ -0,0 +1,204 @@
+//! rawscan - read input, one line at a time, quickly and safely.
+//!
+//! This crate is a native Rust implementation of the rawscan line
+//! reader, whose C implementation lives in `rawscan_static.h`.  It
+//! follows the same algorithm as that C code: one fixed size buffer,
+//! allocated when the stream is opened, and lines returned as slices
+//! directly into that buffer, with no copies except for the occasional
+//! shift of a partial line lower in the buffer.  Lines too long to fit
+//! in the buffer are returned in multiple chunks.
+//!
+//! The C header comments warn that each `rs_getline()` call might
+//! invalidate the lines returned by earlier calls.  Here that warning
+//! is enforced by the compiler.  [`RawScan::getline`] borrows the
+//! stream mutably, and the returned [`RawScanResult`] holds that borrow
+//! for as long as the line is in use, so the following is rejected:
+//!
+//! ```compile_fail
+//! let mut rs = rawscan::RawScan::new(&b"one\ntwo\n"[..], 16, b'\n');
+//! let first = rs.getline();
+//! let second = rs.getline();      // error: rs is still borrowed by first
+//! println!("{:?} {:?}", first, second);
+//! ```
+//!
+//! A typical loop over the results looks like the C switch statement
+//! over `RAWSCAN_RESULT.type`:
+//!
+//! ```
+//! use rawscan::{RawScan, RawScanResult};
+//!
+//! let mut rs = RawScan::new(&b"abc\ndef\nabcdef"[..], 16, b'\n');
+//! let mut matches = 0;
+//! loop {
+//!     match rs.getline() {
+//!         RawScanResult::FullLine(line) |
+//!         RawScanResult::FullLineWithoutEol(line) => {
+//!             if line.starts_with(b"abc") {
+//!                 matches += 1;
+//!             }
+//!         }
+//!         RawScanResult::StartLongline(_) |
+//!         RawScanResult::WithinLongline(_) |
+//!         RawScanResult::LonglineEnded |
+//!         RawScanResult::Paused |
+//!         RawScanResult::WouldBlock => {}
+//!         RawScanResult::Eof => break,
+//!         RawScanResult::Err(e) => panic!("read failed: {}", e),
+//!     }
+//! }
+//! assert_eq!(matches, 2);
+//! ```
+//!
+//! [`RawScan`] also implements [`std::io::BufRead`], over the same
+//! buffer, so code written against `impl BufRead`, or loops over
+//! `lines()` or `split()`, can switch to rawscan by changing the line
+//! that builds the reader:
+//!
+//! ```
+//! use std::io::BufRead;
+//!
+//! let buffered = rawscan::RawScan::new(&b"abc\nxyz\n"[..], 16 * 1024, b'\n');
+//! for line in buffered.lines() {
+//!     let line = line.unwrap();
+//!     if line.starts_with("abc") {
+//!         println!("{}", line);
+//!     }
+//! }
+//! ```
+//!
+//! Input that's already in memory, such as an embedded table, can be
+//! scanned in place with a [`SliceScan`], which returns the same
+//! results without copying or writing to the input.
+//!
+//! Multiline records, such as stack traces, can be read whole, so long
+//! as they fit in the buffer, with [`RawScan::extend_record`], or with
+//! a [`RecordScanner`], which groups lines into records using a
+//! caller supplied test of which lines continue a record.
+//!
+//! Lines can be split into [`Fields`], without copying, with
+//! [`RawScanResult::fields`], and fields parsed with [`parse_field`]
+//! or [`parse_decimal`].
+//!
+//! RFC 4180 CSV records, whose quoted fields may span lines, can be
+//! read whole, and split into unquoted fields, with a [`CsvReader`].
+//!
+//! On Linux, lines can be read from many pipes or sockets at once, in
+//! one thread, as input arrives on each, with a [`RawScanSet`].
+//!
+//! A large regular file can be scanned by many threads at once, each
+//! taking its own range of whole lines, with a [`ParallelScan`].
+//!
+//! A file's lines can be read last line first, as by `tac` or `tail`,
+//! reading backward from the end of the file, with a [`ReverseScan`].
+
+mod csv;
+mod fields;
+#[cfg(unix)]
+mod parallel;
+mod reader;
+mod record;
+mod reverse;
+#[cfg(target_os = "linux")]
+mod set;
+mod slice;
+
+pub use csv::{CsvFields, CsvReader, CsvRecord, CsvResult};
+pub use fields::{parse_decimal, parse_field, Fields};
+#[cfg(unix)]
+pub use parallel::{ParallelScan, RangeReader};
+pub use reader::RawScan;
+pub use record::RecordScanner;
+pub use reverse::ReverseScan;
+#[cfg(target_os = "linux")]
+pub use set::RawScanSet;
+pub use slice::SliceScan;
+
+use std::io;
+
+/// Enumerates the various kinds of results that [`RawScan::getline`]
+/// returns, mirroring the C `enum rs_result_type`.
+///
+/// [`RawScanResult`] carries the same information along with the data;
+/// this plain enum is handy where only the kind of result is wanted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResultType {
+    FullLine,
+    FullLineWithoutEol,
+    StartLongline,
+    WithinLongline,
+    LonglineEnded,
+    Paused,
+    Eof,
+    Err,
+    WouldBlock,
+}
+
+/// The result of one [`RawScan::getline`] call, the Rust equivalent of
+/// the C `RAWSCAN_RESULT`.
+///
+/// Lines and chunks are slices into the stream's buffer, including the
+/// trailing delimiterbyte, if any.  They borrow the stream, so they
+/// can't be used after the next call that could move or overwrite the
+/// buffer contents.
+#[derive(Debug)]
+pub enum RawScanResult<'a> {
+    /// One entire line, ending with the delimiterbyte.
+    FullLine(&'a [u8]),
+    /// One entire line, at the end of input, without a delimiterbyte.
+    FullLineWithoutEol(&'a [u8]),
+    /// First chunk in a line too long to return in one piece.
+    StartLongline(&'a [u8]),
+    /// Another chunk in this long line.
+    WithinLongline(&'a [u8]),
+    /// No more chunks in this long line.
+    LonglineEnded,
+    /// `getline()` is a no-op until `resume_from_pause()` is called.
+    Paused,
+    /// End of input, no more data available.
+    Eof,
+    /// End of data due to a read error.
+    Err(&'a io::Error),
+    /// No input ready yet, from a non-blocking reader; call `getline()`
+    /// again once there is.
+    WouldBlock,
+}
+
+impl<'a> RawScanResult<'a> {
+    /// Which kind of result this is.
+    pub fn result_type(&self) -> ResultType {
+        match self {
+            RawScanResult::FullLine(_) => ResultType::FullLine,
+            RawScanResult::FullLineWithoutEol(_) => ResultType::FullLineWithoutEol,
+            RawScanResult::StartLongline(_) => ResultType::StartLongline,
+            RawScanResult::WithinLongline(_) => ResultType::WithinLongline,
+            RawScanResult::LonglineEnded => ResultType::LonglineEnded,
+            RawScanResult::Paused => ResultType::Paused,
+            RawScanResult::Eof => ResultType::Eof,
+            RawScanResult::Err(_) => ResultType::Err,
+            RawScanResult::WouldBlock => ResultType::WouldBlock,
+        }
+    }
+
+    /// The line or chunk carried by this result, if any.
+    pub fn line(&self) -> Option<&'a [u8]> {
+        match *self {
+            RawScanResult::FullLine(line) |
+            RawScanResult::FullLineWithoutEol(line) |
+            RawScanResult::StartLongline(line) |
+            RawScanResult::WithinLongline(line) => Some(line),
+            _ => None,
+        }
+    }
+
+    /// The fields of the line or chunk carried by this result, less the
+    /// delimiterbyte ending a `FullLine`, split at each `sep` byte, or,
+    /// if `runs`, at each run of `sep` bytes.  Results without a line
+    /// have no fields.  See [`Fields`], and the C `rs_fields_init()`.
+    pub fn fields(&self, sep: u8, runs: bool) -> Fields<'a> {
+        let text = match *self {
+            RawScanResult::FullLine(line) => &line[..line.len() - 1],
+            _ => self.line().unwrap_or_default(),
+        };
+        Fields::new(text, sep, runs)
+    }
+}
@@ -0,0 +1,267 @@
+// ParallelScan, scanning one large regular file with many threads at
+// once, each running its own RawScan over its own byte range of the
+// file.
+//
+// The file is cut into ranges of about equal size, each cut then moved
+// forward to just past the next delimiterbyte, so that every range
+// holds only whole lines, and every line is in just one range.  Each
+// range is read with positioned reads (pread(2)), so the threads share
+// the one open file, without fighting over its file offset.
+//
+// Each range is scanned on its own, so results come back per range,
+// in range order, and concatenating them keeps the lines in the order
+// they're in the file.  filter_to() does that concatenation as it
+// goes, writing out each range's lines as soon as the ranges before
+// it are all written.  Each range sends its lines over a bounded
+// channel, so a range that's not yet being written gets only a few
+// chunks ahead before it waits, and memory use stays small, however
+// large the file.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::os::unix::fs::FileExt;
+use std::panic;
+use std::sync::mpsc;
+use std::thread;
+
+use memchr::memchr;
+
+use crate::{RawScan, RawScanResult};
+
+const FILTER_CHUNK: usize = 64 * 1024;  // filter_to() output per send
+const FILTER_AHEAD: usize = 4;          // sends queued before blocking
+
+/// Scans a regular file in parallel, one thread per byte range, each
+/// range holding only whole lines.
+///
+/// ```
+/// # use std::io::Write;
+/// use rawscan::ParallelScan;
+///
+/// # let path = std::env::temp_dir().join(format!("rawscan-doc-parallel-{}", std::process::id()));
+/// # std::fs::write(&path, b"apple\nbanana\ncherry\navocado\n")?;
+/// let file = std::fs::File::open(&path)?;
+/// let scan = ParallelScan::new(file, 4, 64 * 1024, b'\n')?;
+///
+/// let mut out = Vec::new();
+/// scan.filter_to(&mut out, |line| line.starts_with(b"a"))?;
+/// assert_eq!(out, b"apple\navocado\n");
+/// # std::fs::remove_file(&path)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Unix only, as it reads with `pread(2)`.
+pub struct ParallelScan {
+    file: File,
+    ranges: Vec<Range<u64>>,
+    bufsz: usize,
+    delimiterbyte: u8,
+}
+
+impl ParallelScan {
+    /// Cut `file` into up to `nranges` ranges, to be scanned with
+    /// `bufsz` byte buffers, ending lines at each `delimiterbyte`.
+    ///
+    /// There may be fewer ranges than asked for, if the file is small
+    /// or has few delimiterbytes; an empty file has none.  Fails if
+    /// `nranges` or `bufsz` is zero, or if reading the file does.
+    pub fn new(
+        file: File,
+        nranges: usize,
+        bufsz: usize,
+        delimiterbyte: u8,
+    ) -> io::Result<ParallelScan> {
+        if nranges == 0 || bufsz == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "zero ranges or zero rawscan buffer size",
+            ));
+        }
+
+        let len = file.metadata()?.len();
+        let mut ranges = Vec::with_capacity(nranges);
+        let mut start = 0;
+
+        for i in 1..=nranges as u64 {
+            if start == len {
+                break;
+            }
+            let cut = (len as u128 * i as u128 / nranges as u128) as u64;
+            let end = if cut <= start || cut == len {
+                cut.max(start)
+            } else {
+                after_next_delim(&file, cut - 1, len, delimiterbyte)?
+            };
+            if end > start {
+                ranges.push(start..end);
+                start = end;
+            }
+        }
+        Ok(ParallelScan { file, ranges, bufsz, delimiterbyte })
+    }
+
+    /// The byte ranges of the file, in order, together covering it all.
+    pub fn ranges(&self) -> &[Range<u64>] {
+        &self.ranges
+    }
+
+    /// A stream reading just range `i`.  Its offsets, such as from
+    /// `line_offset()`, are from the start of the range; add
+    /// `ranges()[i].start` for offsets in the file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not less than `ranges().len()`.
+    pub fn reader(&self, i: usize) -> RawScan<RangeReader<'_>> {
+        let range = self.ranges[i].clone();
+        let reader = RangeReader { file: &self.file, pos: range.start, end: range.end };
+
+        RawScan::new(reader, self.bufsz, self.delimiterbyte)
+    }
+
+    /// Call `f` on the stream for each range, each in its own thread,
+    /// returning what each call returns, in range order.  `f` is also
+    /// passed the range's index in `ranges()`.
+    pub fn run<T, F>(&self, f: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(usize, RawScan<RangeReader<'_>>) -> T + Sync,
+    {
+        thread::scope(|scope| {
+            let f = &f;
+            let threads: Vec<_> = (0..self.ranges.len())
+                .map(|i| scope.spawn(move || f(i, self.reader(i))))
+                .collect();
+
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect()
+        })
+    }
+
+    /// Write to `out` each line, including its delimiterbyte, if any,
+    /// for which `keep()` returns true, in the order the lines are in
+    /// the file, as a single threaded filter would.  Lines too long
+    /// for the buffer are put back together before being passed to
+    /// `keep()`.
+    ///
+    /// Each range's thread runs at most a few 64KB chunks of output
+    /// ahead of the writer, then waits for the ranges before it to be
+    /// written, so that memory use stays bounded, however much of the
+    /// file is kept.
+    pub fn filter_to<W, P>(&self, mut out: W, keep: P) -> io::Result<()>
+    where
+        W: Write,
+        P: Fn(&[u8]) -> bool + Sync,
+    {
+        thread::scope(|scope| {
+            let keep = &keep;
+            let receivers: Vec<_> = (0..self.ranges.len())
+                .map(|i| {
+                    let (tx, rx) = mpsc::sync_channel(FILTER_AHEAD);
+                    scope.spawn(move || filter_range(self.reader(i), keep, tx));
+                    rx
+                })
+                .collect();
+
+            // Dropping the receivers, on a write error, stops the rest.
+            for rx in receivers {
+                for chunk in rx {
+                    out.write_all(&chunk?)?;
+                }
+            }
+            out.flush()
+        })
+    }
+}
+
+// Send the lines in rs that keep() accepts, a chunk at a time, ending
+// with a read error, if any.  Waits whenever the channel is full, and
+// stops early if the receiver is dropped.
+
+fn filter_range<P>(
+    mut rs: RawScan<RangeReader<'_>>,
+    keep: &P,
+    tx: mpsc::SyncSender<io::Result<Vec<u8>>>,
+) where
+    P: Fn(&[u8]) -> bool,
+{
+    let mut chunk = Vec::new();
+    let mut long = Vec::new();
+
+    loop {
+        match rs.getline() {
+            RawScanResult::FullLine(line) | RawScanResult::FullLineWithoutEol(line) => {
+                if keep(line) {
+                    chunk.extend_from_slice(line);
+                }
+            }
+            RawScanResult::StartLongline(line) => {
+                long.clear();
+                long.extend_from_slice(line);
+            }
+            RawScanResult::WithinLongline(line) => long.extend_from_slice(line),
+            RawScanResult::LonglineEnded => {
+                if keep(&long) {
+                    chunk.extend_from_slice(&long);
+                }
+            }
+            RawScanResult::Paused | RawScanResult::WouldBlock => {}
+            RawScanResult::Eof => break,
+            RawScanResult::Err(_) => {
+                let e = rs.take_err().expect("rawscan error result without error");
+                let _ = tx.send(Ok(chunk));
+                let _ = tx.send(Err(e));
+                return;
+            }
+        }
+
+        if chunk.len() >= FILTER_CHUNK && tx.send(Ok(std::mem::take(&mut chunk))).is_err() {
+            return;
+        }
+    }
+    let _ = tx.send(Ok(chunk));
+}
+
+// The offset just past the first delimiterbyte at or after "from",
+// else "len", if there's none.
+
+fn after_next_delim(file: &File, mut from: u64, len: u64, delimiterbyte: u8) -> io::Result<u64> {
+    let mut buf = vec![0u8; 64 * 1024];
+
+    while from < len {
+        let n = file.read_at(&mut buf, from)?;
+        if n == 0 {
+            break;                              // file shrank
+        }
+        if let Some(i) = memchr(delimiterbyte, &buf[..n]) {
+            return Ok((from + i as u64 + 1).min(len));
+        }
+        from += n as u64;
+    }
+    Ok(len)
+}
+
+/// Reads one byte range of a file, with positioned reads, leaving the
+/// file's own offset alone.  See [`ParallelScan::reader`].
+#[derive(Debug)]
+pub struct RangeReader<'a> {
+    file: &'a File,
+    pos: u64,
+    end: u64,
+}
+
+impl<'a> Read for RangeReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let want = (buf.len() as u64).min(self.end - self.pos) as usize;
+        if want == 0 {
+            return Ok(0);
+        }
+
+        let n = self.file.read_at(&mut buf[..want], self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
@@ -0,0 +1,1238 @@
+// The RawScan stream, a port of the RAWSCAN structure and the rs_*()
+// routines in rawscan_static.h.
+//
+// The code below deliberately keeps the shape of the C code, down to
+// the names of the private helper routines, so that the two can be
+// read side by side.  Pointers into the C buffer become indices into
+// our buffer, and "buftop" (the l.u.b. of the buffer) is just bufsz.
+//
+// The C code relies on a read-only sentinel copy of the delimiterbyte
+// just above the buffer, so that rawmemchr() always terminates.  Here
+// find_delim() does a bounded memchr() over [start, q) instead, which
+// costs nothing noticeable, and which gives the same answers to every
+// "next_delim < q" test that the C code makes.
+//
+// The buffer is any B that lends out a mutable byte slice, so callers
+// that can't allocate at runtime can supply their own, as C callers
+// can with rs_open_with_buffer().  Without a sentinel, we need no
+// room beyond the buffer itself.
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::thread;
+use std::time::Duration;
+
+use memchr::memchr;
+
+use crate::{RawScanResult, ResultType};
+
+/// A rawscan input stream, reading lines from `R` into a fixed size
+/// buffer.
+///
+/// `R` is any [`Read`], standing in for the C `read(2)` or `rs_read_fn`
+/// input routine.  Where the input routine must be chosen at runtime,
+/// such as a decompressor for some inputs but not others, use a trait
+/// object, as in `RawScan<Box<dyn Read>>`.
+///
+/// The buffer is a `Box<[u8]>` allocated by [`RawScan::new`], unless
+/// the caller supplies some other `B` to [`RawScan::with_buffer`].
+///
+/// See the crate documentation, and the long comments in the C
+/// `rawscan_static.h` header, for how the buffer is managed.
+pub struct RawScan<R, B = Box<[u8]>> {
+    reader: R,              // read rawscan input from here
+    buf: B,                 // bufsz buffer; bufsz is "buftop"
+
+    p: usize,               // [p, q) not yet returned bytes in buf
+    q: usize,
+
+    bufsz: usize,           // main input buffer size
+    min1stchunklen: usize,  // guaranteed min len of first chunk of long line
+
+    // When getline() calls a subroutine to return the next line or
+    // chunk, then it tells the subroutine the index of the last byte
+    // in that chunk/line, and the next value of p.
+
+    end_this_chunk: usize,  // index of last byte in this line/chunk
+    next_val_p: usize,      // start next chunk/line (or q if none)
+
+    // cache one next_delim ahead if in fast loop, to
+    // optimize returning many short lines from one buffer read
+
+    next_delim_peek: usize,
+
+    err: Option<io::Error>, // stashed error from failed read
+    follow: Option<Duration>,   // if Some, at eof, sleep this long, retry
+
+    // The last line or chunk returned, as in the C rsp->result, that
+    // extend_record() extends, or unget() hands back.  None if there
+    // is no such line, as after an unget().
+
+    last: Option<Span>,
+
+    // Where we are in the input, as in the C q_offset, longline_offset,
+    // ndelims and counted_len: delimiterbytes are counted as lines are
+    // returned, less the counted_len bytes at p that extend_record()
+    // has already counted.
+
+    q_offset: u64,          // input byte offset of buf[q]: bytes read so far
+    longline_offset: u64,   // input byte offset of start of long line
+    ndelims: u64,           // delimiterbytes in input before p
+    counted_len: usize,     // bytes at p whose delimiterbytes are counted
+
+    delimiterbyte: u8,      // byte @ end of "lines" (e.g. b'\n' or b'\0')
+    in_longline: bool,      // seen begin of too long line, but not yet end
+    terminate_current_pause: bool, // resume from current pause
+    paused: bool,           // returned Paused, not yet resumed
+
+    longline_ended: bool,   // end of long line seen
+    eof_seen: bool,         // eof seen - can read no more into buffer
+    pause_on_inval: bool,   // pause when need to invalidate buffer
+    paragraph_mode: bool,   // records end at two consecutive delimiterbytes
+    chunk_ended_in_delim: bool, // last longline chunk ended in delimiterbyte
+    would_block: bool,      // last read found no input ready (WouldBlock)
+}
+
+// What a getline() call found, as indices into the buffer.  Converted
+// into a RawScanResult borrowing the buffer only at the very end, so
+// that the loops in rs_getline_morecode() are free to update self.
+
+#[derive(Clone, Copy)]
+pub(crate) struct Span {
+    pub(crate) kind: ResultType,
+    pub(crate) begin: usize,
+    pub(crate) end: usize,
+}
+
+impl Span {
+    pub(crate) fn line(kind: ResultType, begin: usize, end: usize) -> Span {
+        Span { kind, begin, end }
+    }
+
+    pub(crate) fn bare(kind: ResultType) -> Span {
+        Span { kind, begin: 0, end: 0 }
+    }
+
+    // Length of the line or chunk, including any delimiterbyte.
+    pub(crate) fn len(&self) -> usize {
+        self.end + 1 - self.begin
+    }
+}
+
+impl<R: Read> RawScan<R> {
+    /// Open a rawscan stream reading from `reader`, using a `bufsz`
+    /// byte buffer, and ending lines at each `delimiterbyte`.
+    ///
+    /// All lines shorter than `bufsz` are returned in one piece; longer
+    /// lines are returned in chunks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bufsz` is zero.
+    pub fn new(reader: R, bufsz: usize, delimiterbyte: u8) -> RawScan<R> {
+        assert!(bufsz > 0, "rawscan buffer size must be at least one byte");
+
+        RawScan::with_buffer(reader, vec![0u8; bufsz].into_boxed_slice(), delimiterbyte)
+    }
+
+    /// Like [`new()`], but fails with `ErrorKind::OutOfMemory`, rather
+    /// than aborting the process, if the buffer can't be allocated, as
+    /// the C `rs_open()` fails with ENOMEM, and with `InvalidInput` if
+    /// `bufsz` is zero.
+    ///
+    /// [`new()`]: RawScan::new
+    pub fn try_new(reader: R, bufsz: usize, delimiterbyte: u8) -> io::Result<RawScan<R>> {
+        if bufsz == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "zero rawscan buffer size"));
+        }
+
+        let mut buf = Vec::new();
+        if buf.try_reserve_exact(bufsz).is_err() {
+            return Err(io::ErrorKind::OutOfMemory.into());
+        }
+        buf.resize(bufsz, 0u8);
+        Ok(RawScan::with_buffer(reader, buf.into_boxed_slice(), delimiterbyte))
+    }
+
+    /// Resize the buffer to `new_bufsz` bytes, keeping our place in
+    /// the input, as to grow it once lines turn out to be long.  The
+    /// Rust equivalent of the C `rs_resize()`.
+    ///
+    /// ```
+    /// use rawscan::{RawScan, RawScanResult};
+    ///
+    /// let mut rs = RawScan::new(&b"ab\nlonger line\n"[..], 4, b'\n');
+    /// assert!(matches!(rs.getline(), RawScanResult::FullLine(b"ab\n")));
+    /// rs.resize(64).unwrap();
+    /// assert!(matches!(rs.getline(), RawScanResult::FullLine(b"longer line\n")));
+    /// ```
+    ///
+    /// Fails, changing nothing, if `new_bufsz` is zero or is too small
+    /// to hold the bytes buffered but not yet returned.  See
+    /// [`replace_buffer()`] for the details.
+    ///
+    /// [`replace_buffer()`]: RawScan::replace_buffer
+    pub fn resize(&mut self, new_bufsz: usize) -> io::Result<()> {
+        if new_bufsz == 0 || new_bufsz < self.q - self.p {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "new rawscan buffer size zero, or too small for buffered bytes",
+            ));
+        }
+
+        let replaced = self.replace_buffer(vec![0u8; new_bufsz].into_boxed_slice());
+        debug_assert!(replaced.is_ok());
+        Ok(())
+    }
+}
+
+impl<R: Read, B: AsRef<[u8]> + AsMut<[u8]>> RawScan<R, B> {
+    /// Open a rawscan stream reading from `reader` into the caller
+    /// supplied `buf`, ending lines at each `delimiterbyte`, without
+    /// allocating any memory, now or later.  The Rust equivalent of the
+    /// C `rs_open_with_buffer()`.
+    ///
+    /// The buffer may be an array, held right in the `RawScan`, or a
+    /// borrowed slice, such as a `&'static mut [u8]` set aside at
+    /// startup.  Its contents on entry don't matter.
+    ///
+    /// ```
+    /// use rawscan::{RawScan, RawScanResult};
+    ///
+    /// let mut rs = RawScan::with_buffer(&b"abc\ndef\n"[..], [0u8; 64], b'\n');
+    /// assert!(matches!(rs.getline(), RawScanResult::FullLine(b"abc\n")));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buf` is empty.
+    pub fn with_buffer(reader: R, buf: B, delimiterbyte: u8) -> RawScan<R, B> {
+        let bufsz = buf.as_ref().len();
+        assert!(bufsz > 0, "rawscan buffer size must be at least one byte");
+
+        RawScan {
+            reader,
+            buf,
+
+            // Initializing p and q to buftop, not to buf, tricks getline()
+            // into calling rawscan_read() before scanning on first call.
+            p: bufsz,
+            q: bufsz,
+
+            bufsz,
+            min1stchunklen: bufsz,
+            end_this_chunk: 0,
+            next_val_p: 0,
+            next_delim_peek: bufsz,
+            err: None,
+            follow: None,
+            last: None,
+            q_offset: 0,
+            longline_offset: 0,
+            ndelims: 0,
+            counted_len: 0,
+            delimiterbyte,
+            in_longline: false,
+            terminate_current_pause: false,
+            paused: false,
+            longline_ended: false,
+            eof_seen: false,
+            pause_on_inval: false,
+            paragraph_mode: false,
+            chunk_ended_in_delim: false,
+            would_block: false,
+        }
+    }
+
+    /// Return the next line, or chunk of a long line, from the stream.
+    ///
+    /// The returned line borrows the stream, and so must be finished
+    /// with before `getline()` can be called again.  See the comments
+    /// above `rs_getline()` in `rawscan_static.h` for the details of
+    /// the results returned.
+    pub fn getline(&mut self) -> RawScanResult<'_> {
+        let span = self.getline_span();
+        self.to_result(span)
+    }
+
+    /// Return the full line just returned, with the next line appended
+    /// to it, as one multiline record.  Call again to append another
+    /// line, and so on.  The Rust equivalent of the C
+    /// `rs_extend_record()`.
+    ///
+    /// The record is returned as a [`FullLine`], or as a
+    /// [`FullLineWithoutEol`] if the appended line ended the input
+    /// without a delimiterbyte, so long as it fits in the buffer.  A
+    /// record longer than min1stchunklen is returned as a long line
+    /// would be: this call returns its first chunk, and following
+    /// `getline()` calls return the rest of the appended line.  Once
+    /// the input has ended, the record is returned again, no longer
+    /// than before.  If the last result wasn't a `FullLine`, this is
+    /// just a `getline()`.
+    ///
+    /// ```
+    /// use rawscan::{RawScan, RawScanResult};
+    ///
+    /// let mut rs = RawScan::new(&b"Error: oops\n  at main\nOK\n"[..], 64, b'\n');
+    /// rs.getline();
+    /// let record = rs.extend_record();
+    /// assert!(matches!(record, RawScanResult::FullLine(b"Error: oops\n  at main\n")));
+    /// ```
+    ///
+    /// [`FullLine`]: RawScanResult::FullLine
+    /// [`FullLineWithoutEol`]: RawScanResult::FullLineWithoutEol
+    pub fn extend_record(&mut self) -> RawScanResult<'_> {
+        let span = self.rs_extend_record();
+        self.to_result(span)
+    }
+
+    /// Hand back the tail end of the line, or chunk of a long line,
+    /// just returned, from `offset` bytes into it on, to be returned
+    /// again by the next `getline()`.  The Rust equivalent of the C
+    /// `rs_unget()`.
+    ///
+    /// With [`extend_record()`], this stops a record just before a
+    /// line that turns out to start the next record.  Handing back a
+    /// chunk of a long line ends that long line, and the handed back
+    /// bytes are scanned again as the start of a new line.
+    ///
+    /// Fails, changing nothing, if the last result wasn't a line or a
+    /// chunk, or if `offset` is past its end.
+    ///
+    /// [`extend_record()`]: RawScan::extend_record
+    pub fn unget(&mut self, offset: usize) -> io::Result<()> {
+        if self.rs_unget(offset) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no line to unget, or offset past its end",
+            ))
+        }
+    }
+
+    /// Move the stream into the buffer `buf`, carrying over the bytes
+    /// not yet returned and the state of the stream, such as being in
+    /// the middle of a long line, and hand back the old buffer.  The
+    /// buffer may be larger or smaller than the old one.  See the C
+    /// `rs_resize()`, and [`resize()`] for a stream with an allocated
+    /// buffer.
+    ///
+    /// The min1stchunklen carries over, unless it was the whole old
+    /// buffer (the default), or is larger than `buf`, in which case it
+    /// becomes the whole of `buf`.
+    ///
+    /// Fails, changing nothing, and handing `buf` back untouched, if
+    /// `buf` is empty or too small to hold the bytes not yet returned.
+    ///
+    /// [`resize()`]: RawScan::resize
+    pub fn replace_buffer(&mut self, mut buf: B) -> Result<B, B> {
+        let new_bufsz = buf.as_ref().len();
+        let len = self.q - self.p;
+
+        if new_bufsz == 0 || len > new_bufsz {
+            return Err(buf);
+        }
+
+        buf.as_mut()[..len].copy_from_slice(&self.buf.as_ref()[self.p..self.q]);
+        let old = std::mem::replace(&mut self.buf, buf);
+
+        if self.min1stchunklen == self.bufsz || self.min1stchunklen > new_bufsz {
+            self.min1stchunklen = new_bufsz;
+        }
+        self.bufsz = new_bufsz;
+        self.p = 0;
+        self.q = len;
+        self.end_this_chunk = 0;
+        self.next_val_p = 0;
+        self.next_delim_peek = new_bufsz;       // disable "peek"
+        self.last = None;
+
+        Ok(old)
+    }
+
+    // getline() and extend_record(), without the borrow of the buffer,
+    // for use by the RecordScanner.
+
+    pub(crate) fn getline_span(&mut self) -> Span {
+        let span = self.rs_getline();
+        self.last = Some(span);
+        span
+    }
+
+    pub(crate) fn rs_extend_record(&mut self) -> Span {
+        let record = match self.last {
+            Some(span) if span.kind == ResultType::FullLine && !self.in_longline => span,
+            _ => return self.getline_span(),
+        };
+        let scan_from = record.end + 1;
+        debug_assert!(self.p == scan_from);
+
+        // "Unreturn" the record, then scan on from just past it, as if
+        // the record and the line after it were one line.
+
+        self.p = record.begin;
+        self.counted_len = scan_from - record.begin;
+        self.next_delim_peek = self.bufsz;      // disable "peek"
+
+        let span = self.rs_getline_morecode(scan_from);
+
+        if span.kind == ResultType::WouldBlock {
+            // The record may have been shifted down, but is still at p,
+            // with the start of the line to append after it.  Re-return
+            // it, from there.
+            let record = Span::line(ResultType::FullLine, self.p, self.p + self.counted_len - 1);
+            self.p = record.end + 1;
+            self.counted_len = 0;
+            self.last = Some(record);
+        } else if span.kind == ResultType::Paused {
+            self.counted_len = 0;
+            // Nothing shifted yet: re-return the record, so that calling
+            // extend_record() again, after resume_from_pause(), retries.
+            self.p = scan_from;
+            self.last = Some(record);
+        } else {
+            self.last = Some(span);
+        }
+        span
+    }
+
+    pub(crate) fn rs_unget(&mut self, offset: usize) -> bool {
+        let span = match self.last {
+            Some(span) => span,
+            None => return false,
+        };
+        match span.kind {
+            ResultType::FullLine |
+            ResultType::FullLineWithoutEol |
+            ResultType::StartLongline |
+            ResultType::WithinLongline => {}
+            _ => return false,
+        }
+        if offset > span.len() {
+            return false;
+        }
+
+        self.p = span.begin + offset;
+        self.ndelims -= self.count_delims_in(self.p, span.end + 1);
+        self.counted_len = 0;
+        self.in_longline = false;
+        self.longline_ended = false;
+        self.next_delim_peek = self.bufsz;      // disable "peek"
+        self.last = None;
+        true
+    }
+
+    pub(crate) fn last_span(&self) -> Option<Span> {
+        self.last
+    }
+
+    // The bytes of a line or chunk returned as a Span.
+
+    pub(crate) fn span_bytes(&self, span: Span) -> &[u8] {
+        &self.buf.as_ref()[span.begin..=span.end]
+    }
+
+    pub(crate) fn to_result(&self, span: Span) -> RawScanResult<'_> {
+        let line = || &self.buf.as_ref()[span.begin..=span.end];
+
+        match span.kind {
+            ResultType::FullLine => RawScanResult::FullLine(line()),
+            ResultType::FullLineWithoutEol => RawScanResult::FullLineWithoutEol(line()),
+            ResultType::StartLongline => RawScanResult::StartLongline(line()),
+            ResultType::WithinLongline => RawScanResult::WithinLongline(line()),
+            ResultType::LonglineEnded => RawScanResult::LonglineEnded,
+            ResultType::Paused => RawScanResult::Paused,
+            ResultType::Eof => RawScanResult::Eof,
+            ResultType::Err => {
+                RawScanResult::Err(self.err.as_ref().expect("rawscan error result without error"))
+            }
+            ResultType::WouldBlock => RawScanResult::WouldBlock,
+        }
+    }
+
+    // Optimized for short lines in long buffer.
+
+    #[inline]
+    fn rs_getline(&mut self) -> Span {
+        if self.p <= self.next_delim_peek && self.next_delim_peek < self.q {
+            let begin = self.p;
+            let end = self.next_delim_peek;
+
+            self.p = end + 1;
+            self.ndelims += 1;
+            self.next_delim_peek = self.find_delim(self.p);
+            return Span::line(ResultType::FullLine, begin, end);
+        }
+        self.rs_getline_morecode(self.p)
+    }
+
+    // The rest of getline(), for when "peek" can't simply return the
+    // next line.  The search for the next delimiterbyte starts at
+    // scan_from, which is p except when extend_record() has already
+    // scanned [p, scan_from), and knows it to hold whole lines.
+
+    fn rs_getline_morecode(&mut self, scan_from: usize) -> Span {
+        let mut start_next_scan_here;
+
+        if self.in_longline {
+            // finish off two-step longline termination
+            if self.longline_ended {
+                return self.rawscan_handle_end_of_longline();
+            }
+            start_next_scan_here = self.bufsz;
+        } else {
+            // Disables "peek".  Only successful fast_loop re-enables.
+            self.next_delim_peek = self.bufsz;
+
+            start_next_scan_here = scan_from;
+
+            // fast_loop: fastpath the two common cases, where
+            // performance counts most:
+            //      1) We have a full line in buffer, ready to return.
+            //      2) We have a partial line, and room in buffer to read more.
+
+            loop {
+                let next_delim = self.find_end(start_next_scan_here);
+
+                if self.p < self.q {
+                    if next_delim < self.q {
+                        let begin = self.p;
+                        self.count_delims(next_delim);
+                        self.p = next_delim + 1;
+
+                        // If there is another delimiter between p and q,
+                        // then the next line will re-enable above "peek" code.
+                        // Not in paragraph mode, where "peek" would find lines.
+                        if !self.paragraph_mode {
+                            self.next_delim_peek = self.find_delim(self.p);
+                        }
+                        return Span::line(ResultType::FullLine, begin, next_delim);
+                    } else if self.q < self.bufsz {
+                        // have space above q: read more and try again
+                        match self.rawscan_read() {
+                            Some(start) => {
+                                start_next_scan_here = start;
+                                continue;
+                            }
+                            None => {
+                                start_next_scan_here = self.bufsz;
+                                break;
+                            }
+                        }
+                    }
+                }
+                break; // fall into the slow loop ...
+            }
+        }
+
+        // The slow loop, to handle all the rare or corner cases.
+        // Now pedantic exhaustive clarity matters more than speed.
+
+        loop {
+            if self.would_block {               // no input ready: come back later
+                self.would_block = false;
+                return Span::bare(ResultType::WouldBlock);
+            }
+            debug_assert!(start_next_scan_here <= self.bufsz);
+
+            let next_delim = self.find_end(start_next_scan_here);
+            debug_assert!(next_delim >= self.p);
+            let len = self.q - self.p;
+
+            if next_delim < self.q {                    // got delimiter in [p, q)
+                self.end_this_chunk = next_delim;
+                self.next_val_p = next_delim + 1;
+                if self.in_longline {
+                    return self.rawscan_handle_end_of_longline();
+                } else {
+                    return self.rawscan_full_line();
+                }
+            } else if self.eof_seen || self.err.is_some() { // end of input seen
+                if len > 0 {                            // have more bytes in buf
+                    // We know we have buffer space above q because we've
+                    // seen the end of the input, which only happens after
+                    // a call to rawscan_read() has tried, but failed,
+                    // to read more bytes into some empty space above q.
+                    debug_assert!(self.q < self.bufsz);
+                    self.end_this_chunk = self.q - 1;
+                    self.next_val_p = self.q;
+                    if self.in_longline {
+                        return self.rawscan_handle_end_of_longline();
+                    } else {
+                        return self.rawscan_full_line();
+                    }
+                } else if self.in_longline {
+                    self.longline_ended = true;
+                    return self.rawscan_handle_end_of_longline();
+                } else if self.eof_seen {
+                    return self.rawscan_eof();
+                } else {
+                    return Span::bare(ResultType::Err);
+                }
+            } else if self.q < self.bufsz {
+                start_next_scan_here = self.rawscan_read().unwrap_or(self.bufsz);
+            } else if len >= self.min1stchunklen && !self.in_longline {
+                self.end_this_chunk = self.q - 1;
+                self.next_val_p = self.q;
+                return self.rawscan_start_of_longline();
+            } else if len > 0 {                         // have more bytes in buf
+                debug_assert!(len < self.min1stchunklen || self.in_longline);
+                if self.p > 0 {                         // have space below p
+                    if self.pause_on_inval && !self.terminate_current_pause {
+                        return self.rawscan_paused();
+                    }
+                    self.rawscan_shift_buffer_contents_down();
+                    start_next_scan_here = self.bufsz;
+                    self.terminate_current_pause = false;   // reset pause logic
+                } else {
+                    // Buffer is stuffed with one chunk of a long line.
+
+                    debug_assert!(self.q == self.bufsz);
+                    debug_assert!(self.in_longline);
+
+                    self.end_this_chunk = self.q - 1;
+                    self.next_val_p = self.q;
+
+                    return self.rawscan_within_longline();
+                }
+            } else {
+                // Buffer is stuffed with already returned lines.  Time to
+                // reset buffers and read some more, or pause awaiting a resume.
+
+                if self.pause_on_inval && !self.terminate_current_pause {
+                    return self.rawscan_paused();
+                }
+                self.p = 0;                             // reset buffers
+                self.q = 0;
+                self.terminate_current_pause = false;   // reset pause logic
+                start_next_scan_here = self.rawscan_read().unwrap_or(self.bufsz);
+            }
+        }
+    }
+
+    // Private helper routines used by rs_getline_morecode():
+
+    // Index of the first delimiterbyte in [start, q), else some index
+    // at or above q, standing in for where rawmemchr() would have run
+    // into the sentinel.
+
+    #[inline]
+    fn find_delim(&self, start: usize) -> usize {
+        if start >= self.q {
+            return self.bufsz;
+        }
+        match memchr(self.delimiterbyte, &self.buf.as_ref()[start..self.q]) {
+            Some(i) => start + i,
+            None => self.bufsz,
+        }
+    }
+
+    // In paragraph mode, the second delimiterbyte of the first pair of
+    // them ending at or above start, else some index at or above q.
+    // As in the C rawscan_find_paragraph_end(), we check below each
+    // delimiterbyte found for the first of the pair, and first skip
+    // delimiterbytes at the start of a paragraph by moving p past them.
+
+    fn find_end(&mut self, start: usize) -> usize {
+        if !self.paragraph_mode {
+            return self.find_delim(start);
+        }
+
+        let delim = self.delimiterbyte;
+        let mut d = start;
+
+        if !self.in_longline {
+            let buf = self.buf.as_ref();
+            while self.p < self.q && buf[self.p] == delim {
+                self.p += 1;
+                self.ndelims += 1;
+            }
+            d = d.max(self.p);
+        }
+        loop {
+            d = self.find_delim(d);
+            if d >= self.q {
+                return d;
+            }
+            let paired = if d > self.p {
+                self.buf.as_ref()[d - 1] == delim
+            } else {
+                self.chunk_ended_in_delim
+            };
+            if paired {
+                return d;
+            }
+            d += 1;
+        }
+    }
+
+    // Count the delimiterbytes in the line or chunk [p, end] about to
+    // be returned, less the first counted_len bytes, already counted.
+    // Past those, lines and chunks hold at most one delimiterbyte, at
+    // the end, except in paragraph mode.
+
+    fn count_delims(&mut self, end: usize) {
+        let from = self.p + self.counted_len;
+
+        self.counted_len = 0;
+        if from > end {
+            return;
+        }
+        if !self.paragraph_mode {
+            if self.buf.as_ref()[end] == self.delimiterbyte {
+                self.ndelims += 1;
+            }
+            return;
+        }
+        self.ndelims += self.count_delims_in(from, end + 1);
+    }
+
+    fn rawscan_full_line(&mut self) -> Span {
+        // The "normal" case - return another full line all at once.
+        // The line to return is [p, end_this_chunk].
+        // buf[end_this_chunk] is either a delimiterbyte or
+        //    else we're at end of file and it's the last byte.
+
+        debug_assert!(self.p <= self.end_this_chunk);
+        debug_assert!(self.next_val_p <= self.q);
+        debug_assert!(self.end_this_chunk < self.q);
+
+        // In paragraph mode, only a pair of delimiterbytes ends a full
+        // paragraph; one lone delimiterbyte at the end of input doesn't.
+        let buf = self.buf.as_ref();
+        let end = self.end_this_chunk;
+        let kind = if buf[end] == self.delimiterbyte
+            && (!self.paragraph_mode || (end > self.p && buf[end - 1] == self.delimiterbyte))
+        {
+            ResultType::FullLine
+        } else {
+            ResultType::FullLineWithoutEol
+        };
+        let span = Span::line(kind, self.p, self.end_this_chunk);
+        self.count_delims(self.end_this_chunk);
+
+        self.p = self.next_val_p;
+
+        span
+    }
+
+    fn rawscan_eof(&mut self) -> Span {
+        self.eof_seen = true;
+
+        Span::bare(ResultType::Eof)
+    }
+
+    fn rawscan_paused(&mut self) -> Span {
+        self.paused = true;
+
+        Span::bare(ResultType::Paused)
+    }
+
+    fn rawscan_read(&mut self) -> Option<usize> {
+        let pre_read_q = self.q;
+
+        let result = loop {
+            let result = self.reader.read(&mut self.buf.as_mut()[pre_read_q..]);
+            match (&result, self.follow) {
+                (Ok(0), Some(interval)) => thread::sleep(interval), // follow mode: wait for more
+                (Err(e), _) if e.kind() == io::ErrorKind::Interrupted => {}
+                _ => break result,
+            }
+        };
+
+        match result {
+            Ok(0) => {
+                self.eof_seen = true;
+                None
+            }
+            Ok(cnt) => {
+                self.q += cnt;
+                self.q_offset += cnt as u64;
+                Some(pre_read_q)        // returns to start_next_scan_here
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                self.would_block = true;        // not an error: try again later
+                None
+            }
+            Err(e) => {
+                self.err = Some(e);
+                None
+            }
+        }
+    }
+
+    fn rawscan_start_of_longline(&mut self) -> Span {
+        debug_assert!(!self.in_longline);
+        debug_assert!(!self.longline_ended);
+        debug_assert!(self.q == self.bufsz);
+
+        let span = Span::line(ResultType::StartLongline, self.p, self.end_this_chunk);
+        self.chunk_ended_in_delim = self.buf.as_ref()[self.end_this_chunk] == self.delimiterbyte;
+        self.longline_offset = self.offset_of(self.p);
+        self.count_delims(self.end_this_chunk);
+
+        self.p = self.q;
+        self.in_longline = true;
+        self.longline_ended = false;
+
+        span
+    }
+
+    fn rawscan_within_longline(&mut self) -> Span {
+        debug_assert!(self.in_longline);
+        // self.longline_ended might be true or false
+
+        debug_assert!(self.p <= self.end_this_chunk);   // non-empty return chunk
+
+        let span = Span::line(ResultType::WithinLongline, self.p, self.end_this_chunk);
+        self.chunk_ended_in_delim = self.buf.as_ref()[self.end_this_chunk] == self.delimiterbyte;
+        self.count_delims(self.end_this_chunk);
+        self.p = self.next_val_p;
+
+        span
+    }
+
+    fn rawscan_terminate_longline(&mut self) -> Span {
+        // As in the C code, we never both (1) return another chunk of
+        // a longline, and (2) tell the caller that this is the end of
+        // a longline, in the same response.  This routine handles the
+        // latter, after rawscan_handle_end_of_longline() has sequenced
+        // the return of any final chunk.
+
+        debug_assert!(self.in_longline);
+        debug_assert!(self.longline_ended);
+
+        self.in_longline = false;
+        self.longline_ended = false;
+
+        Span::bare(ResultType::LonglineEnded)
+    }
+
+    fn rawscan_shift_buffer_contents_down(&mut self) {
+        // Here we're shifting down a small (less than min1stchunklen)
+        // segment just enough to be able to get a full min1stchunklen
+        // length segment in the upper end of the buffer.
+
+        debug_assert!(!self.in_longline);
+        debug_assert!(self.q == self.bufsz);
+
+        let howmuchtoshift = self.q - self.p;
+        debug_assert!(howmuchtoshift > 0);
+        debug_assert!(howmuchtoshift < self.min1stchunklen);
+
+        let howfartoshift = self.p - (self.bufsz - self.min1stchunklen);
+        debug_assert!(howfartoshift > 0);
+
+        let new_p = self.p - howfartoshift;
+        self.buf.as_mut().copy_within(self.p..self.q, new_p);
+
+        self.p = new_p;
+        self.q -= howfartoshift;
+    }
+
+    fn rawscan_handle_end_of_longline(&mut self) -> Span {
+        // If we come upon the end of a longline, we might have one
+        // more chunk of that longline to return to the caller, before
+        // telling them in a separate response that the longline ended,
+        // or we might not have any more such data and need to
+        // immediately tell the caller that the longline terminated.
+        // Setting "longline_ended = true" below gets us back here, via
+        // the top of rs_getline_morecode(), for the second step.
+
+        if !self.longline_ended {
+            self.longline_ended = true;
+            self.rawscan_within_longline()
+        } else {
+            self.rawscan_terminate_longline()
+        }
+    }
+}
+
+impl<R, B> RawScan<R, B> {
+    /// Pause, rather than invalidate already returned lines, whenever
+    /// `getline()` needs to reuse buffer space.  See the C
+    /// `rs_enable_pause()`.
+    ///
+    /// Since every returned line borrows the stream, the compiler
+    /// already keeps callers from using a line that might have been
+    /// overwritten; pausing remains available for callers that want to
+    /// know when the buffer is about to be recycled.
+    pub fn enable_pause(&mut self) {
+        self.pause_on_inval = true;
+    }
+
+    /// Stop pausing.  See the C `rs_disable_pause()`.
+    pub fn disable_pause(&mut self) {
+        self.pause_on_inval = false;
+        self.terminate_current_pause = false;
+        self.paused = false;
+    }
+
+    /// Let the next `getline()` proceed past a pause.  See the C
+    /// `rs_resume_from_pause()`.
+    pub fn resume_from_pause(&mut self) {
+        self.terminate_current_pause = true;
+        self.paused = false;
+    }
+
+    /// Set the guaranteed minimum length of full lines and of the
+    /// first chunk of long lines.
+    ///
+    /// Fails, changing nothing, if `min1stchunklen` is zero or is
+    /// greater than the buffer size.  See the long comment above the C
+    /// `rs_set_min1stchunklen()` for why one would use this.
+    pub fn set_min1stchunklen(&mut self, min1stchunklen: usize) -> io::Result<()> {
+        if min1stchunklen == 0 || min1stchunklen > self.bufsz {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "min1stchunklen not in [1, rawscan buffer size]",
+            ));
+        }
+
+        self.min1stchunklen = min1stchunklen;
+        Ok(())
+    }
+
+    /// The current min1stchunklen, which defaults to the buffer size.
+    pub fn min1stchunklen(&self) -> usize {
+        self.min1stchunklen
+    }
+
+    /// The number of bytes read into the buffer, but not yet returned.
+    /// See the C `rs_get_buffered_bytes()`.
+    pub fn buffered_bytes(&self) -> usize {
+        self.q - self.p
+    }
+
+    /// The size of the buffer.
+    pub fn bufsz(&self) -> usize {
+        self.bufsz
+    }
+
+    /// Whether end of input has been seen, so that nothing more will
+    /// be read into the buffer, though lines may remain to be returned.
+    pub fn eof_seen(&self) -> bool {
+        self.eof_seen
+    }
+
+    /// Whether a read error has been seen, so that nothing more will
+    /// be read into the buffer.
+    pub fn err_seen(&self) -> bool {
+        self.err.is_some()
+    }
+
+    // Take the stashed read error, to hand on, as is, to a caller that's
+    // done with this stream.  getline() must not be called after.
+
+    pub(crate) fn take_err(&mut self) -> Option<io::Error> {
+        self.err.take()
+    }
+
+    /// Whether `getline()` has returned the `StartLongline` first chunk
+    /// of a long line, but not yet its `LonglineEnded`.
+    pub fn in_longline(&self) -> bool {
+        self.in_longline
+    }
+
+    /// Whether `getline()` has returned `Paused`, and the caller has
+    /// not yet called `resume_from_pause()` or `disable_pause()`.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// The delimiterbyte that ends each line.
+    pub fn delimiterbyte(&self) -> u8 {
+        self.delimiterbyte
+    }
+
+    /// End lines at `delimiterbyte` from here on, keeping whatever is
+    /// already buffered.  See the C `rs_set_delimiterbyte()`.
+    ///
+    /// If in the middle of returning a long line, the next new
+    /// delimiterbyte ends that long line.
+    pub fn set_delimiterbyte(&mut self, delimiterbyte: u8) {
+        self.delimiterbyte = delimiterbyte;
+        self.next_delim_peek = self.bufsz;      // disable "peek"
+    }
+
+    /// Return paragraphs, ending at two consecutive delimiterbytes, a
+    /// blank line for newline delimiters, rather than lines, from here
+    /// on.  See the C `rs_set_paragraph_mode()`.
+    ///
+    /// Each paragraph comes with the pair of delimiterbytes that ended
+    /// it, as a `FullLine`, or as a `FullLineWithoutEol` at the end of
+    /// input.  Further delimiterbytes between paragraphs are skipped,
+    /// as awk does with `RS=""`.  Paragraphs too long for the buffer
+    /// come in chunks, as long lines do.
+    ///
+    /// ```
+    /// use rawscan::{RawScan, RawScanResult};
+    ///
+    /// let mut rs = RawScan::new(&b"From: a\nTo: b\n\n\nbody\n"[..], 64, b'\n');
+    /// rs.set_paragraph_mode(true);
+    /// assert!(matches!(rs.getline(), RawScanResult::FullLine(b"From: a\nTo: b\n\n")));
+    /// assert!(matches!(rs.getline(), RawScanResult::FullLineWithoutEol(b"body\n")));
+    /// ```
+    pub fn set_paragraph_mode(&mut self, paragraph_mode: bool) {
+        self.paragraph_mode = paragraph_mode;
+        self.next_delim_peek = self.bufsz;      // disable "peek"
+    }
+
+    /// Whether paragraph mode is on.
+    pub fn paragraph_mode(&self) -> bool {
+        self.paragraph_mode
+    }
+
+    /// Follow the input, as `tail -f` does: on reaching the end of the
+    /// input, sleep for `interval`, then try reading again, for as long
+    /// as it takes for more input to arrive.  So `getline()` never
+    /// returns `Eof`, and holds back a partial last line until its
+    /// delimiterbyte arrives.  `None` turns following back off.  See
+    /// the C `rs_set_follow()`.
+    ///
+    /// Turning following on after end of input was seen forgets that,
+    /// so the next `getline()` tries reading again.  A reader whose
+    /// every read returns 0 is retried forever, so to stop following
+    /// part way through a `getline()`, have the reader return an error.
+    pub fn set_follow(&mut self, interval: Option<Duration>) {
+        self.follow = interval;
+        if interval.is_some() {
+            self.eof_seen = false;
+        }
+    }
+
+    /// The follow interval, if following.
+    pub fn follow(&self) -> Option<Duration> {
+        self.follow
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the underlying reader, through which
+    /// it may even be replaced, as the C `rs_set_read_fn()` replaces
+    /// the input routine of a stream.  Data already buffered is kept.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Unwraps this stream, returning the underlying reader.
+    ///
+    /// Any data buffered but not yet returned is lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R, B: AsRef<[u8]>> RawScan<R, B> {
+    /// The input byte offset, from 0, of the first byte of the line or
+    /// chunk last returned.  After a result without a line, such as
+    /// `LonglineEnded` or `Eof`, or after `unget()`, the offset of the
+    /// next byte to be returned.  See the C `rs_get_line_offset()`.
+    pub fn line_offset(&self) -> u64 {
+        match self.last_line() {
+            Some(span) => self.offset_of(span.begin),
+            None => self.offset_of(self.p),
+        }
+    }
+
+    /// For a `WithinLongline` chunk, the offset of its first byte from
+    /// the start of its long line, else 0.
+    pub fn chunk_offset(&self) -> u64 {
+        match self.last_line() {
+            Some(span) if span.kind == ResultType::WithinLongline => {
+                self.offset_of(span.begin) - self.longline_offset
+            }
+            _ => 0,
+        }
+    }
+
+    /// The line number, from 1, of the line or chunk last returned, or
+    /// after a result without a line, of the next line.  In paragraph
+    /// mode, the line number of the first line of the paragraph.  See
+    /// the C `rs_get_line_number()`.
+    ///
+    /// ```
+    /// use rawscan::RawScan;
+    ///
+    /// let mut rs = RawScan::new(&b"one\ntwo\nthree\n"[..], 64, b'\n');
+    /// rs.getline();
+    /// rs.getline();
+    /// assert_eq!((rs.line_number(), rs.line_offset()), (2, 4));
+    /// ```
+    pub fn line_number(&self) -> u64 {
+        match self.last_line() {
+            Some(span) => self.ndelims + 1 - self.count_delims_in(span.begin, self.p),
+            None => self.ndelims + 1,
+        }
+    }
+
+    /// The number of delimiterbytes in the input before the next byte
+    /// to be returned, which is to say, the number of whole lines
+    /// returned so far.
+    pub fn delimiter_count(&self) -> u64 {
+        self.ndelims
+    }
+
+    /// The input byte offset of the next byte to be returned, having
+    /// read, but not yet returned, everything before it, to pass to
+    /// [`seek()`] later to resume just here.  See the C `rs_tell()`.
+    ///
+    /// [`seek()`]: RawScan::seek
+    pub fn tell(&self) -> u64 {
+        self.offset_of(self.p)
+    }
+
+    // The last result, if it was a line or chunk.
+
+    fn last_line(&self) -> Option<Span> {
+        match self.last {
+            Some(span) => match span.kind {
+                ResultType::FullLine |
+                ResultType::FullLineWithoutEol |
+                ResultType::StartLongline |
+                ResultType::WithinLongline => Some(span),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    // The input byte offset of buf[i], for i in [p, q].
+
+    fn offset_of(&self, i: usize) -> u64 {
+        self.q_offset - (self.q - i) as u64
+    }
+
+    fn count_delims_in(&self, from: usize, to: usize) -> u64 {
+        memchr::memchr_iter(self.delimiterbyte, &self.buf.as_ref()[from..to]).count() as u64
+    }
+}
+
+impl<R: Seek, B> RawScan<R, B> {
+    /// Seek the reader to `offset` bytes from its start, and discard
+    /// the buffer, so that the next `getline()` reads from there, as
+    /// if the stream had just been opened at that offset.  Returns the
+    /// new offset.  See the C `rs_seek()`.
+    ///
+    /// Any long line, end of input or read error seen so far is
+    /// forgotten, and any pause is over.  From here on, offsets from
+    /// [`tell()`] and [`line_offset()`] are offsets from the start of
+    /// the reader, but [`line_number()`] starts over at 1, as the
+    /// lines before `offset` are never read.
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use rawscan::{RawScan, RawScanResult};
+    ///
+    /// let mut rs = RawScan::new(Cursor::new(&b"one\ntwo\nthree\n"[..]), 64, b'\n');
+    /// rs.getline();
+    /// let resume_at = rs.tell();
+    /// rs.getline();
+    /// rs.seek(resume_at).unwrap();
+    /// assert!(matches!(rs.getline(), RawScanResult::FullLine(b"two\n")));
+    /// assert_eq!((rs.line_offset(), rs.line_number()), (4, 1));
+    /// ```
+    ///
+    /// Fails, changing nothing, if the reader fails to seek.
+    ///
+    /// [`tell()`]: RawScan::tell
+    /// [`line_offset()`]: RawScan::line_offset
+    /// [`line_number()`]: RawScan::line_number
+    pub fn seek(&mut self, offset: u64) -> io::Result<u64> {
+        let offset = self.reader.seek(SeekFrom::Start(offset))?;
+
+        // An empty buffer, with room to read, at the bottom.
+        self.p = 0;
+        self.q = 0;
+        self.q_offset = offset;
+        self.end_this_chunk = 0;
+        self.next_val_p = 0;
+        self.next_delim_peek = self.bufsz;      // disable "peek"
+        self.err = None;
+        self.last = None;
+        self.longline_offset = 0;
+        self.ndelims = 0;
+        self.counted_len = 0;
+        self.in_longline = false;
+        self.terminate_current_pause = false;
+        self.paused = false;
+        self.longline_ended = false;
+        self.eof_seen = false;
+        self.chunk_ended_in_delim = false;
+
+        Ok(offset)
+    }
+}
+
+// std::io::BufRead maps directly onto the [p, q) window of not yet
+// returned bytes: fill_buf() hands out that window, refilling the
+// whole buffer from the bottom only once it is empty, and consume()
+// advances p.  So read_until(), split(), lines() and friends run
+// straight off the rawscan buffer.
+//
+// Errors from BufRead reads are returned as is, rather than latched
+// as getline() latches them, so that callers such as read_until()
+// can retry on ErrorKind::Interrupted, as they expect to.
+//
+// Mixing the two interfaces on one stream works, with one wrinkle:
+// refilling the buffer through BufRead forgets any long line that
+// getline() was in the middle of returning, so the rest of that line
+// goes to the BufRead caller, and getline() won't report its end.
+
+impl<R: Read, B: AsRef<[u8]> + AsMut<[u8]>> BufRead for RawScan<R, B> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.p >= self.q {
+            if let Some(e) = &self.err {
+                return Err(match e.raw_os_error() {
+                    Some(errnum) => io::Error::from_raw_os_error(errnum),
+                    None => io::Error::new(e.kind(), e.to_string()),
+                });
+            }
+            if !self.eof_seen {
+                self.p = 0;                         // reset buffers
+                self.q = 0;
+                self.next_delim_peek = self.bufsz;
+                self.in_longline = false;
+                self.longline_ended = false;
+                self.last = None;
+                let mut result = self.reader.read(self.buf.as_mut());
+                while let (Ok(0), Some(interval)) = (&result, self.follow) {
+                    thread::sleep(interval);
+                    result = self.reader.read(self.buf.as_mut());
+                }
+                match result {
+                    Ok(0) => self.eof_seen = true,
+                    Ok(cnt) => {
+                        self.q = cnt;
+                        self.q_offset += cnt as u64;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(&self.buf.as_ref()[self.p..self.q])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let new_p = self.q.min(self.p + amt);
+
+        self.last = None;
+        self.ndelims += self.count_delims_in(self.p, new_p);
+        self.counted_len = 0;
+        self.p = new_p;
+    }
+}
+
+impl<R: Read, B: AsRef<[u8]> + AsMut<[u8]>> Read for RawScan<R, B> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let avail = self.fill_buf()?;
+        let n = avail.len().min(out.len());
+
+        out[..n].copy_from_slice(&avail[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
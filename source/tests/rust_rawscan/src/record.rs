@@ -0,0 +1,246 @@
+// RecordScanner, grouping lines into multiline records, such as stack
+// traces, or log entries with indented continuation lines, using the
+// RawScan extend_record() and unget() routines.
+//
+// Whether a line continues the current record is up to the caller's
+// is_continuation() predicate, which can only be asked once the line
+// has been appended to the record.  Lines that turn out to start the
+// next record are handed back with unget(), to be scanned again.
+//
+// Records that fit in the buffer are returned whole.  Records that
+// don't are returned in chunks, under the same contract as long lines:
+// a StartLongline chunk, any number of WithinLongline chunks, then
+// LonglineEnded, with the chunks adding up to the whole record.  The
+// lines after the first chunk are read by plain getline() calls, each
+// complete line or first chunk of a line that continues the record
+// being passed along as a WithinLongline chunk.
+
+use std::io::Read;
+
+use crate::reader::Span;
+use crate::{RawScan, RawScanResult, ResultType};
+
+/// Reads multiline records from a [`RawScan`], each record being a
+/// line followed by all the lines after it that `is_continuation()`
+/// accepts.
+///
+/// `is_continuation()` is passed each line after the first in a
+/// record, with its delimiterbyte, or else just the first chunk of
+/// the line, if that line doesn't fit in the buffer along with the
+/// rest of the record.
+///
+/// [`getrecord()`] returns each record as a [`FullLine`] (or as a
+/// [`FullLineWithoutEol`] at the end of input), so long as it fits
+/// in the buffer.  Longer records are returned in chunks, just as
+/// [`RawScan::getline`] returns long lines.
+///
+/// ```
+/// use rawscan::{RawScan, RawScanResult, RecordScanner};
+///
+/// let input = b"ERROR boom\n  at f()\n  at main()\nINFO fine\n";
+/// let rs = RawScan::new(&input[..], 64, b'\n');
+/// let mut records = RecordScanner::new(rs, |line: &[u8]| line.starts_with(b" "));
+///
+/// assert!(matches!(records.getrecord(),
+///     RawScanResult::FullLine(b"ERROR boom\n  at f()\n  at main()\n")));
+/// assert!(matches!(records.getrecord(), RawScanResult::FullLine(b"INFO fine\n")));
+/// assert!(matches!(records.getrecord(), RawScanResult::Eof));
+/// ```
+///
+/// [`getrecord()`]: RecordScanner::getrecord
+/// [`FullLine`]: RawScanResult::FullLine
+/// [`FullLineWithoutEol`]: RawScanResult::FullLineWithoutEol
+pub struct RecordScanner<R, F, B = Box<[u8]>> {
+    rs: RawScan<R, B>,
+    is_continuation: F,
+
+    extending: bool,        // paused (or would block) while extending record
+    in_long_record: bool,   // returned first chunk of record, not yet end
+    line_start_pending: bool, // next chunk starts a line, to be judged
+}
+
+impl<R, F, B> RecordScanner<R, F, B>
+where
+    R: Read,
+    B: AsRef<[u8]> + AsMut<[u8]>,
+    F: FnMut(&[u8]) -> bool,
+{
+    /// Read records from `rs`, continuing each record with the lines
+    /// after it for which `is_continuation()` returns true.
+    pub fn new(rs: RawScan<R, B>, is_continuation: F) -> RecordScanner<R, F, B> {
+        RecordScanner {
+            rs,
+            is_continuation,
+            extending: false,
+            in_long_record: false,
+            line_start_pending: false,
+        }
+    }
+
+    /// Return the next record, or chunk of a long record.
+    ///
+    /// Results other than records and chunks, such as [`Eof`] or
+    /// [`Paused`], are passed along from the underlying `getline()`.
+    /// After a `Paused`, call `resume_from_pause()` on the underlying
+    /// stream, through [`get_mut()`], then call `getrecord()` again.
+    /// After a [`WouldBlock`], call `getrecord()` again once there's
+    /// more input, to carry on with the record where it left off.
+    ///
+    /// [`Eof`]: RawScanResult::Eof
+    /// [`Paused`]: RawScanResult::Paused
+    /// [`WouldBlock`]: RawScanResult::WouldBlock
+    /// [`get_mut()`]: RecordScanner::get_mut
+    pub fn getrecord(&mut self) -> RawScanResult<'_> {
+        let span = if self.in_long_record {
+            self.long_record_span()
+        } else if self.extending {
+            self.extending = false;
+            let record = self.rs.last_span().expect("paused record lost");
+            self.extend_span(record)
+        } else {
+            let first = self.rs.getline_span();
+            match first.kind {
+                ResultType::FullLine => self.extend_span(first),
+                ResultType::StartLongline => {
+                    self.in_long_record = true;
+                    self.line_start_pending = false;
+                    first
+                }
+                _ => first,
+            }
+        };
+        self.rs.to_result(span)
+    }
+
+    // Append lines to the record until one doesn't continue it, the
+    // input ends, or the record no longer fits in the buffer.
+
+    fn extend_span(&mut self, mut record: Span) -> Span {
+        loop {
+            let len = record.len();
+            let ext = self.rs.rs_extend_record();
+
+            match ext.kind {
+                ResultType::FullLine | ResultType::FullLineWithoutEol => {
+                    if ext.len() == len {
+                        return ext;                 // input ended
+                    }
+                    if !self.continues(Span::line(ext.kind, ext.begin + len, ext.end)) {
+                        return self.end_record_before(ext, len);
+                    }
+                    if ext.kind == ResultType::FullLineWithoutEol {
+                        return ext;
+                    }
+                    record = ext;
+                }
+                ResultType::StartLongline => {
+                    // The record no longer fits.  If we have some of
+                    // the appended line, judge it now, as there will
+                    // be no going back once this first chunk is out.
+                    // If we have none of it, judge its first chunk.
+                    if ext.len() > len {
+                        if !self.continues(Span::line(ext.kind, ext.begin + len, ext.end)) {
+                            return self.end_record_before(ext, len);
+                        }
+                        self.line_start_pending = false;
+                    } else {
+                        self.line_start_pending = true;
+                    }
+                    self.in_long_record = true;
+                    return ext;
+                }
+                ResultType::Paused | ResultType::WouldBlock => {
+                    self.extending = true;
+                    return ext;
+                }
+                _ => return ext,
+            }
+        }
+    }
+
+    // Return the rest of a record too long for the buffer.
+
+    fn long_record_span(&mut self) -> Span {
+        loop {
+            let span = self.rs.getline_span();
+
+            match span.kind {
+                ResultType::WithinLongline if self.line_start_pending => {
+                    if !self.continues(span) {
+                        return self.end_long_record_before();
+                    }
+                    self.line_start_pending = false;
+                    return span;
+                }
+                ResultType::WithinLongline => return span,
+                ResultType::LonglineEnded if self.line_start_pending => {
+                    self.in_long_record = false;
+                    return span;
+                }
+                ResultType::LonglineEnded => {
+                    // End of a line in the record; judge the next one.
+                    self.line_start_pending = true;
+                }
+                ResultType::FullLine |
+                ResultType::FullLineWithoutEol |
+                ResultType::StartLongline => {
+                    if !self.continues(span) {
+                        return self.end_long_record_before();
+                    }
+                    self.line_start_pending = span.kind != ResultType::StartLongline;
+                    return Span::line(ResultType::WithinLongline, span.begin, span.end);
+                }
+                ResultType::Paused | ResultType::WouldBlock => return span,
+                ResultType::Eof | ResultType::Err => {
+                    // Eof and Err are sticky, so the next getrecord()
+                    // call will see them again.
+                    self.in_long_record = false;
+                    return Span::bare(ResultType::LonglineEnded);
+                }
+            }
+        }
+    }
+
+    fn continues(&mut self, line: Span) -> bool {
+        (self.is_continuation)(self.rs.span_bytes(line))
+    }
+
+    // Hand back the line just appended to the record, at offset len,
+    // and return the record without it.
+
+    fn end_record_before(&mut self, ext: Span, len: usize) -> Span {
+        let ok = self.rs.rs_unget(len);
+        debug_assert!(ok);
+
+        Span::line(ResultType::FullLine, ext.begin, ext.begin + len - 1)
+    }
+
+    // Hand back the line just read, which starts the next record.
+
+    fn end_long_record_before(&mut self) -> Span {
+        let ok = self.rs.rs_unget(0);
+        debug_assert!(ok);
+
+        self.in_long_record = false;
+        Span::bare(ResultType::LonglineEnded)
+    }
+}
+
+impl<R, F, B> RecordScanner<R, F, B> {
+    /// Gets a reference to the underlying stream.
+    pub fn get_ref(&self) -> &RawScan<R, B> {
+        &self.rs
+    }
+
+    /// Gets a mutable reference to the underlying stream, as to
+    /// resume from a pause.  Reading lines directly from it, other
+    /// than between records, will confuse the record grouping.
+    pub fn get_mut(&mut self) -> &mut RawScan<R, B> {
+        &mut self.rs
+    }
+
+    /// Unwraps this scanner, returning the underlying stream.
+    pub fn into_inner(self) -> RawScan<R, B> {
+        self.rs
+    }
+}
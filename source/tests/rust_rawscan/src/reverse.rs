@@ -0,0 +1,280 @@
+// ReverseScan, returning the lines of a seekable input last line
+// first, as for tac(1), or for tail(1) -n on a file too big to read
+// through from the start.
+//
+// It's a RawScan run backward.  The buffer is filled from the top
+// down, each read taking the bufsz (or fewer) bytes just before those
+// already read, after shifting any partial line still unreturned up
+// to the top of the buffer.  Lines are found with a reverse memchr,
+// memrchr(), looking for the delimiterbyte that ends the line before.
+//
+// Long lines are chunked, as forward, but last chunk first: the
+// StartLongline chunk holds the end of the line, each WithinLongline
+// chunk the bytes just before the chunk before it, then LonglineEnded
+// once the start of the line is reached.  The chunks, reversed, add
+// up to the line.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use memchr::memrchr;
+
+use crate::reader::Span;
+use crate::{RawScanResult, ResultType};
+
+/// Reads the lines of a seekable input, such as a file, from the last
+/// line to the first, reading the input backward, a buffer at a time.
+///
+/// `getline()` returns the same kinds of results as
+/// [`RawScan::getline`], each line with its delimiterbyte, the last
+/// line as a [`FullLineWithoutEol`] if the input doesn't end with a
+/// delimiterbyte.  Lines shorter than the buffer are returned whole.
+/// Longer lines are returned in chunks, from the end of the line
+/// back, so that the first chunk, the [`StartLongline`], holds the
+/// end of the line.
+///
+/// The end of the input is found once, on the first `getline()`, so
+/// the input must not shrink while it's being scanned.  If it does,
+/// `getline()` returns an [`Err`] saying the input shrank.
+///
+/// ```
+/// use std::io::Cursor;
+/// use rawscan::{RawScanResult, ReverseScan};
+///
+/// let mut rs = ReverseScan::new(Cursor::new(b"one\ntwo\nthree"), 64, b'\n');
+/// assert!(matches!(rs.getline(), RawScanResult::FullLineWithoutEol(b"three")));
+/// assert!(matches!(rs.getline(), RawScanResult::FullLine(b"two\n")));
+/// assert_eq!(rs.line_offset(), 4);
+/// assert!(matches!(rs.getline(), RawScanResult::FullLine(b"one\n")));
+/// assert!(matches!(rs.getline(), RawScanResult::Eof));
+/// ```
+///
+/// [`RawScan::getline`]: crate::RawScan::getline
+/// [`FullLineWithoutEol`]: RawScanResult::FullLineWithoutEol
+/// [`StartLongline`]: RawScanResult::StartLongline
+/// [`Err`]: RawScanResult::Err
+pub struct ReverseScan<R> {
+    reader: R,
+    buf: Box<[u8]>,
+
+    // [p, q) not yet returned bytes in buf, from input offset lo on.
+    // Lines are returned from q down.
+
+    p: usize,
+    q: usize,
+    lo: u64,
+    delimiterbyte: u8,
+
+    started: bool,          // found the end of the input yet
+    in_longline: bool,      // returned first (last) chunk, not yet LonglineEnded
+    last_offset: u64,       // input offset of line or chunk last returned
+    err: Option<io::Error>, // read or seek failed
+}
+
+impl<R: Read + Seek> ReverseScan<R> {
+    /// Scan `reader` backward from its end, using a `bufsz` byte
+    /// buffer, and ending lines at each `delimiterbyte`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bufsz` is zero.
+    pub fn new(reader: R, bufsz: usize, delimiterbyte: u8) -> ReverseScan<R> {
+        assert!(bufsz > 0, "rawscan buffer size must be at least one byte");
+
+        ReverseScan {
+            reader,
+            buf: vec![0u8; bufsz].into_boxed_slice(),
+            p: bufsz,
+            q: bufsz,
+            lo: 0,
+            delimiterbyte,
+            started: false,
+            in_longline: false,
+            last_offset: 0,
+            err: None,
+        }
+    }
+
+    /// Return the line before the one last returned, or the last line,
+    /// on the first call, or a chunk of a long line, or [`Eof`] once
+    /// the first line has been returned.
+    ///
+    /// [`Eof`]: RawScanResult::Eof
+    pub fn getline(&mut self) -> RawScanResult<'_> {
+        let span = self.rs_getline();
+        let line = match span.kind {
+            ResultType::FullLine
+            | ResultType::FullLineWithoutEol
+            | ResultType::StartLongline
+            | ResultType::WithinLongline => {
+                self.q = span.begin;            // returned: no longer in [p, q)
+                self.last_offset = self.lo + (span.begin - self.p) as u64;
+                &self.buf[span.begin..=span.end]
+            }
+            ResultType::LonglineEnded
+            | ResultType::Paused
+            | ResultType::Eof
+            | ResultType::Err
+            | ResultType::WouldBlock => {
+                self.last_offset = self.lo + (self.q - self.p) as u64;
+                &[][..]
+            }
+        };
+
+        match span.kind {
+            ResultType::FullLine => RawScanResult::FullLine(line),
+            ResultType::FullLineWithoutEol => RawScanResult::FullLineWithoutEol(line),
+            ResultType::StartLongline => RawScanResult::StartLongline(line),
+            ResultType::WithinLongline => RawScanResult::WithinLongline(line),
+            ResultType::LonglineEnded => RawScanResult::LonglineEnded,
+            ResultType::Err => {
+                RawScanResult::Err(self.err.as_ref().expect("rawscan error result without error"))
+            }
+            ResultType::Eof => RawScanResult::Eof,
+            ResultType::Paused | ResultType::WouldBlock => {
+                unreachable!("ReverseScan never pauses, and returns any failed read as Err")
+            }
+        }
+    }
+
+    fn rs_getline(&mut self) -> Span {
+        if self.err.is_some() {
+            return Span::bare(ResultType::Err);
+        }
+        if !self.started {
+            match self.reader.seek(SeekFrom::End(0)) {
+                Ok(len) => self.lo = len,
+                Err(e) => {
+                    self.err = Some(e);
+                    return Span::bare(ResultType::Err);
+                }
+            }
+            self.started = true;
+        }
+
+        let delim = self.delimiterbyte;
+        loop {
+            let (p, q) = (self.p, self.q);
+
+            if self.in_longline {
+                // Look for the end of the line before, which ends this one.
+                if let Some(i) = memrchr(delim, &self.buf[p..q]) {
+                    let at = p + i;
+                    if at + 1 == q {
+                        self.in_longline = false;
+                        return Span::bare(ResultType::LonglineEnded);
+                    }
+                    return Span::line(ResultType::WithinLongline, at + 1, q - 1);
+                }
+                if p < q && (self.lo == 0 || q - p == self.buf.len()) {
+                    return Span::line(ResultType::WithinLongline, p, q - 1);
+                }
+                if self.lo == 0 {
+                    self.in_longline = false;
+                    return Span::bare(ResultType::LonglineEnded);
+                }
+            } else if p < q {
+                // The line ending at q, with or without a delimiterbyte,
+                // begins just after the delimiterbyte before that.
+                let (kind, skip) = if self.buf[q - 1] == delim {
+                    (ResultType::FullLine, 1)
+                } else {
+                    (ResultType::FullLineWithoutEol, 0)
+                };
+                if let Some(i) = memrchr(delim, &self.buf[p..q - skip]) {
+                    return Span::line(kind, p + i + 1, q - 1);
+                }
+                if self.lo == 0 {
+                    return Span::line(kind, p, q - 1);
+                }
+                if q - p == self.buf.len() {
+                    self.in_longline = true;        // no room to read more
+                    return Span::line(ResultType::StartLongline, p, q - 1);
+                }
+            } else if self.lo == 0 {
+                return Span::bare(ResultType::Eof);
+            }
+
+            if let Err(e) = self.rawscan_read() {
+                self.err = Some(e);
+                return Span::bare(ResultType::Err);
+            }
+        }
+    }
+
+    // Shift the unreturned bytes up to the top of the buffer, then read
+    // as many of the bytes before them as will fit below them.
+
+    fn rawscan_read(&mut self) -> io::Result<()> {
+        let bufsz = self.buf.len();
+        let len = self.q - self.p;
+
+        self.buf.copy_within(self.p..self.q, bufsz - len);
+        self.q = bufsz;
+        self.p = bufsz - len;
+
+        let cnt = (bufsz - len).min(self.lo.min(usize::MAX as u64) as usize);
+        let from = self.lo - cnt as u64;
+        self.reader.seek(SeekFrom::Start(from))?;
+
+        let mut got = 0;
+        while got < cnt {
+            match self.reader.read(&mut self.buf[self.p - cnt + got..self.p]) {
+                Ok(0) => {
+                    // A read returned EOF before the offset found at
+                    // start: the input shrank, as when a log rotation
+                    // truncates it.
+                    let msg = format!(
+                        "input shrank to {} bytes while being read backward",
+                        from + got as u64
+                    );
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg));
+                }
+                Ok(n) => got += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.p -= cnt;
+        self.lo = from;
+        Ok(())
+    }
+}
+
+impl<R> ReverseScan<R> {
+    /// The input byte offset, from 0, of the first byte of the line or
+    /// chunk last returned.  After a result without a line, such as
+    /// `LonglineEnded` or `Eof`, the offset just past the bytes not yet
+    /// returned.  Either way, everything from this offset on has been
+    /// returned, so for `tail -n N`, seek here after the Nth line back,
+    /// and copy from there.
+    pub fn line_offset(&self) -> u64 {
+        self.last_offset
+    }
+
+    /// Whether the last chunk of a long line has been returned, but not
+    /// yet `LonglineEnded`.
+    pub fn in_longline(&self) -> bool {
+        self.in_longline
+    }
+
+    /// The size of the buffer.
+    pub fn bufsz(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// The delimiterbyte that ends each line.
+    pub fn delimiterbyte(&self) -> u8 {
+        self.delimiterbyte
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Unwraps this `ReverseScan`, returning the underlying reader, at
+    /// whatever offset the last read left it.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
@@ -0,0 +1,288 @@
+// RawScanSet, reading lines from many file descriptors at once, such
+// as the pipes from hundreds of child processes, in one thread, with
+// epoll(7) saying which of them have input ready.
+//
+// Each fd is set O_NONBLOCK and read by its own RawScan, with its own
+// buffer and delimiterbyte.  A stream whose read would block returns
+// WouldBlock, keeping any partial line in its buffer, and is then left
+// alone until epoll_wait() says it's readable again.  The fds are
+// registered edge-triggered (EPOLLET), which is only safe because a
+// stream isn't dropped from the ready queue until a read of it has
+// actually come up empty.
+//
+// Ready streams are read round robin, one getline() result from each
+// in turn, with a non-waiting epoll_wait() after each round, so that
+// a stream that keeps its pipe full can't starve the others.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::time::Duration;
+
+use crate::{RawScan, RawScanResult, ResultType};
+
+const MAX_EVENTS: usize = 64;  // epoll_wait() events to take at once
+
+struct Member {
+    rs: RawScan<File>,
+    queued: bool,           // in the ready queue
+}
+
+/// Reads lines from many file descriptors at once, in one thread, each
+/// with its own [`RawScan`] buffer and delimiterbyte, returning each
+/// line along with the id of the stream it came from, as input arrives.
+///
+/// ```
+/// use std::io::Write;
+/// use rawscan::{RawScanResult, RawScanSet};
+///
+/// let mut set = RawScanSet::new()?;
+/// let (reader, mut writer) = std::io::pipe()?;
+/// let id = set.add(reader, 4096, b'\n')?;
+///
+/// writer.write_all(b"hello\n")?;
+/// drop(writer);
+/// match set.getline(None)? {
+///     Some((from, RawScanResult::FullLine(line))) => {
+///         assert_eq!((from, line), (id, &b"hello\n"[..]));
+///     }
+///     other => panic!("{:?}", other),
+/// }
+/// assert!(matches!(set.getline(None)?, Some((_, RawScanResult::Eof))));
+/// assert!(set.is_empty());
+/// assert!(set.getline(None)?.is_none());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Linux only, as it's built on epoll(7).
+pub struct RawScanSet {
+    epfd: OwnedFd,
+    streams: Vec<Option<Member>>,   // indexed by stream id
+    nstreams: usize,                // how many of streams are Some
+    ready: VecDeque<usize>,         // ids of streams to read, in turn
+    round_left: usize,              // reads left before next epoll_wait()
+    ended: Option<usize>,           // returned Eof or Err: drop next call
+    events: Vec<libc::epoll_event>,
+}
+
+impl RawScanSet {
+    /// Make an empty set.  Fails if epoll_create1() does.
+    pub fn new() -> io::Result<RawScanSet> {
+        let epfd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if epfd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(RawScanSet {
+            epfd: unsafe { OwnedFd::from_raw_fd(epfd) },
+            streams: Vec::new(),
+            nstreams: 0,
+            ready: VecDeque::new(),
+            round_left: 0,
+            ended: None,
+            events: Vec::with_capacity(MAX_EVENTS),
+        })
+    }
+
+    /// Add `fd` to the set, to be read using a `bufsz` byte buffer,
+    /// ending lines at each `delimiterbyte`, returning its stream id.
+    ///
+    /// The set takes ownership of `fd`, closing it once the stream
+    /// ends, and sets it `O_NONBLOCK`.  That flag is shared with any
+    /// duplicates of `fd`, such as in a child process.  The ids of
+    /// streams that have ended may be reused.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bufsz` is zero.
+    pub fn add<F>(&mut self, fd: F, bufsz: usize, delimiterbyte: u8) -> io::Result<usize>
+    where
+        F: Into<OwnedFd>,
+    {
+        let fd = fd.into();
+        set_nonblocking(fd.as_raw_fd())?;
+
+        self.drop_ended();
+        let id = match self.streams.iter().position(Option::is_none) {
+            Some(id) => id,
+            None => {
+                self.streams.push(None);
+                self.streams.len() - 1
+            }
+        };
+
+        let mut event = libc::epoll_event {
+            events: (libc::EPOLLIN | libc::EPOLLET) as u32,
+            u64: id as u64,
+        };
+        let ret = unsafe {
+            libc::epoll_ctl(self.epfd.as_raw_fd(), libc::EPOLL_CTL_ADD, fd.as_raw_fd(), &mut event)
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // There may be input waiting already; the first read will tell.
+        let rs = RawScan::new(File::from(fd), bufsz, delimiterbyte);
+        self.streams[id] = Some(Member { rs, queued: true });
+        self.nstreams += 1;
+        self.ready.push_back(id);
+        Ok(id)
+    }
+
+    /// Remove stream `id` from the set, returning it, with whatever it
+    /// has buffered, or `None` if there's no such stream.
+    pub fn remove(&mut self, id: usize) -> Option<RawScan<File>> {
+        self.drop_ended();
+        let member = self.streams.get_mut(id)?.take()?;
+
+        self.deregister(&member.rs);
+        self.nstreams -= 1;
+        if member.queued {
+            self.ready.retain(|&ready| ready != id);
+        }
+        Some(member.rs)
+    }
+
+    /// Return the next result from whichever stream has input ready,
+    /// along with that stream's id, waiting up to `timeout` (forever,
+    /// if `None`) for one to have input.
+    ///
+    /// The results from each stream come in the same order, and with
+    /// the same contract, as from [`RawScan::getline`], so that long
+    /// lines are returned in chunks, but the results from different
+    /// streams are interleaved.  A stream's last result is [`Eof`], or
+    /// [`Err`] if a read failed, after which the stream is dropped from
+    /// the set, closing its fd.
+    ///
+    /// Returns `Ok(None)` if `timeout` passed with no input ready, or
+    /// if the set is empty.  Fails only if epoll_wait() does.
+    ///
+    /// [`Eof`]: RawScanResult::Eof
+    /// [`Err`]: RawScanResult::Err
+    pub fn getline(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<(usize, RawScanResult<'_>)>> {
+        self.drop_ended();
+
+        loop {
+            if self.round_left == 0 || self.ready.is_empty() {
+                if self.nstreams == 0 {
+                    return Ok(None);
+                }
+                let wait = if self.ready.is_empty() { timeout } else { Some(Duration::ZERO) };
+                self.wait(wait)?;
+                if self.ready.is_empty() {
+                    return Ok(None);                    // timed out
+                }
+                self.round_left = self.ready.len();
+            }
+
+            let id = self.ready.pop_front().expect("ready queue empty");
+            self.round_left -= 1;
+            let member = self.streams[id].as_mut().expect("ready stream missing");
+            let span = member.rs.getline_span();
+
+            match span.kind {
+                ResultType::WouldBlock => {
+                    member.queued = false;              // till epoll says otherwise
+                    continue;
+                }
+                ResultType::Eof | ResultType::Err => {
+                    member.queued = false;
+                    self.ended = Some(id);
+                }
+                _ => self.ready.push_back(id),
+            }
+
+            let member = self.streams[id].as_ref().expect("ready stream missing");
+            if self.ended == Some(id) {
+                self.deregister(&member.rs);
+            }
+            return Ok(Some((id, member.rs.to_result(span))));
+        }
+    }
+
+    /// The stream with id `id`, as to change its delimiterbyte, or
+    /// `None` if there's no such stream.
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut RawScan<File>> {
+        self.drop_ended();
+        self.streams.get_mut(id)?.as_mut().map(|member| &mut member.rs)
+    }
+
+    /// The number of streams in the set.
+    pub fn len(&self) -> usize {
+        self.nstreams - self.ended.is_some() as usize
+    }
+
+    /// Whether the set has no streams left.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Wait up to "timeout" for streams to have input ready, adding
+    // those not already queued to the ready queue.
+
+    fn wait(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        let timeout_ms = match timeout {
+            None => -1,
+            Some(t) => t.as_nanos().div_ceil(1_000_000).min(libc::c_int::MAX as u128) as libc::c_int,
+        };
+
+        let n = loop {
+            self.events.clear();
+            let n = unsafe {
+                libc::epoll_wait(
+                    self.epfd.as_raw_fd(),
+                    self.events.as_mut_ptr(),
+                    MAX_EVENTS as libc::c_int,
+                    timeout_ms,
+                )
+            };
+            if n >= 0 {
+                break n as usize;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        };
+        unsafe { self.events.set_len(n) };
+
+        for event in &self.events {
+            let id = event.u64 as usize;
+            if let Some(Some(member)) = self.streams.get_mut(id) {
+                if !member.queued {
+                    member.queued = true;
+                    self.ready.push_back(id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Forget the stream that returned Eof or Err last call, closing it.
+
+    fn drop_ended(&mut self) {
+        if let Some(id) = self.ended.take() {
+            self.streams[id] = None;
+            self.nstreams -= 1;
+        }
+    }
+
+    fn deregister(&self, rs: &RawScan<File>) {
+        let fd = rs.get_ref().as_raw_fd();
+        let mut event = libc::epoll_event { events: 0, u64: 0 };
+        unsafe { libc::epoll_ctl(self.epfd.as_raw_fd(), libc::EPOLL_CTL_DEL, fd, &mut event) };
+    }
+}
+
+fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
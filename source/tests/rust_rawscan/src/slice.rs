@@ -0,0 +1,238 @@
+// SliceScan, the Rust equivalent of a C rs_open_memory() stream,
+// scanning lines in place in a byte slice that's already in memory.
+//
+// A RawScan can't do this itself without copying, as it owns (or at
+// least mutably borrows) its buffer, and reads into it.  Here the
+// slice is the whole buffer, already full, with end of input just
+// past its last byte, so there's never anything to read, shift, or
+// overwrite, and getline() reduces to one bounded memchr() per line.
+
+use std::io;
+
+use memchr::{memchr, memchr_iter};
+
+use crate::RawScanResult;
+
+/// Scans the lines in a byte slice, in place, without copying them or
+/// writing to the slice.
+///
+/// `getline()` returns exactly the results a [`RawScan`] would, reading
+/// the same bytes with a buffer big enough to hold them all: a
+/// [`FullLine`] for each delimiterbyte, perhaps one final
+/// [`FullLineWithoutEol`], then [`Eof`].  Since the slice is never
+/// overwritten, the lines borrow the slice, not the `SliceScan`, and
+/// so remain valid across later `getline()` calls.
+///
+/// ```
+/// use rawscan::{RawScanResult, SliceScan};
+///
+/// let mut ss = SliceScan::new(b"abc\nxyz", b'\n');
+/// let first = ss.getline();
+/// let second = ss.getline();
+/// assert!(matches!(first, RawScanResult::FullLine(b"abc\n")));
+/// assert!(matches!(second, RawScanResult::FullLineWithoutEol(b"xyz")));
+/// assert!(matches!(ss.getline(), RawScanResult::Eof));
+/// ```
+///
+/// [`RawScan`]: crate::RawScan
+/// [`FullLine`]: RawScanResult::FullLine
+/// [`FullLineWithoutEol`]: RawScanResult::FullLineWithoutEol
+/// [`Eof`]: RawScanResult::Eof
+#[derive(Clone, Debug)]
+pub struct SliceScan<'a> {
+    buf: &'a [u8],          // the input, all of it
+    p: usize,               // [p, buf.len()) not yet returned bytes in buf
+    delimiterbyte: u8,      // byte @ end of "lines" (e.g. b'\n' or b'\0')
+    paragraph_mode: bool,   // records end at two consecutive delimiterbytes
+
+    // Start of the last line (or record) returned, and whether it
+    // ended in a delimiterbyte, for extend_record() and unget().
+    last: Option<(usize, bool)>,
+
+    ndelims: u64,           // delimiterbytes in buf before p
+}
+
+impl<'a> SliceScan<'a> {
+    /// Scan the lines in `buf`, ending lines at each `delimiterbyte`.
+    pub fn new(buf: &'a [u8], delimiterbyte: u8) -> SliceScan<'a> {
+        SliceScan { buf, p: 0, delimiterbyte, paragraph_mode: false, last: None, ndelims: 0 }
+    }
+
+    /// Return the next line from the slice, or [`RawScanResult::Eof`]
+    /// once they're all returned.
+    pub fn getline(&mut self) -> RawScanResult<'a> {
+        if self.paragraph_mode {
+            while self.p < self.buf.len() && self.buf[self.p] == self.delimiterbyte {
+                self.p += 1;
+                self.ndelims += 1;
+            }
+        }
+        if self.p == self.buf.len() {
+            self.last = None;
+            return RawScanResult::Eof;
+        }
+        self.line_from(self.p)
+    }
+
+    /// Return the full line just returned, with the next line appended
+    /// to it, as one multiline record, as [`RawScan::extend_record`]
+    /// does.  Since the whole input is already in the slice, every
+    /// record fits.
+    ///
+    /// [`RawScan::extend_record`]: crate::RawScan::extend_record
+    pub fn extend_record(&mut self) -> RawScanResult<'a> {
+        match self.last {
+            Some((begin, true)) if self.p == self.buf.len() => {
+                RawScanResult::FullLine(&self.buf[begin..])
+            }
+            Some((begin, true)) => self.line_from(begin),
+            _ => self.getline(),
+        }
+    }
+
+    /// Hand back the tail end of the line just returned, from `offset`
+    /// bytes into it on, to be returned again by the next `getline()`,
+    /// as [`RawScan::unget`] does.
+    ///
+    /// [`RawScan::unget`]: crate::RawScan::unget
+    pub fn unget(&mut self, offset: usize) -> io::Result<()> {
+        match self.last {
+            Some((begin, _)) if offset <= self.p - begin => {
+                self.ndelims -= self.count_delims_in(begin + offset, self.p);
+                self.p = begin + offset;
+                self.last = None;
+                Ok(())
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no line to unget, or offset past its end",
+            )),
+        }
+    }
+
+    // Return [begin, next delimiterbyte at or after p], or to the end
+    // of the slice, if no more delimiterbytes.  In paragraph mode, only
+    // a delimiterbyte just after another one, both at or after begin,
+    // will do.
+
+    fn line_from(&mut self, begin: usize) -> RawScanResult<'a> {
+        let delim = self.delimiterbyte;
+        let mut i = self.p;
+        let mut end = None;
+
+        while let Some(off) = memchr(delim, &self.buf[i..]) {
+            let at = i + off;
+            if !self.paragraph_mode || (at > begin && self.buf[at - 1] == delim) {
+                end = Some(at);
+                break;
+            }
+            i = at + 1;
+        }
+        // Only the bytes from p on are newly returned; any before p, in
+        // the record being extended, are counted already.
+        let from = self.p;
+        match end {
+            Some(at) => {
+                self.ndelims += self.count_delims_in(from, at + 1);
+                self.p = at + 1;
+                self.last = Some((begin, true));
+                RawScanResult::FullLine(&self.buf[begin..self.p])
+            }
+            None => {
+                self.ndelims += self.count_delims_in(from, self.buf.len());
+                self.p = self.buf.len();
+                self.last = Some((begin, false));
+                RawScanResult::FullLineWithoutEol(&self.buf[begin..])
+            }
+        }
+    }
+
+    /// The delimiterbyte that ends each line.
+    pub fn delimiterbyte(&self) -> u8 {
+        self.delimiterbyte
+    }
+
+    /// End lines at `delimiterbyte` from here on.
+    pub fn set_delimiterbyte(&mut self, delimiterbyte: u8) {
+        self.delimiterbyte = delimiterbyte;
+    }
+
+    /// Return paragraphs, ending at two consecutive delimiterbytes,
+    /// rather than lines, from here on, as
+    /// [`RawScan::set_paragraph_mode`] does.
+    ///
+    /// [`RawScan::set_paragraph_mode`]: crate::RawScan::set_paragraph_mode
+    pub fn set_paragraph_mode(&mut self, paragraph_mode: bool) {
+        self.paragraph_mode = paragraph_mode;
+    }
+
+    /// Whether paragraph mode is on.
+    pub fn paragraph_mode(&self) -> bool {
+        self.paragraph_mode
+    }
+
+    /// The bytes not yet returned by `getline()`.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.buf[self.p..]
+    }
+
+    /// The offset in the slice of the line last returned, else of the
+    /// next byte to be returned, as [`RawScan::line_offset`] returns.
+    ///
+    /// [`RawScan::line_offset`]: crate::RawScan::line_offset
+    pub fn line_offset(&self) -> u64 {
+        match self.last {
+            Some((begin, _)) => begin as u64,
+            None => self.p as u64,
+        }
+    }
+
+    /// The line number, from 1, of the line last returned, else of the
+    /// next line, as [`RawScan::line_number`] returns.
+    ///
+    /// [`RawScan::line_number`]: crate::RawScan::line_number
+    pub fn line_number(&self) -> u64 {
+        match self.last {
+            Some((begin, _)) => self.ndelims + 1 - self.count_delims_in(begin, self.p),
+            None => self.ndelims + 1,
+        }
+    }
+
+    /// The number of delimiterbytes before the next byte to be
+    /// returned.
+    pub fn delimiter_count(&self) -> u64 {
+        self.ndelims
+    }
+
+    /// The offset in the slice of the next byte to be returned.  See
+    /// [`RawScan::tell`].
+    ///
+    /// [`RawScan::tell`]: crate::RawScan::tell
+    pub fn tell(&self) -> u64 {
+        self.p as u64
+    }
+
+    /// Go to `offset` bytes into the slice, so that the next
+    /// `getline()` returns the line from there, as [`RawScan::seek`]
+    /// does.  Line numbers start over at 1.  Fails, changing nothing,
+    /// if `offset` is past the end of the slice.
+    ///
+    /// [`RawScan::seek`]: crate::RawScan::seek
+    pub fn seek(&mut self, offset: u64) -> io::Result<u64> {
+        if offset > self.buf.len() as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek offset past end of slice",
+            ));
+        }
+
+        self.p = offset as usize;
+        self.last = None;
+        self.ndelims = 0;
+        Ok(offset)
+    }
+
+    fn count_delims_in(&self, from: usize, to: usize) -> u64 {
+        memchr_iter(self.delimiterbyte, &self.buf[from..to]).count() as u64
+    }
+}
@@ -0,0 +1,51 @@
+// RawScan as a std::io::BufRead, so the usual std line and record
+// helpers run directly off the rawscan buffer.
+
+use std::io::{self, BufRead, Read};
+
+use rawscan::{RawScan, RawScanResult};
+
+#[test]
+fn lines_match_std() {
+    let input = b"first\nsecond line\n\nlast without eol";
+    let rs = RawScan::new(&input[..], 8, b'\n');
+    let std_lines: Vec<String> = io::Cursor::new(&input[..]).lines().collect::<Result<_, _>>().unwrap();
+
+    assert_eq!(rs.lines().collect::<Result<Vec<_>, _>>().unwrap(), std_lines);
+}
+
+#[test]
+fn split_and_read_until() {
+    let rs = RawScan::new(&b"a\0bb\0ccc"[..], 4, b'\n');
+    let records: Vec<Vec<u8>> = rs.split(b'\0').collect::<Result<_, _>>().unwrap();
+    assert_eq!(records, vec![b"a".to_vec(), b"bb".to_vec(), b"ccc".to_vec()]);
+
+    let mut rs = RawScan::new(&b"0123456789,tail"[..], 3, b'\n');
+    let mut record = Vec::new();
+    assert_eq!(rs.read_until(b',', &mut record).unwrap(), 11);
+    assert_eq!(record, b"0123456789,");
+}
+
+#[test]
+fn read_to_end_after_getline() {
+    let mut rs = RawScan::new(&b"head\nbody 1\nbody 2\n"[..], 16, b'\n');
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"head\n")));
+
+    let mut rest = Vec::new();
+    rs.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"body 1\nbody 2\n");
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+}
+
+#[test]
+fn getline_after_fill_buf() {
+    let mut rs = RawScan::new(&b"abc\ndef\nghi\n"[..], 16, b'\n');
+    assert_eq!(rs.fill_buf().unwrap(), b"abc\ndef\nghi\n");
+    rs.consume(2);
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"c\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"def\n")));
+    rs.consume(1);
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"hi\n")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+}
@@ -0,0 +1,71 @@
+// Fixtures shared by the integration tests: a small random number
+// generator, and a reader handing out its input a few bytes at a time.
+//
+// Each test file is its own crate, using only some of these.
+
+#![allow(dead_code)]
+
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+
+// Small deterministic PCG-ish generator, so failures are reproducible.
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+// Reader handing out at most "step" bytes per read() call, with reads
+// failing, as those of a non-blocking or signalled fd might, at random
+// in between, if given a jitter() generator.  It seeks, as a file
+// would, for ReverseScan.
+pub struct Trickle<'a> {
+    data: &'a [u8],
+    pos: usize,
+    step: usize,
+    jitter: Option<Rng>,
+}
+
+impl<'a> Trickle<'a> {
+    pub fn new(data: &'a [u8], step: usize) -> Trickle<'a> {
+        Trickle { data, pos: 0, step, jitter: None }
+    }
+
+    pub fn jitter(self, rng: Rng) -> Trickle<'a> {
+        Trickle { jitter: Some(rng), ..self }
+    }
+}
+
+impl<'a> Read for Trickle<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.jitter.as_mut().map(|rng| rng.below(4)) {
+            Some(0) => return Err(ErrorKind::WouldBlock.into()),
+            Some(1) => return Err(ErrorKind::Interrupted.into()),
+            _ => {}
+        }
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        let n = rest.len().min(buf.len()).min(self.step);
+        buf[..n].copy_from_slice(&rest[..n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl<'a> Seek for Trickle<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (self.data.len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => (self.pos as u64).checked_add_signed(offset),
+        };
+        let pos = pos.ok_or_else(|| io::Error::from(ErrorKind::InvalidInput))?;
+        self.pos = pos as usize;
+        Ok(pos)
+    }
+}
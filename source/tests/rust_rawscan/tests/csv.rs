@@ -0,0 +1,164 @@
+// CSV records from CsvReader, checked against the records that were
+// encoded, over many small random inputs, small buffers and small
+// reads, so that records are often split across reads, and often too
+// long for the buffer.
+
+mod common;
+
+use std::borrow::Cow;
+use std::io::{self, Read};
+
+use common::{Rng, Trickle};
+use rawscan::{CsvReader, CsvResult, RawScan};
+
+// All the records from "input", as owned fields.
+fn read_all<R: Read>(csv: &mut CsvReader<R>) -> Vec<Vec<Vec<u8>>> {
+    let mut records = Vec::new();
+    loop {
+        match csv.read_record() {
+            CsvResult::Record(record) => {
+                records.push(record.fields().map(Cow::into_owned).collect());
+            }
+            CsvResult::Eof => return records,
+            CsvResult::Err(e) => panic!("read failed: {}", e),
+            CsvResult::WouldBlock => {}
+        }
+    }
+}
+
+// Encode "records" the RFC 4180 way, quoting fields when they need it,
+// and sometimes when they don't.
+fn encode(rng: &mut Rng, records: &[Vec<Vec<u8>>], finaleol: bool) -> Vec<u8> {
+    let mut input = Vec::new();
+    for (i, record) in records.iter().enumerate() {
+        for (j, field) in record.iter().enumerate() {
+            if j > 0 {
+                input.push(b',');
+            }
+            let special = field.iter().any(|b| b",\"\r\n".contains(b));
+            if special || rng.below(4) == 0 || record.len() == 1 && field.is_empty() {
+                input.push(b'"');
+                for &b in field {
+                    if b == b'"' {
+                        input.push(b'"');
+                    }
+                    input.push(b);
+                }
+                input.push(b'"');
+            } else {
+                input.extend_from_slice(field);
+            }
+        }
+        if i + 1 < records.len() || finaleol {
+            input.extend_from_slice(if rng.below(2) == 0 { b"\r\n" } else { b"\n" });
+        }
+    }
+    input
+}
+
+#[test]
+fn quoted_fields() {
+    let input = b"a,\"b,c\",\"d\"\"e\"\n\"multi\nline\",x\r\n\"\"\n\n\"open";
+    let mut csv = CsvReader::new(RawScan::new(&input[..], 64, b'\n'), b',');
+
+    match csv.read_record() {
+        CsvResult::Record(record) => {
+            let fields: Vec<_> = record.fields().collect();
+            assert_eq!(fields, [&b"a"[..], b"b,c", b"d\"e"]);
+            assert!(matches!(fields[1], Cow::Borrowed(_)), "no unescaping needed");
+            assert!(matches!(fields[2], Cow::Owned(_)), "doubled quote");
+        }
+        other => panic!("{:?}", other),
+    }
+    match csv.read_record() {
+        CsvResult::Record(record) => {
+            assert_eq!(record.as_bytes(), b"\"multi\nline\",x");
+            assert_eq!(record.fields().collect::<Vec<_>>(), [&b"multi\nline"[..], b"x"]);
+        }
+        other => panic!("{:?}", other),
+    }
+    match csv.read_record() {
+        CsvResult::Record(record) => assert_eq!(record.fields().collect::<Vec<_>>(), [&b""[..]]),
+        other => panic!("{:?}", other),
+    }
+    match csv.read_record() {
+        CsvResult::Record(record) => assert_eq!(record.fields().count(), 0, "empty record"),
+        other => panic!("{:?}", other),
+    }
+    match csv.read_record() {
+        CsvResult::Record(record) => {
+            assert_eq!(record.fields().collect::<Vec<_>>(), [&b"open"[..]], "unterminated");
+        }
+        other => panic!("{:?}", other),
+    }
+    assert!(matches!(csv.read_record(), CsvResult::Eof));
+    assert!(matches!(csv.read_record(), CsvResult::Eof));
+}
+
+#[test]
+fn records_longer_than_buffer() {
+    let input = b"short\n\"0123456789\nabcdefghij\",\"x\"\"y\"\nlast,\"z\"\n";
+    let mut csv = CsvReader::new(RawScan::new(&input[..], 8, b'\n'), b',');
+
+    assert_eq!(
+        read_all(&mut csv),
+        [
+            vec![b"short".to_vec()],
+            vec![b"0123456789\nabcdefghij".to_vec(), b"x\"y".to_vec()],
+            vec![b"last".to_vec(), b"z".to_vec()],
+        ]
+    );
+}
+
+#[test]
+fn tab_separated() {
+    let input = b"a\t\"b\tc\"\t\n";
+    let mut csv = CsvReader::new(RawScan::new(&input[..], 64, b'\n'), b'\t');
+
+    assert_eq!(read_all(&mut csv), [vec![b"a".to_vec(), b"b\tc".to_vec(), Vec::new()]]);
+}
+
+#[test]
+fn read_error() {
+    struct Fail;
+
+    impl Read for Fail {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("boom"))
+        }
+    }
+
+    let mut csv = CsvReader::new(RawScan::new(Fail, 16, b'\n'), b',');
+    assert!(matches!(csv.read_record(), CsvResult::Err(e) if e.to_string() == "boom"));
+    assert!(matches!(csv.read_record(), CsvResult::Err(_)));
+}
+
+#[test]
+fn random_records() {
+    let mut rng = Rng(17);
+
+    for _ in 0..3000 {
+        let records: Vec<Vec<Vec<u8>>> = (0..rng.below(6) + 1)
+            .map(|_| {
+                (0..rng.below(4) + 1)
+                    .map(|_| (0..rng.below(8)).map(|_| b"ab,\"\n\r"[rng.below(6)]).collect())
+                    .collect()
+            })
+            .collect();
+        let finaleol = rng.below(2) == 0;
+        let input = encode(&mut rng, &records, finaleol);
+        let bufsz = rng.below(40) + 1;
+        let step = rng.below(12) + 1;
+
+        let rs = RawScan::new(Trickle::new(&input, step), bufsz, b'\n');
+        let got = read_all(&mut CsvReader::new(rs, b','));
+        assert_eq!(
+            got,
+            records,
+            "input {:?} bufsz {} step {}",
+            String::from_utf8_lossy(&input),
+            bufsz,
+            step
+        );
+    }
+}
@@ -0,0 +1,80 @@
+// Field splitting with Fields, and RawScanResult::fields(), checked
+// against the obvious split() over many small random lines, and
+// parse_field() over some numbers.
+
+mod common;
+
+use common::Rng;
+use rawscan::{parse_field, Fields, RawScan, RawScanResult};
+
+// The fields of "text", the slow and obvious way.
+fn expected_fields(text: &[u8], sep: u8, runs: bool) -> Vec<&[u8]> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    let fields = text.split(|&b| b == sep);
+    if runs {
+        fields.filter(|f| !f.is_empty()).collect()
+    } else {
+        fields.collect()
+    }
+}
+
+#[test]
+fn separated_values() {
+    let got: Vec<&[u8]> = Fields::new(b"a,,b,", b',', false).collect();
+    assert_eq!(got, [&b"a"[..], b"", b"b", b""]);
+    assert_eq!(Fields::new(b"", b',', false).count(), 0);
+    assert_eq!(Fields::new(b",", b',', false).count(), 2);
+}
+
+#[test]
+fn runs_of_spaces() {
+    let got: Vec<&[u8]> = Fields::new(b"  one two   three ", b' ', true).collect();
+    assert_eq!(got, [&b"one"[..], b"two", b"three"]);
+    assert_eq!(Fields::new(b"   ", b' ', true).count(), 0);
+    assert_eq!(Fields::new(b"  one two", b' ', true).nth(1), Some(&b"two"[..]));
+    assert_eq!(Fields::new(b"  one two", b' ', true).nth(2), None);
+}
+
+#[test]
+fn fields_of_results() {
+    let mut rs = RawScan::new(&b"x\ty\n\nlong\tline\tz"[..], 8, b'\n');
+
+    assert_eq!(rs.getline().fields(b'\t', false).collect::<Vec<_>>(), [&b"x"[..], b"y"]);
+    assert_eq!(rs.getline().fields(b'\t', false).count(), 0, "empty line");
+    assert!(matches!(rs.getline(), RawScanResult::StartLongline(b"long\tlin")));
+    assert_eq!(rs.getline().fields(b'\t', false).collect::<Vec<_>>(), [&b"e"[..], b"z"]);
+    assert_eq!(rs.getline().fields(b'\t', false).count(), 0, "LonglineEnded");
+}
+
+#[test]
+fn parse_numbers() {
+    assert_eq!(parse_field::<i64>(b"-42"), Some(-42));
+    assert_eq!(parse_field::<i64>(b"+7"), Some(7));
+    assert_eq!(parse_field::<i64>(b"-9223372036854775808"), Some(i64::MIN));
+    assert_eq!(parse_field::<i64>(b"9223372036854775808"), None);
+    assert_eq!(parse_field::<i64>(b" 1"), None);
+    assert_eq!(parse_field::<i64>(b""), None);
+    assert_eq!(parse_field::<f64>(b"2.5e3"), Some(2500.0));
+    assert_eq!(parse_field::<f64>(b"x"), None);
+    assert_eq!(parse_field::<u8>(b"\xff"), None);
+}
+
+#[test]
+fn random_fields() {
+    let mut rng = Rng(17);
+
+    for _ in 0..5000 {
+        let text: Vec<u8> = (0..rng.below(20)).map(|_| b"ab, \t"[rng.below(5)]).collect();
+        let sep = b", \t"[rng.below(3)];
+        let runs = rng.below(2) == 0;
+        let expected = expected_fields(&text, sep, runs);
+        let context = format!("text {:?} sep {:?} runs {}", String::from_utf8_lossy(&text), sep as char, runs);
+
+        assert_eq!(Fields::new(&text, sep, runs).collect::<Vec<_>>(), expected, "{}", context);
+        assert_eq!(Fields::new(&text, sep, runs).count(), expected.len(), "{}", context);
+        let n = rng.below(expected.len() + 2);
+        assert_eq!(Fields::new(&text, sep, runs).nth(n), expected.get(n).copied(), "{}", context);
+    }
+}
@@ -0,0 +1,82 @@
+// Follow mode, as for "tail -f": at end of input, getline() waits for
+// more to be appended, rather than returning Eof, and holds back a
+// partial last line until its delimiterbyte arrives.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+use rawscan::{RawScan, RawScanResult, ResultType};
+
+// Reader handing out "chunks" one per read() call, with a read of
+// nothing, as at end of a file not yet appended to, before each.
+struct Appended {
+    chunks: Vec<&'static [u8]>,
+    at_eof: bool,
+}
+
+impl Read for Appended {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.at_eof = !self.at_eof;
+        if self.at_eof || self.chunks.is_empty() {
+            return Ok(0);
+        }
+        let n = self.chunks[0].len().min(buf.len());
+        buf[..n].copy_from_slice(&self.chunks[0][..n]);
+        self.chunks[0] = &self.chunks[0][n..];
+        if self.chunks[0].is_empty() {
+            self.chunks.remove(0);
+        }
+        Ok(n)
+    }
+}
+
+#[test]
+fn partial_lines_held_back() {
+    let chunks = vec![&b"ab"[..], b"c\nde", b"f", b"\n\nlong line\n"];
+    let mut rs = RawScan::new(Appended { chunks, at_eof: false }, 8, b'\n');
+    rs.set_follow(Some(Duration::from_millis(1)));
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"abc\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"def\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"\n")));
+    assert!(matches!(rs.getline(), RawScanResult::StartLongline(b"long lin")));
+    assert!(matches!(rs.getline(), RawScanResult::WithinLongline(b"e\n")));
+    assert!(matches!(rs.getline(), RawScanResult::LonglineEnded));
+    assert!(!rs.eof_seen());
+
+    rs.set_follow(None);
+    assert_eq!(rs.follow(), None);
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+
+    // Following again forgets the end of input.
+    rs.set_follow(Some(Duration::from_millis(1)));
+    assert!(!rs.eof_seen());
+}
+
+#[test]
+fn tail_growing_file() -> io::Result<()> {
+    let path = std::env::temp_dir().join(format!("rawscan-follow-{}", std::process::id()));
+    File::create(&path)?.write_all(b"first\npart")?;
+
+    let mut rs = RawScan::new(File::open(&path)?, 64, b'\n');
+    rs.set_follow(Some(Duration::from_millis(5)));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"first\n")));
+
+    let writer = {
+        let path = path.clone();
+        thread::spawn(move || -> io::Result<()> {
+            let mut log = OpenOptions::new().append(true).open(path)?;
+            thread::sleep(Duration::from_millis(50));
+            log.write_all(b"ial\nsecond\n")
+        })
+    };
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"partial\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"second\n")));
+    writer.join().unwrap()?;
+
+    rs.set_follow(None);
+    assert_eq!(rs.getline().result_type(), ResultType::Eof);
+    std::fs::remove_file(&path)
+}
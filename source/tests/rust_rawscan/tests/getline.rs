@@ -0,0 +1,323 @@
+// Regression tests for RawScan::getline(), in the spirit of the
+// regression_stress_test.sh script next door: feed many small random
+// inputs through small buffers, where the tricky edge cases live,
+// and check the results against a trivial line splitter.
+
+mod common;
+
+use std::io::{self, Read};
+
+use common::{Rng, Trickle};
+use rawscan::{RawScan, RawScanResult, ResultType, SliceScan};
+
+fn collect<R: Read, B>(rs: &mut RawScan<R, B>) -> Vec<(ResultType, Vec<u8>)>
+where
+    B: AsRef<[u8]> + AsMut<[u8]>,
+{
+    let mut results = Vec::new();
+    loop {
+        let rt = rs.getline();
+        let kind = rt.result_type();
+        let line = rt.line().map(<[u8]>::to_vec).unwrap_or_default();
+        results.push((kind, line));
+        match kind {
+            ResultType::Eof | ResultType::Err => return results,
+            _ => {}
+        }
+    }
+}
+
+fn random_input(rng: &mut Rng, nlines: usize, maxlen: usize, finaleol: bool) -> Vec<u8> {
+    let mut input = Vec::new();
+    for i in 0..nlines {
+        let len = rng.below(maxlen + 1);
+        input.extend((0..len).map(|_| b"abc "[rng.below(4)]));
+        if i + 1 < nlines || finaleol {
+            input.push(b'\n');
+        }
+    }
+    input
+}
+
+// Check one scan of "input" against what rawscan promises.
+fn check(input: &[u8], results: &[(ResultType, Vec<u8>)], min1st: usize) {
+    let mut expected = input.split_inclusive(|&b| b == b'\n');
+    let mut i = 0;
+
+    while i < results.len() {
+        let (kind, line) = &results[i];
+        match kind {
+            ResultType::FullLine | ResultType::FullLineWithoutEol => {
+                let want = expected.next().expect("extra line returned");
+                assert_eq!(line, want);
+                assert_eq!(*kind == ResultType::FullLine, want.ends_with(b"\n"));
+                i += 1;
+            }
+            ResultType::StartLongline => {
+                let want = expected.next().expect("extra long line returned");
+                assert!(line.len() >= min1st, "first chunk shorter than min1stchunklen");
+                assert!(want.len() > min1st || !want.ends_with(b"\n"));
+                let mut got = line.clone();
+                i += 1;
+                while results[i].0 == ResultType::WithinLongline {
+                    got.extend(&results[i].1);
+                    i += 1;
+                }
+                assert_eq!(results[i].0, ResultType::LonglineEnded);
+                assert_eq!(got, want);
+                i += 1;
+            }
+            ResultType::Eof => {
+                assert!(expected.next().is_none(), "missing lines at eof");
+                assert_eq!(i + 1, results.len());
+                i += 1;
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn short_lines() {
+    let mut rs = RawScan::new(&b"one\ntwo\n\nthree"[..], 64, b'\n');
+    let results = collect(&mut rs);
+
+    assert_eq!(
+        results,
+        vec![
+            (ResultType::FullLine, b"one\n".to_vec()),
+            (ResultType::FullLine, b"two\n".to_vec()),
+            (ResultType::FullLine, b"\n".to_vec()),
+            (ResultType::FullLineWithoutEol, b"three".to_vec()),
+            (ResultType::Eof, Vec::new()),
+        ]
+    );
+}
+
+#[test]
+fn empty_input() {
+    let mut rs = RawScan::new(io::empty(), 8, b'\n');
+    assert_eq!(collect(&mut rs), vec![(ResultType::Eof, Vec::new())]);
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+}
+
+#[test]
+fn nul_delimiter() {
+    let mut rs = RawScan::new(&b"a\nb\0c\0"[..], 8, b'\0');
+    let results = collect(&mut rs);
+
+    assert_eq!(results[0], (ResultType::FullLine, b"a\nb\0".to_vec()));
+    assert_eq!(results[1], (ResultType::FullLine, b"c\0".to_vec()));
+}
+
+#[test]
+fn long_line_in_chunks() {
+    let mut rs = RawScan::new(&b"ab\n0123456789\nxy\n"[..], 4, b'\n');
+    let results = collect(&mut rs);
+
+    check(b"ab\n0123456789\nxy\n", &results, 4);
+    assert_eq!(results[1].0, ResultType::StartLongline);
+}
+
+#[test]
+fn min1stchunklen_limits_shifting() {
+    let input = b"0123456789abcdef\n";
+    let mut rs = RawScan::new(Trickle::new(input, 3), 8, b'\n');
+
+    assert!(rs.set_min1stchunklen(9).is_err());
+    assert!(rs.set_min1stchunklen(0).is_err());
+    rs.set_min1stchunklen(3).unwrap();
+    assert_eq!(rs.min1stchunklen(), 3);
+
+    let results = collect(&mut rs);
+    check(input, &results, 3);
+}
+
+#[test]
+fn random_inputs_small_buffers() {
+    let mut rng = Rng(42);
+
+    for nlines in 0..12 {
+        for maxlen in 0..24 {
+            for &finaleol in &[false, true] {
+                let input = random_input(&mut rng, nlines, maxlen, finaleol);
+                for bufsz in 1..=20 {
+                    let min1st = 1 + rng.below(bufsz);
+                    let step = 1 + rng.below(bufsz + 4);
+
+                    let mut rs = RawScan::new(&input[..], bufsz, b'\n');
+                    check(&input, &collect(&mut rs), bufsz);
+
+                    let mut rs = RawScan::new(Trickle::new(&input, step), bufsz, b'\n');
+                    rs.set_min1stchunklen(min1st).unwrap();
+                    check(&input, &collect(&mut rs), min1st);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn read_error_is_sticky() {
+    struct Failing(bool);
+
+    impl Read for Failing {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0 {
+                return Err(io::Error::from_raw_os_error(5));
+            }
+            self.0 = true;
+            buf[..4].copy_from_slice(b"ab\nc");
+            Ok(4)
+        }
+    }
+
+    let mut rs = RawScan::new(Failing(false), 16, b'\n');
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"ab\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLineWithoutEol(b"c")));
+    assert!(rs.err_seen());
+    assert!(!rs.eof_seen());
+    for _ in 0..2 {
+        match rs.getline() {
+            RawScanResult::Err(e) => assert_eq!(e.raw_os_error(), Some(5)),
+            other => panic!("expected error, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn pause_and_resume() {
+    let mut rs = RawScan::new(&b"aa\nbb\ncc\ndd\n"[..], 6, b'\n');
+    rs.enable_pause();
+
+    let mut lines = Vec::new();
+    let mut pauses = 0;
+    loop {
+        match rs.getline() {
+            RawScanResult::FullLine(line) => lines.push(line.to_vec()),
+            RawScanResult::Paused => {
+                pauses += 1;
+                rs.resume_from_pause();
+            }
+            RawScanResult::Eof => break,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    assert_eq!(lines, vec![b"aa\n", b"bb\n", b"cc\n", b"dd\n"]);
+    assert!(pauses > 0);
+}
+
+#[test]
+fn stream_state() {
+    let mut rs = RawScan::new(&b"ab\ncdefg\nh"[..], 4, b'\n');
+    assert_eq!((rs.buffered_bytes(), rs.bufsz()), (0, 4));
+    rs.enable_pause();
+
+    // Nothing read yet, so even the first read would recycle the buffer.
+    assert!(matches!(rs.getline(), RawScanResult::Paused));
+    assert!(rs.is_paused());
+    rs.resume_from_pause();
+    assert!(!rs.is_paused());
+    rs.disable_pause();
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"ab\n")));
+    assert_eq!(rs.buffered_bytes(), 1);
+    assert!(!rs.in_longline());
+    assert!(matches!(rs.getline(), RawScanResult::StartLongline(b"cdef")));
+    assert!(rs.in_longline());
+    assert!(matches!(rs.getline(), RawScanResult::WithinLongline(b"g\n")));
+    assert!(matches!(rs.getline(), RawScanResult::LonglineEnded));
+    assert!(!rs.in_longline());
+    assert!(!rs.eof_seen());
+    assert!(matches!(rs.getline(), RawScanResult::FullLineWithoutEol(b"h")));
+    assert!(rs.eof_seen());
+    assert!(!rs.err_seen());
+    assert_eq!(rs.buffered_bytes(), 0);
+}
+
+#[test]
+fn caller_supplied_buffers() {
+    let mut rng = Rng(11);
+    let stash: &'static mut [u8] = Box::leak(vec![0xffu8; 8].into_boxed_slice());
+
+    for _ in 0..50 {
+        let finaleol = rng.below(2) == 0;
+        let input = random_input(&mut rng, 12, 20, finaleol);
+        let expected = collect(&mut RawScan::new(Trickle::new(&input, 3), 8, b'\n'));
+
+        let mut rs = RawScan::with_buffer(Trickle::new(&input, 3), [0u8; 8], b'\n');
+        assert_eq!(collect(&mut rs), expected);
+
+        let mut rs = RawScan::with_buffer(Trickle::new(&input, 3), &mut *stash, b'\n');
+        assert_eq!(collect(&mut rs), expected);
+    }
+}
+
+#[test]
+fn slice_scan_matches_big_buffer() {
+    let mut rng = Rng(5);
+
+    for nlines in 0..20 {
+        let finaleol = rng.below(2) == 0;
+        let input = random_input(&mut rng, nlines, 30, finaleol);
+        let expected = collect(&mut RawScan::new(&input[..], input.len() + 1, b'\n'));
+
+        let mut ss = SliceScan::new(&input, b'\n');
+        let mut results = Vec::new();
+        loop {
+            let rt = ss.getline();
+            results.push((rt.result_type(), rt.line().map(<[u8]>::to_vec).unwrap_or_default()));
+            if rt.result_type() == ResultType::Eof {
+                break;
+            }
+        }
+        assert_eq!(results, expected);
+        assert!(ss.remaining().is_empty());
+        assert!(matches!(ss.getline(), RawScanResult::Eof));
+    }
+}
+
+#[test]
+fn read_trait_object_and_get_mut() {
+    let first: Box<dyn Read> = Box::new(Trickle::new(b"abc\nde", 8));
+    let mut rs = RawScan::new(first, 8, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"abc\n")));
+    *rs.get_mut() = Box::new(Trickle::new(b"f\nxyz\n", 2));
+    assert_eq!(
+        collect(&mut rs),
+        vec![
+            (ResultType::FullLine, b"def\n".to_vec()),
+            (ResultType::FullLine, b"xyz\n".to_vec()),
+            (ResultType::Eof, Vec::new()),
+        ]
+    );
+}
+
+#[test]
+fn change_delimiterbyte_mid_stream() {
+    // A newline delimited header, then a nul delimited body, all
+    // read into the buffer at once.
+    let input = b"name\nsize\n\nfoo\0bar\0baz";
+    let mut rs = RawScan::new(&input[..], 64, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"name\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"size\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"\n")));
+    rs.set_delimiterbyte(b'\0');
+    assert_eq!(rs.delimiterbyte(), b'\0');
+    assert_eq!(
+        collect(&mut rs),
+        vec![
+            (ResultType::FullLine, b"foo\0".to_vec()),
+            (ResultType::FullLine, b"bar\0".to_vec()),
+            (ResultType::FullLineWithoutEol, b"baz".to_vec()),
+            (ResultType::Eof, Vec::new()),
+        ]
+    );
+
+    let mut ss = SliceScan::new(input, b'\n');
+    while !matches!(ss.getline(), RawScanResult::FullLine(b"\n")) {}
+    ss.set_delimiterbyte(b'\0');
+    assert!(matches!(ss.getline(), RawScanResult::FullLine(b"foo\0")));
+}
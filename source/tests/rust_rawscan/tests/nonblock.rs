@@ -0,0 +1,186 @@
+// Non-blocking input: reads failing with WouldBlock, which getline()
+// and friends pass up as RawScanResult::WouldBlock, to be called again
+// later, and reads failing with Interrupted, which are just retried.
+// Checked against the same input read without such failures, over
+// many small random inputs, small buffers and small reads.
+
+mod common;
+
+use std::borrow::Cow;
+use std::io::{self, ErrorKind, Read};
+
+use common::{Rng, Trickle};
+use rawscan::{CsvReader, CsvResult, RawScan, RawScanResult, RecordScanner, ResultType};
+
+// Reader handing out "reads" one per read() call, each some bytes, or
+// an error kind to fail with, then end of input.
+struct Scripted {
+    reads: Vec<Result<&'static [u8], ErrorKind>>,
+}
+
+impl Read for Scripted {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.reads.is_empty() {
+            return Ok(0);
+        }
+        match self.reads.remove(0) {
+            Ok(data) => {
+                buf[..data.len()].copy_from_slice(data);
+                Ok(data.len())
+            }
+            Err(kind) => Err(kind.into()),
+        }
+    }
+}
+
+#[test]
+fn would_block_and_call_again() {
+    let reads = vec![
+        Err(ErrorKind::WouldBlock),
+        Ok(&b"ab\n"[..]),
+        Err(ErrorKind::Interrupted),
+        Err(ErrorKind::WouldBlock),
+        Ok(b"cd"),
+        Err(ErrorKind::WouldBlock),
+        Err(ErrorKind::Interrupted),
+    ];
+    let mut rs = RawScan::new(Scripted { reads }, 8, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::WouldBlock));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"ab\n")));
+    assert!(matches!(rs.getline(), RawScanResult::WouldBlock));
+    assert!(matches!(rs.getline(), RawScanResult::WouldBlock), "partial line held back");
+    assert!(!rs.eof_seen());
+    assert!(matches!(rs.getline(), RawScanResult::FullLineWithoutEol(b"cd")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+}
+
+#[test]
+fn would_block_while_extending() {
+    let reads = vec![Ok(&b"ab\n"[..]), Err(ErrorKind::WouldBlock), Ok(b"cd\n")];
+    let mut rs = RawScan::new(Scripted { reads }, 8, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"ab\n")));
+    assert!(matches!(rs.extend_record(), RawScanResult::WouldBlock));
+    assert_eq!(rs.line_offset(), 0, "record still the last line returned");
+    assert!(matches!(rs.extend_record(), RawScanResult::FullLine(b"ab\ncd\n")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+}
+
+#[test]
+fn other_errors_still_end_input() {
+    let reads = vec![Ok(&b"ab\n"[..]), Err(ErrorKind::BrokenPipe)];
+    let mut rs = RawScan::new(Scripted { reads }, 8, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"ab\n")));
+    assert!(matches!(rs.getline(), RawScanResult::Err(e) if e.kind() == ErrorKind::BrokenPipe));
+}
+
+// The results, as (type, bytes), from getline() and extend_record()
+// calls chosen by "ops", calling the same again after a WouldBlock,
+// and leaving the WouldBlock results out.
+fn results<R: Read>(rs: &mut RawScan<R>, mut ops: Rng) -> Vec<(ResultType, Vec<u8>)> {
+    let mut got = Vec::new();
+    let mut extend = false;
+
+    loop {
+        let result = if extend { rs.extend_record() } else { rs.getline() };
+        match result {
+            RawScanResult::WouldBlock => continue,
+            RawScanResult::Eof => return got,
+            result => got.push((result.result_type(), result.line().unwrap_or_default().to_vec())),
+        }
+        extend = ops.below(4) == 0;
+    }
+}
+
+#[test]
+fn random_lines() {
+    let mut rng = Rng(21);
+
+    for i in 0..3000 {
+        let input: Vec<u8> = (0..rng.below(60)).map(|_| b"ab\n"[rng.below(3)]).collect();
+        let bufsz = rng.below(12) + 1;
+        let step = rng.below(8) + 1;
+
+        let mut plain = RawScan::new(Trickle::new(&input, step), bufsz, b'\n');
+        let mut jittery = RawScan::new(Trickle::new(&input, step).jitter(Rng(i)), bufsz, b'\n');
+        assert_eq!(
+            results(&mut jittery, Rng(i)),
+            results(&mut plain, Rng(i)),
+            "input {:?} bufsz {} step {}",
+            String::from_utf8_lossy(&input),
+            bufsz,
+            step
+        );
+    }
+}
+
+// All the records, putting chunked records back together.
+fn records<R: Read>(records: &mut RecordScanner<R, fn(&[u8]) -> bool>) -> Vec<Vec<u8>> {
+    let mut got = Vec::new();
+    loop {
+        match records.getrecord() {
+            RawScanResult::FullLine(record) | RawScanResult::FullLineWithoutEol(record) => {
+                got.push(record.to_vec());
+            }
+            RawScanResult::StartLongline(chunk) => got.push(chunk.to_vec()),
+            RawScanResult::WithinLongline(chunk) => got.last_mut().unwrap().extend(chunk),
+            RawScanResult::Eof => return got,
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn random_records() {
+    let mut rng = Rng(22);
+    let is_continuation: fn(&[u8]) -> bool = |line| line.starts_with(b" ");
+
+    for i in 0..3000 {
+        let input: Vec<u8> = (0..rng.below(60)).map(|_| b"ab \n"[rng.below(4)]).collect();
+        let bufsz = rng.below(12) + 1;
+        let step = rng.below(8) + 1;
+
+        let plain = RawScan::new(Trickle::new(&input, step), bufsz, b'\n');
+        let jittery = RawScan::new(Trickle::new(&input, step).jitter(Rng(i)), bufsz, b'\n');
+        assert_eq!(
+            records(&mut RecordScanner::new(jittery, is_continuation)),
+            records(&mut RecordScanner::new(plain, is_continuation)),
+            "input {:?} bufsz {} step {}",
+            String::from_utf8_lossy(&input),
+            bufsz,
+            step
+        );
+    }
+}
+
+#[test]
+fn csv_records() {
+    let input = b"a,\"b\nc\"\n\"0123456789\nabcdefghij\",\"x\"\"y\"\nlast,\"z\n\"\n";
+    let expected = [
+        vec![b"a".to_vec(), b"b\nc".to_vec()],
+        vec![b"0123456789\nabcdefghij".to_vec(), b"x\"y".to_vec()],
+        vec![b"last".to_vec(), b"z\n".to_vec()],
+    ];
+
+    for i in 0..1000 {
+        let mut rng = Rng(i);
+        let bufsz = rng.below(40) + 1;
+        let step = rng.below(6) + 1;
+        let rs = RawScan::new(Trickle::new(input, step).jitter(rng), bufsz, b'\n');
+        let mut csv = CsvReader::new(rs, b',');
+        let mut got = Vec::new();
+        loop {
+            match csv.read_record() {
+                CsvResult::Record(record) => {
+                    got.push(record.fields().map(Cow::into_owned).collect::<Vec<_>>());
+                }
+                CsvResult::WouldBlock => {}
+                CsvResult::Eof => break,
+                CsvResult::Err(e) => panic!("read failed: {}", e),
+            }
+        }
+        assert_eq!(got, expected, "bufsz {} step {}", bufsz, step);
+    }
+}
@@ -0,0 +1,145 @@
+// Paragraph mode, where records end at two consecutive delimiterbytes,
+// checked against a trivial paragraph splitter, over many small random
+// inputs, small buffers and small reads, for both RawScan and
+// SliceScan.
+
+mod common;
+
+use std::io::Read;
+
+use common::{Rng, Trickle};
+use rawscan::{RawScan, RawScanResult, SliceScan};
+
+// The paragraphs in "input", the slow and obvious way, each with
+// whether it ended at a blank line (true), or at end of input.
+fn expected_paragraphs(input: &[u8]) -> Vec<(bool, Vec<u8>)> {
+    let mut paragraphs = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < input.len() && input[i] == b'\n' {
+            i += 1;
+        }
+        if i == input.len() {
+            return paragraphs;
+        }
+        let begin = i;
+        match (begin + 1..input.len()).find(|&k| input[k] == b'\n' && input[k - 1] == b'\n') {
+            Some(k) => {
+                paragraphs.push((true, input[begin..=k].to_vec()));
+                i = k + 1;
+            }
+            None => {
+                paragraphs.push((false, input[begin..].to_vec()));
+                return paragraphs;
+            }
+        }
+    }
+}
+
+// Read all the paragraphs, putting chunked ones back together, and
+// checking that first chunks are at least min1stchunklen long.
+// Chunked paragraphs are reported as having ended at a blank line if
+// they end with two newlines.
+fn collect_paragraphs<R: Read>(rs: &mut RawScan<R>) -> Vec<(bool, Vec<u8>)> {
+    let min1st = rs.min1stchunklen();
+    let mut got = Vec::new();
+    let mut long: Option<Vec<u8>> = None;
+
+    loop {
+        match rs.getline() {
+            RawScanResult::FullLine(para) => got.push((true, para.to_vec())),
+            RawScanResult::FullLineWithoutEol(para) => got.push((false, para.to_vec())),
+            RawScanResult::StartLongline(chunk) => {
+                assert!(chunk.len() >= min1st, "short first chunk");
+                long = Some(chunk.to_vec());
+            }
+            RawScanResult::WithinLongline(chunk) => long.as_mut().unwrap().extend(chunk),
+            RawScanResult::LonglineEnded => {
+                let para = long.take().unwrap();
+                got.push((para.ends_with(b"\n\n"), para));
+            }
+            RawScanResult::Eof => return got,
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
+
+fn random_input(rng: &mut Rng) -> Vec<u8> {
+    (0..rng.below(60)).map(|_| b"ab\n\n\n"[rng.below(5)]).collect()
+}
+
+#[test]
+fn paragraphs() {
+    let mut rs = RawScan::new(&b"\n\nFrom: a\nTo: b\n\nbody\n\n\n\nlast\n"[..], 64, b'\n');
+    rs.set_paragraph_mode(true);
+    assert!(rs.paragraph_mode());
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"From: a\nTo: b\n\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"body\n\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLineWithoutEol(b"last\n")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+}
+
+#[test]
+fn long_paragraph_in_chunks() {
+    let mut rs = RawScan::new(&b"abc\ndef\n\nxy\n\n"[..], 4, b'\n');
+    rs.set_paragraph_mode(true);
+
+    assert!(matches!(rs.getline(), RawScanResult::StartLongline(b"abc\n")));
+    assert!(matches!(rs.getline(), RawScanResult::WithinLongline(b"def\n")));
+    assert!(matches!(rs.getline(), RawScanResult::WithinLongline(b"\n")));
+    assert!(matches!(rs.getline(), RawScanResult::LonglineEnded));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"xy\n\n")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+}
+
+#[test]
+fn switch_to_paragraphs_mid_stream() {
+    let mut rs = RawScan::new(&b"header\nmore\n\npara one\n\npara two"[..], 16, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"header\n")));
+    rs.set_paragraph_mode(true);
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"more\n\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"para one\n\n")));
+    rs.set_paragraph_mode(false);
+    assert!(matches!(rs.getline(), RawScanResult::FullLineWithoutEol(b"para two")));
+}
+
+#[test]
+fn random_paragraphs_small_buffers() {
+    let mut rng = Rng(5);
+
+    for _ in 0..5000 {
+        let input = random_input(&mut rng);
+        let bufsz = 1 + rng.below(16);
+        let min1st = 1 + rng.below(bufsz);
+        let step = 1 + rng.below(bufsz + 2);
+
+        let mut rs = RawScan::new(Trickle::new(&input, step), bufsz, b'\n');
+        rs.set_paragraph_mode(true);
+        rs.set_min1stchunklen(min1st).unwrap();
+        let got = collect_paragraphs(&mut rs);
+        let expected = expected_paragraphs(&input);
+
+        let context = format!(
+            "input {:?} bufsz {} min1stchunklen {} step {}",
+            String::from_utf8_lossy(&input),
+            bufsz,
+            min1st,
+            step
+        );
+        assert_eq!(got, expected, "{}", context);
+
+        let mut ss = SliceScan::new(&input, b'\n');
+        ss.set_paragraph_mode(true);
+        for (full, para) in &expected {
+            match ss.getline() {
+                RawScanResult::FullLine(p) => assert!(*full && p == &para[..], "{}", context),
+                RawScanResult::FullLineWithoutEol(p) => assert!(!*full && p == &para[..], "{}", context),
+                other => panic!("unexpected {:?}, {}", other, context),
+            }
+        }
+        assert!(matches!(ss.getline(), RawScanResult::Eof));
+    }
+}
@@ -0,0 +1,139 @@
+// ParallelScan, cutting many small random files into ranges of whole
+// lines, checking that the ranges cover each file exactly, and that
+// the lines scanned from the ranges, put back in range order, are the
+// lines of the file, in order.
+
+mod common;
+
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+
+use common::Rng;
+use rawscan::{ParallelScan, RawScanResult};
+
+// A temp file holding "input", removed when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str, input: &[u8]) -> io::Result<TempFile> {
+        let path = std::env::temp_dir().join(format!("rawscan-{}-{}", name, std::process::id()));
+        std::fs::write(&path, input)?;
+        Ok(TempFile(path))
+    }
+
+    fn open(&self) -> io::Result<File> {
+        File::open(&self.0)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn lines_of(input: &[u8]) -> Vec<Vec<u8>> {
+    input.split_inclusive(|&b| b == b'\n').map(<[u8]>::to_vec).collect()
+}
+
+// The whole lines in rs, putting long lines back together.
+fn all_lines<R: io::Read>(mut rs: rawscan::RawScan<R>) -> Vec<Vec<u8>> {
+    let mut lines: Vec<Vec<u8>> = Vec::new();
+    loop {
+        match rs.getline() {
+            RawScanResult::FullLine(line)
+            | RawScanResult::FullLineWithoutEol(line)
+            | RawScanResult::StartLongline(line) => lines.push(line.to_vec()),
+            RawScanResult::WithinLongline(line) => lines.last_mut().unwrap().extend(line),
+            RawScanResult::Eof => return lines,
+            RawScanResult::Err(e) => panic!("read failed: {}", e),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn ranges_of_whole_lines() -> io::Result<()> {
+    let file = TempFile::new("parallel-ranges", b"ab\ncd\nefghijklmnop\nq\n\nrs")?;
+    let scan = ParallelScan::new(file.open()?, 4, 8, b'\n')?;
+
+    assert_eq!(scan.ranges(), [0..6, 6..19, 19..24]);
+    assert!(ParallelScan::new(file.open()?, 0, 8, b'\n').is_err());
+
+    // No delimiterbytes: one range, however many are asked for.
+    let file = TempFile::new("parallel-one-line", b"abcdefgh")?;
+    let scan = ParallelScan::new(file.open()?, 3, 8, b'\n')?;
+    assert_eq!((scan.ranges().len(), scan.ranges()[0].clone()), (1, 0..8));
+
+    let file = TempFile::new("parallel-empty", b"")?;
+    let scan = ParallelScan::new(file.open()?, 3, 8, b'\n')?;
+    assert!(scan.ranges().is_empty());
+    assert!(scan.run(|_, rs| all_lines(rs)).is_empty());
+    Ok(())
+}
+
+#[test]
+fn random_files() -> io::Result<()> {
+    let mut rng = Rng(24);
+
+    for _ in 0..300 {
+        let input: Vec<u8> = (0..rng.below(400)).map(|_| b"ab\n"[rng.below(3)]).collect();
+        let nranges = rng.below(10) + 1;
+        let bufsz = rng.below(12) + 1;
+        let context =
+            format!("input {:?} nranges {} bufsz {}", String::from_utf8_lossy(&input), nranges, bufsz);
+
+        let file = TempFile::new("parallel-random", &input)?;
+        let scan = ParallelScan::new(file.open()?, nranges, bufsz, b'\n')?;
+        let ranges = scan.ranges();
+        assert!(ranges.len() <= nranges, "{}", context);
+        let mut at = 0;
+        for range in ranges {
+            assert_eq!(range.start, at, "{}", context);
+            assert!(range.end > range.start, "{}", context);
+            assert!(at == 0 || input[at as usize - 1] == b'\n', "{}", context);
+            at = range.end;
+        }
+        assert_eq!(at, input.len() as u64, "{}", context);
+
+        let per_range = scan.run(|i, rs| (i, all_lines(rs)));
+        assert!(per_range.iter().enumerate().all(|(i, (j, _))| i == *j), "{}", context);
+        let lines: Vec<Vec<u8>> = per_range.into_iter().flat_map(|(_, lines)| lines).collect();
+        assert_eq!(lines, lines_of(&input), "{}", context);
+
+        let mut out = Vec::new();
+        scan.filter_to(&mut out, |line| line.starts_with(b"a"))?;
+        let expected: Vec<u8> =
+            lines_of(&input).into_iter().filter(|line| line.starts_with(b"a")).flatten().collect();
+        assert_eq!(out, expected, "{}", context);
+    }
+    Ok(())
+}
+
+#[test]
+fn large_file_in_order() -> io::Result<()> {
+    let input: Vec<u8> = (0..200_000).flat_map(|i| format!("line {}\n", i).into_bytes()).collect();
+    let file = TempFile::new("parallel-large", &input)?;
+    let scan = ParallelScan::new(file.open()?, 8, 4096, b'\n')?;
+    assert_eq!(scan.ranges().len(), 8);
+
+    let mut out = Vec::new();
+    scan.filter_to(&mut out, |line| line.ends_with(b"7\n"))?;
+    let expected: Vec<u8> = (0..200_000)
+        .filter(|i| i % 10 == 7)
+        .flat_map(|i| format!("line {}\n", i).into_bytes())
+        .collect();
+    assert_eq!(out, expected);
+
+    // Offsets within a range, plus its start, are offsets in the file.
+    let starts = scan.run(|i, mut rs| {
+        rs.getline();
+        scan.ranges()[i].start + rs.line_offset()
+    });
+    assert_eq!(starts, scan.ranges().iter().map(|range| range.start).collect::<Vec<_>>());
+    for start in starts {
+        assert!(start == 0 || input[start as usize - 1] == b'\n');
+    }
+    Ok(())
+}
@@ -0,0 +1,208 @@
+// Byte offsets and line numbers from line_offset(), chunk_offset(),
+// line_number() and delimiter_count(), checked against the input
+// itself after every result, over many small random inputs, small
+// buffers and small reads, with and without paragraph mode, and with
+// random extend_record() and unget() calls mixed in.  Also seek() and
+// tell(), resuming scans part way through.
+
+mod common;
+
+use std::io::{Cursor, Read};
+
+use common::{Rng, Trickle};
+use rawscan::{RawScan, RawScanResult, SliceScan};
+
+fn delims(bytes: &[u8]) -> u64 {
+    bytes.iter().filter(|&&b| b == b'\n').count() as u64
+}
+
+#[test]
+fn lines_and_chunks() {
+    let mut rs = RawScan::new(&b"ab\ncdefghij\nk"[..], 4, b'\n');
+    let mut got = Vec::new();
+
+    loop {
+        let done = matches!(rs.getline(), RawScanResult::Eof);
+        got.push((rs.line_offset(), rs.chunk_offset(), rs.line_number(), rs.delimiter_count()));
+        if done {
+            break;
+        }
+    }
+    assert_eq!(
+        got,
+        [
+            (0, 0, 1, 1),       // "ab\n"
+            (3, 0, 2, 1),       // "cdef", StartLongline
+            (7, 4, 2, 1),       // "ghij"
+            (11, 8, 2, 2),      // "\n"
+            (12, 0, 3, 2),      // LonglineEnded
+            (12, 0, 3, 2),      // "k"
+            (13, 0, 3, 2),      // Eof
+        ]
+    );
+}
+
+#[test]
+fn slice_positions() {
+    let mut ss = SliceScan::new(b"one\n\n\ntwo\nthree", b'\n');
+
+    ss.getline();
+    assert_eq!((ss.line_offset(), ss.line_number(), ss.delimiter_count()), (0, 1, 1));
+    ss.set_paragraph_mode(true);
+    ss.getline();
+    assert_eq!((ss.line_offset(), ss.line_number(), ss.delimiter_count()), (6, 4, 4));
+    ss.unget(4).unwrap();
+    assert_eq!((ss.line_offset(), ss.line_number(), ss.delimiter_count()), (10, 5, 4));
+    ss.getline();
+    assert!(matches!(ss.getline(), RawScanResult::Eof));
+    assert_eq!((ss.line_offset(), ss.line_number(), ss.delimiter_count()), (15, 5, 4));
+}
+
+#[test]
+fn random_positions() {
+    let mut rng = Rng(18);
+
+    for _ in 0..3000 {
+        let input: Vec<u8> = (0..rng.below(80)).map(|_| b"ab\n\n"[rng.below(4)]).collect();
+        let bufsz = rng.below(12) + 1;
+        let step = rng.below(8) + 1;
+        let paragraph_mode = rng.below(3) == 0;
+
+        let mut rs = RawScan::new(Trickle::new(&input, step), bufsz, b'\n');
+        rs.set_min1stchunklen(rng.below(bufsz) + 1).unwrap();
+        rs.set_paragraph_mode(paragraph_mode);
+        let context = format!("input {:?} bufsz {} step {}", String::from_utf8_lossy(&input), bufsz, step);
+        let mut longline_offset = 0;
+
+        for _ in 0..400 {
+            let op = rng.below(8);
+            let result = match op {
+                0 => rs.extend_record(),
+                _ => rs.getline(),
+            };
+            let (line, starts_longline, within) = match result {
+                RawScanResult::FullLine(line) | RawScanResult::FullLineWithoutEol(line) => {
+                    (Some(line.to_vec()), false, false)
+                }
+                RawScanResult::StartLongline(line) => (Some(line.to_vec()), true, false),
+                RawScanResult::WithinLongline(line) => (Some(line.to_vec()), false, true),
+                RawScanResult::Eof => break,
+                _ => (None, false, false),
+            };
+
+            let off = rs.line_offset() as usize;
+            match &line {
+                Some(line) => {
+                    assert_eq!(&input[off..off + line.len()], &line[..], "{}", context);
+                    assert_eq!(rs.line_number(), delims(&input[..off]) + 1, "{}", context);
+                    assert_eq!(rs.delimiter_count(), delims(&input[..off + line.len()]), "{}", context);
+                    if starts_longline {
+                        longline_offset = off;
+                    }
+                    let chunk_offset = if within { off - longline_offset } else { 0 };
+                    assert_eq!(rs.chunk_offset() as usize, chunk_offset, "{}", context);
+                }
+                None => {
+                    assert_eq!(rs.line_number(), delims(&input[..off]) + 1, "{}", context);
+                    assert_eq!(rs.delimiter_count(), delims(&input[..off]), "{}", context);
+                }
+            }
+
+            if let (Some(line), 1) = (&line, op) {
+                let offset = rng.below(line.len() + 1);
+                rs.unget(offset).unwrap();
+                let off = off + offset;
+                assert_eq!(rs.line_offset() as usize, off, "{}", context);
+                assert_eq!(rs.delimiter_count(), delims(&input[..off]), "{}", context);
+            }
+        }
+    }
+}
+
+// All the lines left in rs, owned, along with their line numbers.
+fn rest<R: Read>(rs: &mut RawScan<R>) -> Vec<(Vec<u8>, u64)> {
+    let mut lines = Vec::new();
+    loop {
+        match rs.getline() {
+            RawScanResult::Eof => return lines,
+            RawScanResult::Err(e) => panic!("read failed: {}", e),
+            result => {
+                let line = result.line().map(<[u8]>::to_vec);
+                if let Some(line) = line {
+                    lines.push((line, rs.line_number()));
+                }
+            }
+        }
+    }
+}
+
+// The lines rs should return after seeking to "at": those of a new
+// stream reading just the input from there on.
+fn expected_from(input: &[u8], at: u64, bufsz: usize) -> Vec<(Vec<u8>, u64)> {
+    rest(&mut RawScan::new(&input[at as usize..], bufsz, b'\n'))
+}
+
+#[test]
+fn seek_and_tell() {
+    let input = b"one\ntwo\nthree is long\nfour\n";
+    let mut rs = RawScan::new(Cursor::new(&input[..]), 8, b'\n');
+
+    assert_eq!(rs.tell(), 0);
+    rs.getline();
+    assert_eq!(rs.tell(), 4);
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"two\n")));
+    assert!(matches!(rs.getline(), RawScanResult::StartLongline(b"three is")));
+    assert!(rs.in_longline());
+    assert_eq!(rs.tell(), 16);
+
+    assert_eq!(rs.seek(4).unwrap(), 4);
+    assert!(!rs.in_longline() && !rs.eof_seen());
+    assert!(rs.unget(0).is_err(), "nothing to hand back after seek");
+    assert_eq!((rs.tell(), rs.line_offset(), rs.line_number()), (4, 4, 1));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"two\n")));
+    assert_eq!(rest(&mut rs).len(), 3);
+    assert!(rs.eof_seen());
+
+    // Seek back after end of input.
+    rs.seek(22).unwrap();
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"four\n")));
+    assert_eq!((rs.line_offset(), rs.line_number(), rs.tell()), (22, 1, 27));
+
+    let mut ss = SliceScan::new(input, b'\n');
+    assert!(ss.seek(28).is_err());
+    assert_eq!(ss.seek(8).unwrap(), 8);
+    assert!(matches!(ss.getline(), RawScanResult::FullLine(b"three is long\n")));
+    assert_eq!((ss.tell(), ss.line_number()), (22, 1));
+}
+
+#[test]
+fn random_resumes() {
+    let mut rng = Rng(19);
+
+    for _ in 0..1000 {
+        let input: Vec<u8> = (0..rng.below(80)).map(|_| b"ab\n"[rng.below(3)]).collect();
+        let bufsz = rng.below(12) + 1;
+        let context = format!("input {:?} bufsz {}", String::from_utf8_lossy(&input), bufsz);
+
+        // Stop part way through, then resume from tell() in a new stream.
+        let mut rs = RawScan::new(Cursor::new(&input), bufsz, b'\n');
+        for _ in 0..rng.below(20) {
+            rs.getline();
+        }
+        let at = rs.tell();
+        let mut resumed = RawScan::new(Cursor::new(&input), bufsz, b'\n');
+        resumed.seek(at).unwrap();
+        assert_eq!(rest(&mut resumed), expected_from(&input, at, bufsz), "{} at {}", context, at);
+
+        // And resume in the same stream, from an earlier tell().
+        let mut rs = RawScan::new(Cursor::new(&input), bufsz, b'\n');
+        let mut offsets = vec![0];
+        for _ in 0..rng.below(20) {
+            rs.getline();
+            offsets.push(rs.tell());
+        }
+        let at = offsets[rng.below(offsets.len())];
+        rs.seek(at).unwrap();
+        assert_eq!(rest(&mut rs), expected_from(&input, at, bufsz), "{} at {}", context, at);
+    }
+}
@@ -0,0 +1,183 @@
+// Multiline records: RawScan::extend_record() and unget(), and the
+// RecordScanner built on them, checked against a trivial grouping of
+// the lines from a trivial line splitter, over many small random
+// inputs, small buffers and small reads.
+
+mod common;
+
+use std::io::Read;
+
+use common::{Rng, Trickle};
+use rawscan::{RawScan, RawScanResult, RecordScanner, ResultType};
+
+fn is_continuation(line: &[u8]) -> bool {
+    line.starts_with(b" ")
+}
+
+// Lines, some indented as continuation lines, some empty.
+fn random_input(rng: &mut Rng, nlines: usize, maxlen: usize, finaleol: bool) -> Vec<u8> {
+    let mut input = Vec::new();
+    for i in 0..nlines {
+        let len = rng.below(maxlen + 1);
+        input.extend((0..len).map(|_| b"ab  "[rng.below(4)]));
+        if i + 1 < nlines || finaleol {
+            input.push(b'\n');
+        }
+    }
+    input
+}
+
+// The records in "input", the slow and obvious way.
+fn expected_records(input: &[u8]) -> Vec<Vec<u8>> {
+    let mut records: Vec<Vec<u8>> = Vec::new();
+    for line in input.split_inclusive(|&b| b == b'\n') {
+        match records.last_mut() {
+            Some(record) if is_continuation(line) => record.extend(line),
+            _ => records.push(line.to_vec()),
+        }
+    }
+    records
+}
+
+// Read all the records, putting chunked records back together, and
+// checking that every record shorter than the buffer came whole.
+fn collect_records<R: Read>(records: &mut RecordScanner<R, fn(&[u8]) -> bool>, bufsz: usize) -> Vec<Vec<u8>> {
+    let mut got = Vec::new();
+    let mut long: Option<Vec<u8>> = None;
+
+    loop {
+        match records.getrecord() {
+            RawScanResult::FullLine(record) | RawScanResult::FullLineWithoutEol(record) => {
+                assert!(long.is_none());
+                got.push(record.to_vec());
+            }
+            RawScanResult::StartLongline(chunk) => {
+                assert!(long.is_none());
+                long = Some(chunk.to_vec());
+            }
+            RawScanResult::WithinLongline(chunk) => long.as_mut().unwrap().extend(chunk),
+            RawScanResult::LonglineEnded => {
+                let record = long.take().unwrap();
+                assert!(record.len() >= bufsz, "record of {} bytes chunked", record.len());
+                got.push(record);
+            }
+            RawScanResult::Eof => {
+                assert!(long.is_none());
+                return got;
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn extend_and_unget() {
+    let mut rs = RawScan::new(&b"one\n two\n three\nfour\n"[..], 32, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"one\n")));
+    assert!(matches!(rs.extend_record(), RawScanResult::FullLine(b"one\n two\n")));
+    assert!(matches!(rs.extend_record(), RawScanResult::FullLine(b"one\n two\n three\n")));
+    assert!(matches!(rs.extend_record(), RawScanResult::FullLine(b"one\n two\n three\nfour\n")));
+    rs.unget(16).unwrap();
+    assert!(rs.unget(0).is_err(), "second unget of the same line");
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"four\n")));
+
+    // Nothing left to append: the same record comes back again.
+    assert!(matches!(rs.extend_record(), RawScanResult::FullLine(b"four\n")));
+    assert!(rs.unget(6).is_err(), "offset past end of line");
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+    assert!(rs.unget(0).is_err(), "nothing to unget at eof");
+}
+
+#[test]
+fn extend_shifts_record_down() {
+    // "ab\n" lands at the top of the 8 byte buffer, so appending "cd\n"
+    // means shifting the record down first.
+    let input = b"xyz\nab\ncd\n";
+    let mut rs = RawScan::new(Trickle::new(input, 7), 8, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"xyz\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"ab\n")));
+    assert!(matches!(rs.extend_record(), RawScanResult::FullLine(b"ab\ncd\n")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+}
+
+#[test]
+fn extend_past_buffer_is_chunked() {
+    let mut rs = RawScan::new(&b"abc\ndefgh\nz\n"[..], 8, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"abc\n")));
+    assert!(matches!(rs.extend_record(), RawScanResult::StartLongline(b"abc\ndefg")));
+    assert!(matches!(rs.getline(), RawScanResult::WithinLongline(b"h\n")));
+    assert!(matches!(rs.getline(), RawScanResult::LonglineEnded));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"z\n")));
+}
+
+#[test]
+fn pause_while_extending() {
+    let input = b"xyz\nab\ncd\n";
+    let mut rs = RawScan::new(Trickle::new(input, 7), 8, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"xyz\n")));
+    rs.enable_pause();
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"ab\n")));
+    assert!(matches!(rs.extend_record(), RawScanResult::Paused));
+    rs.resume_from_pause();
+    assert!(matches!(rs.extend_record(), RawScanResult::FullLine(b"ab\ncd\n")));
+}
+
+#[test]
+fn record_scanner_pause_and_resume() {
+    let input = b"xyz\nab\n cd\n";
+    let rs = RawScan::new(Trickle::new(input, 7), 8, b'\n');
+    let mut records = RecordScanner::new(rs, is_continuation);
+
+    assert!(matches!(records.getrecord(), RawScanResult::FullLine(b"xyz\n")));
+    records.get_mut().enable_pause();
+    assert!(matches!(records.getrecord(), RawScanResult::Paused));
+    records.get_mut().resume_from_pause();
+    assert!(matches!(records.getrecord(), RawScanResult::FullLine(b"ab\n cd\n")));
+    assert!(matches!(records.getrecord(), RawScanResult::Eof));
+}
+
+#[test]
+fn random_records_small_buffers() {
+    let mut rng = Rng(11);
+
+    for _ in 0..3000 {
+        let nlines = rng.below(12);
+        let maxlen = rng.below(12);
+        let finaleol = rng.below(2) == 0;
+        let input = random_input(&mut rng, nlines, maxlen, finaleol);
+        let bufsz = 1 << (1 + rng.below(5));
+        let step = 1 + rng.below(bufsz + 2);
+
+        let rs = RawScan::new(Trickle::new(&input, step), bufsz, b'\n');
+        let mut records = RecordScanner::new(rs, is_continuation as fn(&[u8]) -> bool);
+        let got = collect_records(&mut records, bufsz);
+
+        assert_eq!(
+            got,
+            expected_records(&input),
+            "input {:?} bufsz {} step {}",
+            String::from_utf8_lossy(&input),
+            bufsz,
+            step
+        );
+    }
+}
+
+#[test]
+fn records_result_types() {
+    let rs = RawScan::new(&b"a\n b\nc"[..], 16, b'\n');
+    let mut records = RecordScanner::new(rs, is_continuation);
+    let mut kinds = Vec::new();
+    loop {
+        let kind = records.getrecord().result_type();
+        kinds.push(kind);
+        if kind == ResultType::Eof {
+            break;
+        }
+    }
+    assert_eq!(kinds, [ResultType::FullLine, ResultType::FullLineWithoutEol, ResultType::Eof]);
+}
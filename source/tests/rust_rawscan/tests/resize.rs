@@ -0,0 +1,91 @@
+// Resizing the buffer mid-stream, with RawScan::resize() and
+// replace_buffer(), checked against a trivial line splitter, over many
+// small random inputs, buffer sizes and reads.
+
+mod common;
+
+use common::{Rng, Trickle};
+use rawscan::{RawScan, RawScanResult};
+
+#[test]
+fn grow_keeps_place() {
+    let mut rs = RawScan::new(&b"ab\ncdefghij\nk\n"[..], 4, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"ab\n")));
+    rs.resize(16).unwrap();
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"cdefghij\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"k\n")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+}
+
+#[test]
+fn grow_within_long_line() {
+    let mut rs = RawScan::new(&b"abcdefghij\nk\n"[..], 4, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::StartLongline(b"abcd")));
+    rs.resize(16).unwrap();
+    assert!(matches!(rs.getline(), RawScanResult::WithinLongline(b"efghij\n")));
+    assert!(matches!(rs.getline(), RawScanResult::LonglineEnded));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"k\n")));
+}
+
+#[test]
+fn shrink_too_far_fails() {
+    let mut rs = RawScan::new(&b"a\nb\nc\n"[..], 16, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"a\n")));
+    assert!(rs.resize(3).is_err(), "4 bytes still buffered");
+    assert!(rs.resize(0).is_err());
+    assert_eq!(rs.min1stchunklen(), 16);
+    rs.resize(4).unwrap();
+    assert_eq!(rs.min1stchunklen(), 4);
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"b\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"c\n")));
+}
+
+#[test]
+fn replace_caller_buffer() {
+    let mut small = [0u8; 4];
+    let mut big = [0u8; 16];
+    let mut rs = RawScan::with_buffer(&b"abcdefghi\nj\n"[..], &mut small[..], b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::StartLongline(b"abcd")));
+    assert!(matches!(rs.getline(), RawScanResult::WithinLongline(b"efgh")));
+    assert!(rs.replace_buffer(&mut [][..]).is_err());
+    let old = rs.replace_buffer(&mut big[..]).unwrap();
+    assert_eq!(old.len(), 4);
+    assert!(matches!(rs.getline(), RawScanResult::WithinLongline(b"i\n")));
+    assert!(matches!(rs.getline(), RawScanResult::LonglineEnded));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"j\n")));
+}
+
+#[test]
+fn random_resizes() {
+    let mut rng = Rng(13);
+
+    for _ in 0..3000 {
+        let input: Vec<u8> = (0..rng.below(80)).map(|_| b"abc\n"[rng.below(4)]).collect();
+        let bufsz = 1 + rng.below(12);
+        let step = 1 + rng.below(bufsz + 2);
+        let mut rs = RawScan::new(Trickle::new(&input, step), bufsz, b'\n');
+
+        let mut got = Vec::new();
+        let mut long: Option<Vec<u8>> = None;
+        loop {
+            if rng.below(3) == 0 {
+                let _ = rs.resize(1 + rng.below(20));
+            }
+            match rs.getline() {
+                RawScanResult::FullLine(line) | RawScanResult::FullLineWithoutEol(line) => got.push(line.to_vec()),
+                RawScanResult::StartLongline(chunk) => long = Some(chunk.to_vec()),
+                RawScanResult::WithinLongline(chunk) => long.as_mut().unwrap().extend(chunk),
+                RawScanResult::LonglineEnded => got.push(long.take().unwrap()),
+                RawScanResult::Eof => break,
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+
+        let expected: Vec<Vec<u8>> = input.split_inclusive(|&b| b == b'\n').map(<[u8]>::to_vec).collect();
+        assert_eq!(got, expected, "input {:?} bufsz {} step {}", String::from_utf8_lossy(&input), bufsz, step);
+    }
+}
@@ -0,0 +1,155 @@
+// ReverseScan, reading many small random inputs backward, with many
+// buffer sizes, checking that the lines, long lines put back together
+// from their chunks, are the lines of the input, last line first, and
+// that line_offset() says where each came from.  Also a file cut
+// short while being read.
+
+mod common;
+
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+use common::{Rng, Trickle};
+use rawscan::{RawScanResult, ReverseScan};
+
+fn lines_of(input: &[u8]) -> Vec<Vec<u8>> {
+    input.split_inclusive(|&b| b == b'\n').map(<[u8]>::to_vec).collect()
+}
+
+// The whole lines in rs, last first, putting long lines back together
+// from their chunks, each chunk checked against the buffer size, and
+// each line's offset checked against "input".
+fn lines_backward<R>(mut rs: ReverseScan<R>, input: &[u8], context: &str) -> Vec<Vec<u8>>
+where
+    R: Read + Seek,
+{
+    let bufsz = rs.bufsz();
+    let mut lines: Vec<Vec<u8>> = Vec::new();
+    let mut long: Option<Vec<u8>> = None;
+
+    loop {
+        match rs.getline() {
+            RawScanResult::FullLine(line) | RawScanResult::FullLineWithoutEol(line) => {
+                assert!(long.is_none(), "{}: whole line within long line", context);
+                assert!(line.len() <= bufsz, "{}: line longer than buffer", context);
+                let line = line.to_vec();
+                let at = rs.line_offset() as usize;
+                assert_eq!(&input[at..at + line.len()], &line[..], "{}: line_offset", context);
+                lines.push(line);
+            }
+            RawScanResult::StartLongline(chunk) => {
+                assert!(long.is_none(), "{}: long line within long line", context);
+                assert_eq!(chunk.len(), bufsz, "{}: first chunk not full", context);
+                long = Some(chunk.to_vec());
+            }
+            RawScanResult::WithinLongline(chunk) => {
+                assert!(chunk.len() <= bufsz, "{}: chunk longer than buffer", context);
+                let mut line = chunk.to_vec();
+                line.extend(long.take().expect("chunk outside long line"));
+                long = Some(line);
+            }
+            RawScanResult::LonglineEnded => {
+                let line = long.take().expect("LonglineEnded outside long line");
+                let at = input.len() - lines_len(&lines) - line.len();
+                assert_eq!(rs.line_offset() as usize, at, "{}: long line offset", context);
+                lines.push(line);
+            }
+            RawScanResult::Eof => break,
+            other => panic!("{}: {:?}", context, other),
+        }
+        assert_eq!(rs.in_longline(), long.is_some(), "{}: in_longline", context);
+    }
+    assert!(matches!(rs.getline(), RawScanResult::Eof), "{}: Eof again", context);
+    assert_eq!(rs.line_offset(), 0, "{}: offset at Eof", context);
+    lines
+}
+
+fn lines_len(lines: &[Vec<u8>]) -> usize {
+    lines.iter().map(Vec::len).sum()
+}
+
+#[test]
+fn short_input() {
+    let mut rs = ReverseScan::new(Cursor::new(b"one\n\ntwo\nthree\n".to_vec()), 64, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"three\n")));
+    assert_eq!(rs.line_offset(), 9);
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"two\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"one\n")));
+    assert_eq!(rs.line_offset(), 0);
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+
+    let mut rs = ReverseScan::new(Cursor::new(Vec::new()), 64, b'\n');
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+}
+
+#[test]
+fn long_lines_last_chunk_first() {
+    let mut rs = ReverseScan::new(Cursor::new(b"ab\ncdefghij\nk".to_vec()), 4, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLineWithoutEol(b"k")));
+    assert!(matches!(rs.getline(), RawScanResult::StartLongline(b"hij\n")));
+    assert_eq!(rs.line_offset(), 8);
+    assert!(matches!(rs.getline(), RawScanResult::WithinLongline(b"defg")));
+    assert!(matches!(rs.getline(), RawScanResult::WithinLongline(b"c")));
+    assert!(matches!(rs.getline(), RawScanResult::LonglineEnded));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"ab\n")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+}
+
+#[test]
+fn random_inputs() {
+    let mut rng = Rng(25);
+
+    for round in 0..2000 {
+        let len = rng.below(200);
+        let input: Vec<u8> = (0..len).map(|_| b"abc\n"[rng.below(4)]).collect();
+        let bufsz = 1 + rng.below(24);
+        let step = 1 + rng.below(32);
+        let context = format!("round {} bufsz {} step {} input {:?}", round, bufsz, step, input);
+
+        let reader = Trickle::new(&input, step);
+        let mut got = lines_backward(ReverseScan::new(reader, bufsz, b'\n'), &input, &context);
+        got.reverse();
+        assert_eq!(got, lines_of(&input), "{}", context);
+    }
+}
+
+#[test]
+fn tail_n() -> io::Result<()> {
+    let input: Vec<u8> = (0..1000).flat_map(|i| format!("line {}\n", i).into_bytes()).collect();
+    let mut rs = ReverseScan::new(Cursor::new(input), 64, b'\n');
+
+    for _ in 0..10 {
+        assert!(matches!(rs.getline(), RawScanResult::FullLine(_)));
+    }
+    let at = rs.line_offset();
+    let mut reader = rs.into_inner();
+    reader.seek(SeekFrom::Start(at))?;
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail)?;
+
+    let want: Vec<u8> = (990..1000).flat_map(|i| format!("line {}\n", i).into_bytes()).collect();
+    assert_eq!(tail, want);
+    Ok(())
+}
+
+#[test]
+fn input_shrinks() -> io::Result<()> {
+    let path = std::env::temp_dir().join(format!("rawscan-reverse-shrink-{}", std::process::id()));
+    std::fs::write(&path, b"one\ntwo\nthree\nfour\n")?;
+    let mut rs = ReverseScan::new(std::fs::File::open(&path)?, 8, b'\n');
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"four\n")));
+    std::fs::OpenOptions::new().write(true).open(&path)?.set_len(4)?;
+    let result = loop {
+        match rs.getline() {
+            RawScanResult::Err(e) => break Ok(e.to_string()),
+            RawScanResult::Eof => break Err("no error"),
+            _ => {}
+        }
+    };
+    std::fs::remove_file(&path)?;
+    assert!(result.unwrap().contains("shrank"));
+    Ok(())
+}
@@ -0,0 +1,138 @@
+// RawScanSet, reading many pipes at once, written to a bit at a time
+// by another thread, checking that the lines from each pipe come back
+// whole and in order, however the results from the pipes interleave.
+
+mod common;
+
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+use common::Rng;
+use rawscan::{RawScanResult, RawScanSet};
+
+fn lines_of(input: &[u8]) -> Vec<Vec<u8>> {
+    input.split_inclusive(|&b| b == b'\n').map(<[u8]>::to_vec).collect()
+}
+
+#[test]
+fn many_pipes() -> io::Result<()> {
+    let mut rng = Rng(22);
+    let mut set = RawScanSet::new()?;
+    let mut writers = Vec::new();
+    let mut inputs = Vec::new();
+
+    for i in 0..100 {
+        let input: Vec<u8> = (0..rng.below(2000)).map(|_| b"ab\n"[rng.below(3)]).collect();
+        let (reader, writer) = io::pipe()?;
+        assert_eq!(set.add(reader, 16, b'\n')?, i);
+        writers.push(writer);
+        inputs.push(input);
+    }
+    assert_eq!(set.len(), 100);
+
+    // Write each input a few bytes at a time, in among all the others.
+    let writing = inputs.clone();
+    let writer = thread::spawn(move || -> io::Result<()> {
+        let mut left: Vec<&[u8]> = writing.iter().map(Vec::as_slice).collect();
+        let mut rng = Rng(23);
+        while left.iter().any(|input| !input.is_empty()) {
+            let i = rng.below(left.len());
+            let n = left[i].len().min(rng.below(40));
+            writers[i].write_all(&left[i][..n])?;
+            left[i] = &left[i][n..];
+            if rng.below(50) == 0 {
+                thread::sleep(Duration::from_micros(100));
+            }
+        }
+        Ok(())
+    });
+
+    let mut got: Vec<Vec<Vec<u8>>> = vec![Vec::new(); 100];
+    let mut ended = [false; 100];
+    while let Some((id, result)) = set.getline(None)? {
+        assert!(!ended[id], "result after end of stream {}", id);
+        match result {
+            RawScanResult::FullLine(line) | RawScanResult::FullLineWithoutEol(line) => {
+                got[id].push(line.to_vec());
+            }
+            RawScanResult::StartLongline(chunk) => got[id].push(chunk.to_vec()),
+            RawScanResult::WithinLongline(chunk) => got[id].last_mut().unwrap().extend(chunk),
+            RawScanResult::LonglineEnded => {}
+            RawScanResult::Eof => ended[id] = true,
+            other => panic!("stream {}: {:?}", id, other),
+        }
+    }
+    writer.join().unwrap()?;
+
+    assert!(set.is_empty());
+    assert!(ended.iter().all(|&ended| ended));
+    for (id, input) in inputs.iter().enumerate() {
+        assert_eq!(got[id], lines_of(input), "stream {}", id);
+    }
+    Ok(())
+}
+
+#[test]
+fn timeout_and_partial_lines() -> io::Result<()> {
+    let mut set = RawScanSet::new()?;
+    let (reader, mut writer) = io::pipe()?;
+    let id = set.add(reader, 64, b'\n')?;
+
+    assert!(set.getline(Some(Duration::from_millis(10)))?.is_none());
+    writer.write_all(b"ab")?;
+    assert!(set.getline(Some(Duration::from_millis(10)))?.is_none(), "partial line held back");
+    writer.write_all(b"c\nd")?;
+    let result = set.getline(Some(Duration::ZERO))?;
+    assert!(matches!(result, Some((i, RawScanResult::FullLine(b"abc\n"))) if i == id));
+    drop(writer);
+    assert!(matches!(set.getline(None)?, Some((_, RawScanResult::FullLineWithoutEol(b"d")))));
+    assert!(matches!(set.getline(None)?, Some((_, RawScanResult::Eof))));
+    assert!(set.getline(None)?.is_none());
+    Ok(())
+}
+
+#[test]
+fn busy_pipe_does_not_starve_others() -> io::Result<()> {
+    let mut set = RawScanSet::new()?;
+    let (busy, mut busy_writer) = io::pipe()?;
+    let (quiet, mut quiet_writer) = io::pipe()?;
+    set.add(busy, 4096, b'\n')?;
+    let quiet_id = set.add(quiet, 4096, b'\n')?;
+
+    busy_writer.write_all(&b"busy\n".repeat(4000))?;
+    quiet_writer.write_all(b"quiet\n")?;
+    let mut results = 0;
+    loop {
+        match set.getline(None)? {
+            Some((id, _)) if id == quiet_id => break,
+            Some(_) => results += 1,
+            None => panic!("no line from quiet pipe"),
+        }
+    }
+    assert!(results <= 2, "{} busy lines first", results);
+    Ok(())
+}
+
+#[test]
+fn remove_and_reuse_ids() -> io::Result<()> {
+    let mut set = RawScanSet::new()?;
+    let (first, mut first_writer) = io::pipe()?;
+    let (second, _second_writer) = io::pipe()?;
+    let first = set.add(first, 64, b'\n')?;
+    let second = set.add(second, 64, b'\n')?;
+
+    first_writer.write_all(b"one\ntwo\n")?;
+    assert!(matches!(set.getline(None)?, Some((id, RawScanResult::FullLine(b"one\n"))) if id == first));
+    let mut rs = set.remove(first).expect("first stream");
+    assert!(set.remove(first).is_none());
+    assert_eq!(set.len(), 1);
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"two\n")), "buffered line kept");
+
+    // The empty slot is taken by the next stream added.
+    let (third, _third_writer) = io::pipe()?;
+    assert_eq!(set.add(third, 64, b'\n')?, first);
+    assert!(set.get_mut(second).is_some());
+    assert!(set.getline(Some(Duration::ZERO))?.is_none());
+    Ok(())
+}
@@ -0,0 +1,464 @@
+//! Safe Rust wrapper over the C rawscan library in `rawscan-sys`.
+//!
+//! [`RawScan`] owns a C `RAWSCAN` stream, along with the file
+//! descriptor source it reads from, and returns the same
+//! [`RawScanResult`]s as the native Rust `rawscan` crate.
+//!
+//! In C, pause/resume is purely a calling convention: after enabling
+//! pause, the caller may keep using every line returned since the last
+//! resume, up until it calls `rs_resume_from_pause()`.  Here that
+//! convention is a type.  [`RawScan::pause_guard`] enables pausing and
+//! returns a [`PauseGuard`], and every line returned by
+//! [`PauseGuard::getline`] borrows that guard.  When the buffer must be
+//! recycled, `getline()` returns [`RawScanResult::Paused`] instead, and
+//! keeps returning it.  Dropping the guard calls
+//! `rs_resume_from_pause()`, and the compiler won't let the guard be
+//! dropped while any of its lines are still in use:
+//!
+//! ```compile_fail
+//! # use rawscan_ffi::RawScan;
+//! # let file = std::fs::File::open("/dev/null").unwrap();
+//! let mut rs = RawScan::open(file, 4096, b'\n').unwrap();
+//! let guard = rs.pause_guard();
+//! let line = guard.getline().line();
+//! drop(guard);                    // error: guard is still borrowed by line
+//! println!("{:?}", line);
+//! ```
+//!
+//! A [`SharedRawScan`] goes further, letting many threads take lines
+//! from one stream at once, each reading its lines in the buffer in
+//! parallel with the others, the buffer only being recycled once every
+//! thread is done with the lines it holds.
+
+use std::cell::{Cell, OnceCell};
+use std::convert::TryFrom;
+use std::io::{self, Read};
+use std::mem::ManuallyDrop;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::os::unix::io::AsRawFd;
+use std::ptr::{self, NonNull};
+use std::slice;
+use std::time::Duration;
+
+pub use rawscan::{RawScanResult, ResultType};
+pub use rawscan_sys::{RS_BUFFER_OVERHEAD, RS_BUFFER_SPACE, RS_SENTINEL_IN_BUFFER};
+use rawscan_sys::*;
+
+mod shared;
+
+pub use shared::{LineBatch, SharedLine, SharedRawScan};
+
+/// A C rawscan stream, reading from the file descriptor of `S`.
+pub struct RawScan<S> {
+    rsp: NonNull<RAWSCAN>,
+    err: OnceCell<io::Error>,   // rt_err's errnum, as returned
+    source: S,
+}
+
+// The C RAWSCAN stream has no ties to the thread that opened it.
+unsafe impl<S: Send> Send for RawScan<S> {}
+
+impl<S: AsRawFd> RawScan<S> {
+    /// Open a C rawscan stream on `source`'s file descriptor, with a
+    /// `bufsz` byte buffer, ending lines at each `delimiterbyte`.
+    pub fn open(source: S, bufsz: usize, delimiterbyte: u8) -> io::Result<RawScan<S>> {
+        let rsp = unsafe { rs_open(source.as_raw_fd(), bufsz, delimiterbyte as c_char) };
+
+        match NonNull::new(rsp) {
+            Some(rsp) => Ok(RawScan { rsp, err: OnceCell::new(), source }),
+            None => Err(io::Error::last_os_error()),
+        }
+    }
+
+    /// Open a C rawscan stream as [`RawScan::open`] does, with `flags`
+    /// such as [`RS_SENTINEL_IN_BUFFER`].  See the C `rs_open_flags()`.
+    pub fn open_flags(source: S, bufsz: usize, delimiterbyte: u8, flags: u32) -> io::Result<RawScan<S>> {
+        let rsp = unsafe { rs_open_flags(source.as_raw_fd(), bufsz, delimiterbyte as c_char, flags) };
+
+        match NonNull::new(rsp) {
+            Some(rsp) => Ok(RawScan { rsp, err: OnceCell::new(), source }),
+            None => Err(io::Error::last_os_error()),
+        }
+    }
+
+    /// Open a C rawscan stream on `source`'s file descriptor, entirely
+    /// within the caller supplied `mem`, allocating nothing.  The
+    /// buffer gets all but [`RS_BUFFER_OVERHEAD`] bytes of `mem`; see
+    /// the C `rs_open_with_buffer()`.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if `mem` has no room
+    /// for at least a one byte buffer.
+    pub fn open_with_buffer(
+        source: S,
+        mem: &'static mut [u8],
+        delimiterbyte: u8,
+    ) -> io::Result<RawScan<S>> {
+        let rsp = unsafe {
+            rs_open_with_buffer(
+                source.as_raw_fd(),
+                mem.as_mut_ptr().cast(),
+                mem.len(),
+                delimiterbyte as c_char,
+            )
+        };
+
+        match NonNull::new(rsp) {
+            Some(rsp) => Ok(RawScan { rsp, err: OnceCell::new(), source }),
+            None => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+impl<R: Read> RawScan<Box<R>> {
+    /// Open a C rawscan stream that gets its input from `reader`,
+    /// rather than from a file descriptor, using the C
+    /// `rs_set_read_fn()`.  Use a `Box<dyn Read>` to pick the reader
+    /// at runtime.
+    ///
+    /// Reads interrupted by a signal ([`io::ErrorKind::Interrupted`])
+    /// are retried; any other read error ends the input, as usual.  A
+    /// panic in `reader` can't unwind through the C code, and so aborts.
+    pub fn open_reader(reader: R, bufsz: usize, delimiterbyte: u8) -> io::Result<RawScan<Box<R>>> {
+        let mut source = Box::new(reader);
+        let rsp = unsafe { rs_open(-1, bufsz, delimiterbyte as c_char) };
+        let rsp = NonNull::new(rsp).ok_or_else(io::Error::last_os_error)?;
+
+        // The Box keeps the reader at one address for as long as the
+        // stream, which it outlives, so the C side can hold onto it.
+        let context: *mut R = &mut *source;
+        unsafe { rs_set_read_fn(rsp.as_ptr(), Some(read_fn::<R>), context.cast()) };
+
+        Ok(RawScan { rsp, err: OnceCell::new(), source })
+    }
+}
+
+// The rs_read_fn through which C rawscan streams from open_reader()
+// call their Rust reader.
+
+unsafe extern "C" fn read_fn<R: Read>(context: *mut c_void, buf: *mut c_void, count: usize) -> isize {
+    let reader = &mut *context.cast::<R>();
+    let buf = slice::from_raw_parts_mut(buf.cast::<u8>(), count);
+
+    loop {
+        match reader.read(buf) {
+            Ok(cnt) => return cnt as isize,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                let errnum = match e.kind() {
+                    io::ErrorKind::WouldBlock => libc::EAGAIN,
+                    _ => libc::EIO,
+                };
+                *libc::__errno_location() = e.raw_os_error().unwrap_or(errnum);
+                return -1;
+            }
+        }
+    }
+}
+
+impl<'a> RawScan<&'a [u8]> {
+    /// Open a C rawscan stream scanning the lines in `mem` in place,
+    /// without copying them, and without ever writing to `mem`; see
+    /// the C `rs_open_memory()`.  The returned results are those of a
+    /// stream reading `mem` with a buffer big enough to hold it all.
+    pub fn open_memory(mem: &'a [u8], delimiterbyte: u8) -> io::Result<RawScan<&'a [u8]>> {
+        let rsp = unsafe { rs_open_memory(mem.as_ptr().cast(), mem.len(), delimiterbyte as c_char) };
+
+        match NonNull::new(rsp) {
+            Some(rsp) => Ok(RawScan { rsp, err: OnceCell::new(), source: mem }),
+            None => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+impl<S> RawScan<S> {
+    /// Return the next line, or chunk of a long line.
+    ///
+    /// The returned line borrows the stream mutably, so no earlier
+    /// line can still be in use, and this never returns
+    /// [`RawScanResult::Paused`].  Use [`pause_guard`](Self::pause_guard)
+    /// to hold onto several lines at once.
+    pub fn getline(&mut self) -> RawScanResult<'_> {
+        loop {
+            let rt = unsafe { rs_getline(self.rsp.as_ptr()) };
+            if rt.type_ != rt_paused {
+                return self.to_result(rt);
+            }
+            // Paused by an earlier pause_guard(); nothing returned
+            // before this call can still be borrowed, so resume.
+            unsafe { rs_resume_from_pause(self.rsp.as_ptr()) };
+        }
+    }
+
+    /// Return the full line just returned, with the next line appended
+    /// to it, as one multiline record.  See the C `rs_extend_record()`.
+    pub fn extend_record(&mut self) -> RawScanResult<'_> {
+        loop {
+            let rt = unsafe { rs_extend_record(self.rsp.as_ptr()) };
+            if rt.type_ != rt_paused {
+                return self.to_result(rt);
+            }
+            // As in getline(), nothing can still be borrowed.  The C
+            // code left the record as it was, to be extended again.
+            unsafe { rs_resume_from_pause(self.rsp.as_ptr()) };
+        }
+    }
+
+    /// Hand back the line, or chunk, just returned, from `offset` bytes
+    /// into it on, to be returned again by the next `getline()`.  See
+    /// the C `rs_unget()`.
+    pub fn unget(&mut self, offset: usize) -> io::Result<()> {
+        if unsafe { rs_unget(self.rsp.as_ptr(), offset) } < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no line to unget, or offset past its end",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Resize the buffer to `new_bufsz` bytes, keeping our place in
+    /// the input.  Fails, changing nothing, for streams opened on
+    /// caller supplied memory, or if `new_bufsz` is too small for the
+    /// bytes not yet returned.  See the C `rs_resize()`.
+    pub fn resize(&mut self, new_bufsz: usize) -> io::Result<()> {
+        let rsp = unsafe { rs_resize(self.rsp.as_ptr(), new_bufsz) };
+        self.rsp = NonNull::new(rsp).ok_or_else(io::Error::last_os_error)?;
+        Ok(())
+    }
+
+    /// Enable pausing, and return a guard whose lines all stay valid
+    /// until the guard is dropped.
+    pub fn pause_guard(&mut self) -> PauseGuard<'_, S> {
+        unsafe {
+            rs_enable_pause(self.rsp.as_ptr());
+
+            // No line from before this call can still be borrowed, so
+            // let the first getline() below recycle the buffer.
+            rs_resume_from_pause(self.rsp.as_ptr());
+        }
+        PauseGuard { rs: self, fresh: Cell::new(true) }
+    }
+
+    /// End lines at `delimiterbyte` from here on, keeping whatever is
+    /// already buffered.  See the C `rs_set_delimiterbyte()`.
+    pub fn set_delimiterbyte(&mut self, delimiterbyte: u8) -> io::Result<()> {
+        if unsafe { rs_set_delimiterbyte(self.rsp.as_ptr(), delimiterbyte as c_char) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// The delimiterbyte that ends each line.
+    pub fn delimiterbyte(&self) -> u8 {
+        unsafe { rs_get_delimiterbyte(self.rsp.as_ptr()) as u8 }
+    }
+
+    /// Return paragraphs, ending at two consecutive delimiterbytes,
+    /// rather than lines.  See the C `rs_set_paragraph_mode()`.
+    pub fn set_paragraph_mode(&mut self, paragraph_mode: bool) {
+        unsafe { rs_set_paragraph_mode(self.rsp.as_ptr(), paragraph_mode) }
+    }
+
+    /// Whether paragraph mode is on.
+    pub fn paragraph_mode(&self) -> bool {
+        unsafe { rs_get_paragraph_mode(self.rsp.as_ptr()) }
+    }
+
+    /// Set the guaranteed minimum length of full lines and of the
+    /// first chunk of long lines.  Fails if greater than the buffer
+    /// size.  See the C `rs_set_min1stchunklen()`.
+    pub fn set_min1stchunklen(&mut self, min1stchunklen: usize) -> io::Result<()> {
+        if unsafe { rs_set_min1stchunklen(self.rsp.as_ptr(), min1stchunklen) } < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "min1stchunklen larger than rawscan buffer size",
+            ));
+        }
+        Ok(())
+    }
+
+    /// The current min1stchunklen, which defaults to the buffer size.
+    pub fn min1stchunklen(&self) -> usize {
+        unsafe { rs_get_min1stchunklen(self.rsp.as_ptr()) }
+    }
+
+    /// The number of bytes read into the buffer, but not yet returned.
+    /// See the C `rs_get_buffered_bytes()`.
+    pub fn buffered_bytes(&self) -> usize {
+        unsafe { rs_get_buffered_bytes(self.rsp.as_ptr()) }
+    }
+
+    /// The size of the buffer.  See the C `rs_get_bufsz()`.
+    pub fn bufsz(&self) -> usize {
+        unsafe { rs_get_bufsz(self.rsp.as_ptr()) }
+    }
+
+    /// Whether end of input has been seen.  See the C `rs_eof_seen()`.
+    pub fn eof_seen(&self) -> bool {
+        unsafe { rs_eof_seen(self.rsp.as_ptr()) }
+    }
+
+    /// Whether a read error has been seen.  See the C `rs_err_seen()`.
+    pub fn err_seen(&self) -> bool {
+        unsafe { rs_err_seen(self.rsp.as_ptr()) }
+    }
+
+    /// Whether in the middle of returning a long line in chunks.  See
+    /// the C `rs_in_longline()`.
+    pub fn in_longline(&self) -> bool {
+        unsafe { rs_in_longline(self.rsp.as_ptr()) }
+    }
+
+    /// The input byte offset of the line last returned, else of the
+    /// next byte to be returned.  See the C `rs_get_line_offset()`.
+    pub fn line_offset(&self) -> u64 {
+        unsafe { rs_get_line_offset(self.rsp.as_ptr()) as u64 }
+    }
+
+    /// The offset of the chunk last returned within its long line, or
+    /// 0.  See the C `rs_get_chunk_offset()`.
+    pub fn chunk_offset(&self) -> u64 {
+        unsafe { rs_get_chunk_offset(self.rsp.as_ptr()) as u64 }
+    }
+
+    /// The line number, from 1, of the line last returned, else of the
+    /// next line.  See the C `rs_get_line_number()`.
+    pub fn line_number(&self) -> u64 {
+        unsafe { rs_get_line_number(self.rsp.as_ptr()) }
+    }
+
+    /// The number of delimiterbytes before the next byte to be
+    /// returned.  See the C `rs_get_delimiter_count()`.
+    pub fn delimiter_count(&self) -> u64 {
+        unsafe { rs_get_delimiter_count(self.rsp.as_ptr()) }
+    }
+
+    /// Seek the file descriptor to `offset`, discarding the buffer,
+    /// so that the next `getline()` reads from there.  Returns the new
+    /// offset.  See the C `rs_seek()`.
+    pub fn seek(&mut self, offset: u64) -> io::Result<u64> {
+        let offset = off_t::try_from(offset)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "offset too large for off_t"))?;
+        let offset = unsafe { rs_seek(self.rsp.as_ptr(), offset) };
+        if offset < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(offset as u64)
+    }
+
+    /// Follow the input, as `tail -f` does, sleeping `interval`, in
+    /// whole milliseconds, at least one, at end of input, and reading
+    /// again.  `None` turns following off.  See the C `rs_set_follow()`.
+    pub fn set_follow(&mut self, interval: Option<Duration>) {
+        let interval_ms = interval.map_or(0, |interval| interval.as_millis().clamp(1, c_uint::MAX as u128));
+        unsafe { rs_set_follow(self.rsp.as_ptr(), interval_ms as c_uint) }
+    }
+
+    /// The follow interval, if following.  See the C `rs_get_follow()`.
+    pub fn follow(&self) -> Option<Duration> {
+        match unsafe { rs_get_follow(self.rsp.as_ptr()) } {
+            0 => None,
+            interval_ms => Some(Duration::from_millis(interval_ms.into())),
+        }
+    }
+
+    /// The input byte offset of the next byte to be returned.  See the
+    /// C `rs_tell()`.
+    pub fn tell(&self) -> u64 {
+        unsafe { rs_tell(self.rsp.as_ptr()) as u64 }
+    }
+
+    /// Gets a reference to the underlying source.
+    pub fn get_ref(&self) -> &S {
+        &self.source
+    }
+
+    /// Close the C stream, returning the underlying source.
+    pub fn into_inner(self) -> S {
+        let mut this = ManuallyDrop::new(self);
+        unsafe {
+            rs_close(this.rsp.as_ptr());
+            ptr::drop_in_place(&mut this.err);
+            ptr::read(&this.source)
+        }
+    }
+
+    #[allow(non_upper_case_globals)]
+    fn to_result(&self, rt: RAWSCAN_RESULT) -> RawScanResult<'_> {
+        let line = || unsafe {
+            let len = rt.u.line.end.offset_from(rt.u.line.begin) as usize + 1;
+            slice::from_raw_parts(rt.u.line.begin as *const u8, len)
+        };
+
+        match rt.type_ {
+            rt_full_line => RawScanResult::FullLine(line()),
+            rt_full_line_without_eol => RawScanResult::FullLineWithoutEol(line()),
+            rt_start_longline => RawScanResult::StartLongline(line()),
+            rt_within_longline => RawScanResult::WithinLongline(line()),
+            rt_longline_ended => RawScanResult::LonglineEnded,
+            rt_paused => RawScanResult::Paused,
+            rt_eof => RawScanResult::Eof,
+            rt_would_block => RawScanResult::WouldBlock,
+            _ => {
+                let errnum = unsafe { rt.u.errnum };
+                RawScanResult::Err(self.err.get_or_init(|| io::Error::from_raw_os_error(errnum)))
+            }
+        }
+    }
+}
+
+impl<S> Drop for RawScan<S> {
+    fn drop(&mut self) {
+        unsafe { rs_close(self.rsp.as_ptr()) };
+    }
+}
+
+/// Scope within which every line returned stays valid.
+///
+/// Returned by [`RawScan::pause_guard`].  Once the buffer has no more
+/// room without overwriting lines already returned, `getline()` keeps
+/// returning [`RawScanResult::Paused`].  Dropping the guard calls
+/// `rs_resume_from_pause()`, so that a new guard can continue.
+pub struct PauseGuard<'a, S> {
+    rs: &'a mut RawScan<S>,
+    fresh: Cell<bool>,      // no getline() yet in this guard
+}
+
+impl<'a, S> PauseGuard<'a, S> {
+    /// Return the next line, or chunk of a long line, which remains
+    /// valid for as long as this guard.
+    pub fn getline(&self) -> RawScanResult<'_> {
+        let rsp = self.rs.rsp.as_ptr();
+        let rt = unsafe { rs_getline(rsp) };
+
+        if self.fresh.replace(false) {
+            // pause_guard() let this first call recycle the buffer.
+            // If it didn't need to,
+            // that resume is still latched; clear it, so that the lines
+            // now being returned can't be overwritten until we're gone.
+            unsafe {
+                rs_disable_pause(rsp);
+                rs_enable_pause(rsp);
+            }
+        }
+
+        self.rs.to_result(rt)
+    }
+
+    /// Whether `getline()` has returned `Paused`, so that no more lines
+    /// will come until this guard is dropped.  See the C `rs_is_paused()`.
+    pub fn is_paused(&self) -> bool {
+        unsafe { rs_is_paused(self.rs.rsp.as_ptr()) }
+    }
+
+    /// The underlying stream, as to observe its state.
+    pub fn get_ref(&self) -> &RawScan<S> {
+        self.rs
+    }
+}
+
+impl<'a, S> Drop for PauseGuard<'a, S> {
+    fn drop(&mut self) {
+        unsafe { rs_resume_from_pause(self.rs.rsp.as_ptr()) };
+    }
+}
@@ -0,0 +1,303 @@
+// SharedRawScan, the multi-threading wrapper that the long comment
+// near the top of rawscan_static.h anticipates: rs_getline() calls
+// serialized under a mutex, with pause enabled, so that the lines
+// already handed out to other threads can be read in parallel, right
+// in the buffer, while more lines are being scanned.
+//
+// Whenever rs_getline() returns rt_paused, because the buffer must be
+// recycled, the thread that got the rt_paused waits until every line
+// handed out in place has been dropped, then resumes.  Other threads
+// wanting lines meanwhile wait too, as they do while one thread is
+// copying out all the chunks of a line too long for the buffer, so
+// that no other thread is handed a chunk from the middle of it.
+
+use std::io;
+use std::ops::Deref;
+use std::slice;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+use rawscan_sys::*;
+
+use crate::RawScan;
+
+struct State<S> {
+    rs: RawScan<S>,
+    lines_out: usize,       // SharedLines in the buffer not yet dropped
+    refilling: bool,        // some thread is waiting to refill, or is
+                            // copying out a long line: others wait
+    long: Option<Vec<u8>>,  // long line chunks copied out so far
+}
+
+/// A C rawscan stream that many threads can read lines from at once.
+///
+/// Lines come either one at a time, from [`getline()`], as a
+/// [`SharedLine`] that reads the line right in the stream's buffer,
+/// or in batches, from [`getbatch()`], copied out to a [`LineBatch`]
+/// the calling thread owns.  Each line goes to just one thread, so
+/// which thread gets which line, and in what order threads finish
+/// with them, is up to the scheduler.  Lines too long for the buffer
+/// are copied out whole, rather than returned in chunks.
+///
+/// The buffer can't be recycled while any `SharedLine` is still in use,
+/// so a thread must drop any `SharedLine` it holds before asking for
+/// more lines, or it may wait forever, for itself.
+///
+/// ```
+/// use std::io::Write;
+/// use rawscan_ffi::{RawScan, SharedRawScan};
+///
+/// let (reader, mut writer) = std::io::pipe()?;
+/// writer.write_all(b"one\ntwo\nthree\n")?;
+/// drop(writer);
+///
+/// let shared = SharedRawScan::new(RawScan::open(reader, 4096, b'\n')?);
+/// let total: usize = std::thread::scope(|scope| {
+///     let workers: Vec<_> = (0..4)
+///         .map(|_| {
+///             scope.spawn(|| {
+///                 let mut bytes = 0;
+///                 while let Some(line) = shared.getline().unwrap() {
+///                     bytes += line.len();
+///                 }
+///                 bytes
+///             })
+///         })
+///         .collect();
+///     workers.into_iter().map(|worker| worker.join().unwrap()).sum()
+/// });
+/// assert_eq!(total, 14);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// [`getline()`]: SharedRawScan::getline
+/// [`getbatch()`]: SharedRawScan::getbatch
+pub struct SharedRawScan<S> {
+    state: Mutex<State<S>>,
+    changed: Condvar,       // lines_out reached 0, or refilling ended
+}
+
+// What next_line() found.
+
+enum Next {
+    InBuffer(*const u8, usize),
+    Copied(Vec<u8>),
+    Eof,
+    Err(io::Error),
+}
+
+impl<S> SharedRawScan<S> {
+    /// Share `rs` among threads, enabling pause on it.
+    pub fn new(rs: RawScan<S>) -> SharedRawScan<S> {
+        unsafe { rs_enable_pause(rs.rsp.as_ptr()) };
+        SharedRawScan {
+            state: Mutex::new(State { rs, lines_out: 0, refilling: false, long: None }),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Return the next line, including its delimiterbyte, if any, for
+    /// this thread alone, or `None` at end of input.  Fails if a read
+    /// fails, as will every call after, except for an error of kind
+    /// `WouldBlock`, from a non-blocking input with nothing more ready
+    /// yet, which passes: call again once there's more input, and
+    /// scanning picks up where it left off, even within a long line.
+    pub fn getline(&self) -> io::Result<Option<SharedLine<'_, S>>> {
+        let (mut state, next) = self.next_line(self.lock());
+
+        let bytes = match next {
+            Next::InBuffer(begin, len) => {
+                state.lines_out += 1;
+                Bytes::InBuffer(begin, len)
+            }
+            Next::Copied(line) => Bytes::Copied(line),
+            Next::Eof => return Ok(None),
+            Next::Err(e) => return Err(e),
+        };
+        Ok(Some(SharedLine { shared: self, bytes }))
+    }
+
+    /// Return up to `max_lines` lines, copied out for this thread
+    /// alone, taking the lock just once.  Returns an empty batch at end
+    /// of input.  Fails if a read fails before any lines are copied;
+    /// if it fails after, the lines so far are returned, and the next
+    /// call fails, unless, as for [`getline()`], it's a `WouldBlock`
+    /// that passes once there's more input.
+    ///
+    /// [`getline()`]: SharedRawScan::getline
+    pub fn getbatch(&self, max_lines: usize) -> io::Result<LineBatch> {
+        let mut batch = LineBatch { bytes: Vec::new(), ends: Vec::new() };
+        let mut state = self.lock();
+
+        while batch.len() < max_lines {
+            let (relocked, next) = self.next_line(state);
+            state = relocked;
+            match next {
+                Next::InBuffer(begin, len) => {
+                    batch.bytes.extend_from_slice(unsafe { slice::from_raw_parts(begin, len) });
+                }
+                Next::Copied(line) => batch.bytes.extend_from_slice(&line),
+                Next::Eof => break,
+                Next::Err(e) if batch.is_empty() => return Err(e),
+                Next::Err(_) => break,
+            }
+            batch.ends.push(batch.bytes.len());
+        }
+        Ok(batch)
+    }
+
+    /// Unwraps this `SharedRawScan`, returning the stream, with pause
+    /// disabled again.
+    pub fn into_inner(self) -> RawScan<S> {
+        let state = self.state.into_inner().unwrap_or_else(PoisonError::into_inner);
+        unsafe { rs_disable_pause(state.rs.rsp.as_ptr()) };
+        state.rs
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<S>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait<'a>(&self, state: MutexGuard<'a, State<S>>) -> MutexGuard<'a, State<S>> {
+        self.changed.wait(state).unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Scan the next line, holding the lock, except while waiting our
+    // turn, or for the lines in the buffer to be dropped.
+
+    #[allow(non_upper_case_globals)]
+    fn next_line<'a>(
+        &self,
+        mut state: MutexGuard<'a, State<S>>,
+    ) -> (MutexGuard<'a, State<S>>, Next) {
+        while state.refilling {
+            state = self.wait(state);
+        }
+
+        let rsp = state.rs.rsp.as_ptr();
+        let mut resumed = false;
+
+        loop {
+            let rt = unsafe { rs_getline(rsp) };
+            if resumed {
+                // As in PauseGuard::getline(): if that didn't recycle
+                // the buffer, the resume is still latched; clear it, so
+                // that the lines now being handed out can't be
+                // overwritten until they're dropped.
+                unsafe {
+                    rs_disable_pause(rsp);
+                    rs_enable_pause(rsp);
+                }
+                resumed = false;
+            }
+            let line = || unsafe {
+                let len = rt.u.line.end.offset_from(rt.u.line.begin) as usize + 1;
+                (rt.u.line.begin as *const u8, len)
+            };
+
+            let next = match rt.type_ {
+                rt_full_line | rt_full_line_without_eol => {
+                    let (begin, len) = line();
+                    Next::InBuffer(begin, len)
+                }
+                rt_start_longline | rt_within_longline => {
+                    // Copy it all out, keeping other threads waiting.
+                    let (begin, len) = line();
+                    let chunk = unsafe { slice::from_raw_parts(begin, len) };
+                    state.long.get_or_insert_with(Vec::new).extend_from_slice(chunk);
+                    state.refilling = true;
+                    continue;
+                }
+                rt_paused => {
+                    state.refilling = true;
+                    while state.lines_out > 0 {
+                        state = self.wait(state);
+                    }
+                    unsafe { rs_resume_from_pause(rsp) };
+                    resumed = true;
+                    continue;
+                }
+                rt_longline_ended => match state.long.take() {
+                    Some(line) => Next::Copied(line),
+                    None => continue,
+                },
+                rt_eof => Next::Eof,
+                rt_would_block => Next::Err(io::ErrorKind::WouldBlock.into()),
+                _ => Next::Err(io::Error::from_raw_os_error(unsafe { rt.u.errnum })),
+            };
+
+            if state.refilling {
+                state.refilling = false;
+                self.changed.notify_all();
+            }
+            return (state, next);
+        }
+    }
+}
+
+enum Bytes {
+    InBuffer(*const u8, usize),
+    Copied(Vec<u8>),
+}
+
+/// One line from a [`SharedRawScan`], which derefs to the line's
+/// bytes, including its delimiterbyte, if any.  Lines that fit in the
+/// buffer are read right there, holding off the buffer's refill until
+/// dropped.
+pub struct SharedLine<'a, S> {
+    shared: &'a SharedRawScan<S>,
+    bytes: Bytes,
+}
+
+// The line's bytes are only read, and only written again after it's
+// dropped, so it can go wherever the SharedRawScan can.
+unsafe impl<'a, S: Send> Send for SharedLine<'a, S> {}
+unsafe impl<'a, S: Send> Sync for SharedLine<'a, S> {}
+
+impl<'a, S> Deref for SharedLine<'a, S> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.bytes {
+            Bytes::InBuffer(begin, len) => unsafe { slice::from_raw_parts(*begin, *len) },
+            Bytes::Copied(line) => line,
+        }
+    }
+}
+
+impl<'a, S> Drop for SharedLine<'a, S> {
+    fn drop(&mut self) {
+        if let Bytes::InBuffer(..) = self.bytes {
+            let mut state = self.shared.lock();
+            state.lines_out -= 1;
+            if state.lines_out == 0 {
+                self.shared.changed.notify_all();
+            }
+        }
+    }
+}
+
+/// Consecutive lines from a [`SharedRawScan`], copied out together.
+#[derive(Clone, Debug, Default)]
+pub struct LineBatch {
+    bytes: Vec<u8>,
+    ends: Vec<usize>,       // offset just past each line in bytes
+}
+
+impl LineBatch {
+    /// The number of lines.
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    /// Whether there are no lines, as at end of input.
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// The lines, in input order, each including its delimiterbyte,
+    /// if any.
+    pub fn lines(&self) -> impl Iterator<Item = &[u8]> {
+        let begins = std::iter::once(0).chain(self.ends.iter().copied());
+        begins.zip(self.ends.iter()).map(move |(begin, &end)| &self.bytes[begin..end])
+    }
+}
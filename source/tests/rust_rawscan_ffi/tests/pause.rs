@@ -0,0 +1,335 @@
+// Exercise the safe C rawscan wrapper, especially holding many lines
+// at once within a PauseGuard.
+
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use rawscan_ffi::{RawScan, RawScanResult, ResultType, RS_BUFFER_OVERHEAD, RS_BUFFER_SPACE, RS_SENTINEL_IN_BUFFER};
+
+fn pipe_with(input: &[u8]) -> io::Result<io::PipeReader> {
+    let (reader, mut writer) = io::pipe()?;
+    writer.write_all(input)?;
+    Ok(reader)
+}
+
+fn numbered_lines(n: usize) -> Vec<u8> {
+    (0..n).flat_map(|i| format!("line {}\n", i).into_bytes()).collect()
+}
+
+#[test]
+fn plain_getline() -> io::Result<()> {
+    let mut rs = RawScan::open(pipe_with(b"abc\nxyz\nabcdef")?, 16, b'\n')?;
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"abc\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"xyz\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLineWithoutEol(b"abcdef")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+
+    assert!(rs.set_min1stchunklen(17).is_err());
+    rs.set_min1stchunklen(4)?;
+    assert_eq!(rs.min1stchunklen(), 4);
+    Ok(())
+}
+
+#[test]
+fn guard_keeps_lines_valid_until_paused() -> io::Result<()> {
+    let input = numbered_lines(200);
+    let mut rs = RawScan::open(pipe_with(&input)?, 64, b'\n')?;
+
+    let mut output = Vec::new();
+    let mut guards = 0;
+    let mut eof = false;
+
+    while !eof {
+        let guard = rs.pause_guard();
+        let mut held = Vec::new();
+        guards += 1;
+
+        loop {
+            match guard.getline() {
+                RawScanResult::FullLine(line) => held.push(line),
+                RawScanResult::Paused => {
+                    assert!(guard.is_paused());
+                    assert!(guard.get_ref().buffered_bytes() < 64);
+                    break;
+                }
+                RawScanResult::Eof => {
+                    eof = true;
+                    break;
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+
+        // Every line held since this guard began is still intact.
+        for line in &held {
+            assert!(line.starts_with(b"line ") && line.ends_with(b"\n"));
+            output.extend_from_slice(line);
+        }
+    }
+
+    assert_eq!(output, input);
+    assert!(guards > 10);
+    Ok(())
+}
+
+#[test]
+fn getline_after_guard_resumes() -> io::Result<()> {
+    let input = numbered_lines(50);
+    let mut rs = RawScan::open(pipe_with(&input)?, 32, b'\n')?;
+
+    let mut output = Vec::new();
+    {
+        let guard = rs.pause_guard();
+        while let Some(line) = guard.getline().line() {
+            output.extend_from_slice(line);
+        }
+    }
+    loop {
+        match rs.getline() {
+            RawScanResult::FullLine(line) => output.extend_from_slice(line),
+            RawScanResult::Eof => break,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    assert_eq!(output, input);
+    Ok(())
+}
+
+#[test]
+fn sentinel_in_buffer() -> io::Result<()> {
+    let mut rs = RawScan::open_flags(pipe_with(b"abc\ndefghij\n\0x\0")?, 8, b'\n', RS_SENTINEL_IN_BUFFER)?;
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"abc\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"defghij\n")));
+    rs.set_delimiterbyte(b'\0')?;
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"\0")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"x\0")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+
+    assert!(RawScan::open_flags(pipe_with(b"")?, 8, b'\n', 0x80).is_err());
+    Ok(())
+}
+
+#[test]
+fn stream_state() -> io::Result<()> {
+    let mut rs = RawScan::open(pipe_with(b"ab\ncdefg\nh")?, 4, b'\n')?;
+
+    assert_eq!((rs.buffered_bytes(), rs.bufsz()), (0, 4));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"ab\n")));
+    assert_eq!(rs.buffered_bytes(), 1);
+    assert_eq!((rs.line_offset(), rs.line_number(), rs.delimiter_count()), (0, 1, 1));
+    assert!(matches!(rs.getline(), RawScanResult::StartLongline(b"cdef")));
+    assert!(rs.in_longline());
+    assert!(matches!(rs.getline(), RawScanResult::WithinLongline(b"g\n")));
+    assert_eq!((rs.line_offset(), rs.chunk_offset(), rs.line_number()), (7, 4, 2));
+    while rs.getline().result_type() != ResultType::Eof {}
+    assert!(!rs.in_longline());
+    assert!(rs.eof_seen());
+    assert!(!rs.err_seen());
+
+    let rs = RawScan::open_memory(&b"abc\n"[..], b'\n')?;
+    assert_eq!((rs.buffered_bytes(), rs.bufsz()), (4, 4));
+    assert!(rs.eof_seen());
+    Ok(())
+}
+
+#[test]
+fn caller_supplied_memory() -> io::Result<()> {
+    let mem: &'static mut [u8] = Box::leak(vec![0u8; RS_BUFFER_SPACE(16)].into_boxed_slice());
+    let mut rs = RawScan::open_with_buffer(pipe_with(b"abc\n0123456789abcdef\n")?, mem, b'\n')?;
+
+    assert_eq!(rs.min1stchunklen(), 16);
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"abc\n")));
+    assert!(matches!(rs.getline(), RawScanResult::StartLongline(b"0123456789abcdef")));
+    assert!(matches!(rs.getline(), RawScanResult::WithinLongline(b"\n")));
+    assert!(matches!(rs.getline(), RawScanResult::LonglineEnded));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+
+    let short: &'static mut [u8] = Box::leak(vec![0u8; RS_BUFFER_OVERHEAD].into_boxed_slice());
+    let err = RawScan::open_with_buffer(pipe_with(b"")?, short, b'\n').err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    Ok(())
+}
+
+#[test]
+fn scan_memory_in_place() -> io::Result<()> {
+    let input = b"abc\n\nxyz".to_vec();
+    let mut rs = RawScan::open_memory(&input, b'\n')?;
+
+    match rs.getline() {
+        RawScanResult::FullLine(line) => assert_eq!(line.as_ptr(), input.as_ptr()),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLineWithoutEol(b"xyz")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+    assert_eq!(rs.into_inner(), &input[..]);
+    Ok(())
+}
+
+#[test]
+fn read_from_rust_reader() -> io::Result<()> {
+    let input = numbered_lines(100);
+    let reader: Box<dyn io::Read> = Box::new(&input[..]);
+    let mut rs = RawScan::open_reader(reader, 16, b'\n')?;
+
+    let mut output = Vec::new();
+    while let Some(line) = rs.getline().line() {
+        output.extend_from_slice(line);
+    }
+    assert_eq!(output, input);
+
+    // Errors without an errno come through as EIO.
+    let mut rs = RawScan::open_reader(FailingReader, 16, b'\n')?;
+    match rs.getline() {
+        RawScanResult::Err(e) => assert_eq!(e.raw_os_error(), Some(5)),
+        other => panic!("unexpected {:?}", other),
+    }
+    Ok(())
+}
+
+struct FailingReader;
+
+impl io::Read for FailingReader {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::other("decoder failed"))
+    }
+}
+
+#[test]
+fn change_delimiterbyte() -> io::Result<()> {
+    let mut rs = RawScan::open(pipe_with(b"header\n\nfoo\0bar\0")?, 4096, b'\n')?;
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"header\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"\n")));
+    rs.set_delimiterbyte(b'\0')?;
+    assert_eq!(rs.delimiterbyte(), b'\0');
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"foo\0")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"bar\0")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+    Ok(())
+}
+
+#[test]
+fn extend_and_unget_records() -> io::Result<()> {
+    let mut rs = RawScan::open(pipe_with(b"Error: oops\n  at f\n  at main\nOK\n")?, 24, b'\n')?;
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"Error: oops\n")));
+    assert!(matches!(rs.extend_record(), RawScanResult::FullLine(b"Error: oops\n  at f\n")));
+
+    // The record no longer fits in the 24 byte buffer, so comes in chunks.
+    assert!(matches!(rs.extend_record(), RawScanResult::StartLongline(b"Error: oops\n  at f\n  at ")));
+    assert!(matches!(rs.getline(), RawScanResult::WithinLongline(b"main\n")));
+    assert!(matches!(rs.getline(), RawScanResult::LonglineEnded));
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"OK\n")));
+    assert!(matches!(rs.extend_record(), RawScanResult::FullLine(b"OK\n")));
+    rs.unget(1)?;
+    assert!(rs.unget(0).is_err());
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"K\n")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+    Ok(())
+}
+
+#[test]
+fn resize_buffer() -> io::Result<()> {
+    let mut rs = RawScan::open(pipe_with(b"ab\ncdefghij\nk\n")?, 8, b'\n')?;
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"ab\n")));
+    assert!(rs.resize(4).is_err(), "5 bytes still buffered");
+    rs.resize(16)?;
+    assert_eq!(rs.min1stchunklen(), 16);
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"cdefghij\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"k\n")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+
+    let mut rs = RawScan::open_memory(&b"abc\n"[..], b'\n')?;
+    assert!(rs.resize(64).is_err());
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"abc\n")));
+    Ok(())
+}
+
+// The same paragraph checks, over each kind of stream.
+fn check_paragraphs<S>(rs: &mut RawScan<S>) {
+    rs.set_paragraph_mode(true);
+    assert!(rs.paragraph_mode());
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"From: a\nTo: b\n\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"body\n\n")));
+    assert!(matches!(rs.getline(), RawScanResult::FullLineWithoutEol(b"last\n")));
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+}
+
+#[test]
+fn paragraphs() -> io::Result<()> {
+    let input = b"\n\nFrom: a\nTo: b\n\n\nbody\n\nlast\n";
+
+    check_paragraphs(&mut RawScan::open(pipe_with(input)?, 4096, b'\n')?);
+    check_paragraphs(&mut RawScan::open_memory(&input[..], b'\n')?);
+    Ok(())
+}
+
+#[test]
+fn seek_and_tell() -> io::Result<()> {
+    let path = std::env::temp_dir().join(format!("rawscan-ffi-seek-{}", std::process::id()));
+    std::fs::write(&path, b"one\ntwo\nthree\n")?;
+    let mut rs = RawScan::open(std::fs::File::open(&path)?, 8, b'\n')?;
+
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"one\n")));
+    let resume_at = rs.tell();
+    while rs.getline().result_type() != ResultType::Eof {}
+    assert_eq!(rs.seek(resume_at)?, 4);
+    assert!(!rs.eof_seen());
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"two\n")));
+    assert_eq!((rs.line_offset(), rs.line_number(), rs.tell()), (4, 1, 8));
+    std::fs::remove_file(&path)?;
+
+    let mut rs = RawScan::open(pipe_with(b"abc\n")?, 8, b'\n')?;
+    assert!(rs.seek(0).is_err());
+    Ok(())
+}
+
+#[test]
+fn follow() -> io::Result<()> {
+    let path = std::env::temp_dir().join(format!("rawscan-ffi-follow-{}", std::process::id()));
+    std::fs::write(&path, b"ab")?;
+    let mut rs = RawScan::open(std::fs::File::open(&path)?, 8, b'\n')?;
+    rs.set_follow(Some(Duration::from_micros(10)));
+    assert_eq!(rs.follow(), Some(Duration::from_millis(1)));
+
+    // The partial line "ab" is held back until the rest is appended.
+    let writer = {
+        let path = path.clone();
+        std::thread::spawn(move || -> io::Result<()> {
+            std::thread::sleep(Duration::from_millis(20));
+            std::fs::OpenOptions::new().append(true).open(path)?.write_all(b"c\n")
+        })
+    };
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"abc\n")));
+    writer.join().unwrap()?;
+    rs.set_follow(None);
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+    std::fs::remove_file(&path)
+}
+
+#[test]
+fn non_blocking_pipe() -> io::Result<()> {
+    let (reader, mut writer) = io::pipe()?;
+    unsafe {
+        let flags = libc::fcntl(reader.as_raw_fd(), libc::F_GETFL);
+        assert_eq!(libc::fcntl(reader.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK), 0);
+    }
+    let mut rs = RawScan::open(reader, 8, b'\n')?;
+
+    writer.write_all(b"ab\ncd")?;
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"ab\n")));
+    assert!(matches!(rs.getline(), RawScanResult::WouldBlock), "partial line held back");
+    writer.write_all(b"\n")?;
+    assert!(matches!(rs.getline(), RawScanResult::FullLine(b"cd\n")));
+    assert!(matches!(rs.getline(), RawScanResult::WouldBlock));
+    assert!(!rs.eof_seen() && !rs.err_seen());
+    drop(writer);
+    assert!(matches!(rs.getline(), RawScanResult::Eof));
+    Ok(())
+}
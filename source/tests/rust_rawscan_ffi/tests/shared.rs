@@ -0,0 +1,154 @@
+// SharedRawScan, with several threads taking lines from one small
+// buffer at once, checking that every line is handed out once, whole,
+// and unchanged until dropped, however the threads interleave.  Also
+// a non-blocking input, whose WouldBlock errors pass as more arrives.
+
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::sync::Mutex;
+use std::thread;
+
+use rawscan_ffi::{RawScan, RawScanResult, SharedRawScan};
+
+// Numbered lines, every seventh longer than "bufsz", for copying out.
+fn numbered_lines(n: usize, bufsz: usize) -> Vec<Vec<u8>> {
+    (0..n)
+        .map(|i| {
+            let pad = if i % 7 == 0 { bufsz + i % 50 } else { i % 20 };
+            format!("line {} {}\n", i, "x".repeat(pad)).into_bytes()
+        })
+        .collect()
+}
+
+fn shared_pipe(lines: &[Vec<u8>], bufsz: usize) -> io::Result<SharedRawScan<io::PipeReader>> {
+    let (reader, mut writer) = io::pipe()?;
+    let input = lines.concat();
+    thread::spawn(move || writer.write_all(&input));
+    Ok(SharedRawScan::new(RawScan::open(reader, bufsz, b'\n')?))
+}
+
+#[test]
+fn shared_is_sync() {
+    fn sync<T: Sync + Send>() {}
+    sync::<SharedRawScan<io::PipeReader>>();
+}
+
+#[test]
+fn lines_shared_among_threads() -> io::Result<()> {
+    let lines = numbered_lines(5000, 64);
+    let shared = shared_pipe(&lines, 64)?;
+    let got = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                let mut mine = Vec::new();
+                while let Some(line) = shared.getline().unwrap() {
+                    let copy = line.to_vec();
+                    thread::yield_now();
+                    assert_eq!(&line[..], &copy[..], "line changed while held");
+                    mine.push(copy);
+                }
+                got.lock().unwrap().extend(mine);
+            });
+        }
+    });
+
+    let mut got = got.into_inner().unwrap();
+    let mut expected = lines;
+    got.sort();
+    expected.sort();
+    assert_eq!(got, expected);
+    Ok(())
+}
+
+#[test]
+fn batches_shared_among_threads() -> io::Result<()> {
+    let lines = numbered_lines(5000, 64);
+    let shared = shared_pipe(&lines, 64)?;
+    let got = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| loop {
+                let batch = shared.getbatch(10).unwrap();
+                if batch.is_empty() {
+                    break;
+                }
+                assert!(batch.len() <= 10);
+                let batch: Vec<Vec<u8>> = batch.lines().map(<[u8]>::to_vec).collect();
+                got.lock().unwrap().push(batch);
+            });
+        }
+    });
+
+    // Each batch is a run of consecutive lines.
+    let mut got = got.into_inner().unwrap();
+    got.sort_by_key(|batch| lines.iter().position(|line| *line == batch[0]));
+    assert_eq!(got.concat(), lines);
+    Ok(())
+}
+
+#[test]
+fn mixed_lines_and_batches() -> io::Result<()> {
+    let lines = numbered_lines(2000, 32);
+    let shared = shared_pipe(&lines, 32)?;
+
+    assert_eq!(&shared.getline()?.unwrap()[..], &lines[0][..], "long line, copied out");
+    let held = shared.getline()?.unwrap();
+    assert_eq!(&held[..], &lines[1][..]);
+
+    // Another thread can take lines while this one holds a line,
+    // until the buffer must be refilled, and then waits for it.
+    let taken = thread::scope(|scope| {
+        let taker = scope.spawn(|| shared.getbatch(1000).unwrap());
+        thread::sleep(std::time::Duration::from_millis(20));
+        assert_eq!(&held[..], &lines[1][..], "held line intact");
+        drop(held);
+        taker.join().unwrap()
+    });
+    assert_eq!(taken.len(), 1000);
+    assert!(taken.lines().eq(lines[2..1002].iter().map(Vec::as_slice)));
+
+    let mut rs = shared.into_inner();
+    let mut rest = 0;
+    loop {
+        match rs.getline() {
+            RawScanResult::FullLine(_) | RawScanResult::StartLongline(_) => rest += 1,
+            RawScanResult::Eof => break,
+            _ => {}
+        }
+    }
+    assert_eq!(rest, 998, "lines left after into_inner()");
+    Ok(())
+}
+
+fn would_block<T>(got: io::Result<T>) -> bool {
+    matches!(got, Err(e) if e.kind() == io::ErrorKind::WouldBlock)
+}
+
+#[test]
+fn would_block_passes() -> io::Result<()> {
+    let (reader, mut writer) = io::pipe()?;
+    unsafe {
+        let flags = libc::fcntl(reader.as_raw_fd(), libc::F_GETFL);
+        assert_eq!(libc::fcntl(reader.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK), 0);
+    }
+    let shared = SharedRawScan::new(RawScan::open(reader, 8, b'\n')?);
+
+    writer.write_all(b"ab\ncdefghijkl")?;
+    assert_eq!(&shared.getline()?.unwrap()[..], b"ab\n");
+    assert!(would_block(shared.getline()), "long line held back");
+    writer.write_all(b"mn\n")?;
+    assert_eq!(&shared.getline()?.unwrap()[..], b"cdefghijklmn\n", "long line resumed");
+
+    assert!(would_block(shared.getbatch(4)));
+    writer.write_all(b"o\np")?;
+    let batch = shared.getbatch(4)?;
+    assert!(batch.lines().eq([&b"o\n"[..]]), "batch cut short");
+    assert!(would_block(shared.getbatch(4)));
+    drop(writer);
+    assert_eq!(&shared.getline()?.unwrap()[..], b"p");
+    assert!(shared.getline()?.is_none());
+    Ok(())
+}
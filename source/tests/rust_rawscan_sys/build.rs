@@ -0,0 +1,24 @@
+// Compile the C rawscan library, the same lib/rawscan.c (a two line
+// wrapper around rawscan_static.h) that cmake builds into
+// librawscan.so, into a static library linked into this crate.
+
+use std::path::PathBuf;
+
+fn main() {
+    let source = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../..");
+    let include = source.join("include");
+    let lib = source.join("lib/rawscan.c");
+
+    // need >= C11 for anon union in rawscan.h, and GNU extensions
+    // for rawmemchr(), matching the cmake build.
+    cc::Build::new()
+        .file(&lib)
+        .include(&include)
+        .std("gnu11")
+        .compile("rawscan");
+
+    println!("cargo:rerun-if-changed={}", lib.display());
+    println!("cargo:rerun-if-changed={}", include.join("rawscan.h").display());
+    println!("cargo:rerun-if-changed={}", include.join("rawscan_static.h").display());
+    println!("cargo:include={}", include.display());
+}
@@ -0,0 +1,163 @@
+//! Raw bindings to the C rawscan library.
+//!
+//! The build script compiles `source/lib/rawscan.c`, which pulls in
+//! the one and only C implementation in `rawscan_static.h`, and links
+//! it statically into this crate, so Rust code can call the same C
+//! reader in-process.
+//!
+//! These bindings are hand-written, and follow the declarations in
+//! `source/include/rawscan.h` one for one.  Everything here is unsafe
+//! to call; the `rawscan-ffi` crate provides a safe wrapper.
+
+#![allow(non_camel_case_types, non_snake_case, non_upper_case_globals)]
+
+use std::marker::{PhantomData, PhantomPinned};
+use std::os::raw::{c_char, c_double, c_int, c_long, c_uint, c_void};
+
+/// Opaque RAWSCAN stream, only ever handled by pointer.
+#[repr(C)]
+pub struct RAWSCAN {
+    _private: [u8; 0],
+    _marker: PhantomData<(*mut u8, PhantomPinned)>,
+}
+
+/// Enumerate the various kinds of RAWSCAN_RESULT's that rs_getline returns.
+pub type rs_result_type = c_uint;
+
+// The RAWSCAN_RESULT line begin and end fields are valid:
+pub const rt_full_line: rs_result_type = 0;
+pub const rt_full_line_without_eol: rs_result_type = 1;
+pub const rt_start_longline: rs_result_type = 2;
+pub const rt_within_longline: rs_result_type = 3;
+pub const rt_longline_ended: rs_result_type = 4;
+
+// No further RAWSCAN_RESULT fields are valid:
+pub const rt_paused: rs_result_type = 5;
+pub const rt_eof: rs_result_type = 6;
+
+// The RAWSCAN_RESULT errnum field is valid:
+pub const rt_err: rs_result_type = 7;
+
+// No further RAWSCAN_RESULT fields are valid (added last, so as not
+// to renumber the above):
+pub const rt_would_block: rs_result_type = 8;
+
+/// The `line` member of the RAWSCAN_RESULT anonymous union.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RAWSCAN_RESULT_line {
+    /// ptr to first byte in line or chunk
+    pub begin: *const c_char,
+    /// ptr to last byte in line or chunk
+    pub end: *const c_char,
+}
+
+/// The anonymous union within RAWSCAN_RESULT.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union RAWSCAN_RESULT_union {
+    pub line: RAWSCAN_RESULT_line,
+    /// errno of last read if failed
+    pub errnum: c_int,
+}
+
+/// rs_getline() returns a copy of this structure.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RAWSCAN_RESULT {
+    pub type_: rs_result_type,
+    pub u: RAWSCAN_RESULT_union,
+}
+
+/// One field of a line, from rs_next_field() or rs_nth_field().
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RAWSCAN_FIELD {
+    /// ptr to first byte in field
+    pub begin: *const c_char,
+    /// number of bytes in field, maybe 0
+    pub len: usize,
+}
+
+/// Field splitting state, set up by rs_fields_init().
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RAWSCAN_FIELDS {
+    pub next: *const c_char,
+    pub end: *const c_char,
+    pub sep: c_char,
+    pub runs: bool,
+    pub done: bool,
+}
+
+/// Bytes of caller supplied memory that rs_open_with_buffer() needs
+/// beyond the buffer itself, for the RAWSCAN stream and sentinel.
+pub const RS_BUFFER_OVERHEAD: usize = 512;
+
+/// Size of memory to pass rs_open_with_buffer() for a `bufsz` buffer.
+pub const fn RS_BUFFER_SPACE(bufsz: usize) -> usize {
+    bufsz + RS_BUFFER_OVERHEAD
+}
+
+/// rs_open_flags() flag keeping the sentinel copy of the delimiterbyte
+/// in the last, writable, buffer page, rather than in a read-only page
+/// of its own.
+pub const RS_SENTINEL_IN_BUFFER: c_uint = 0x1;
+
+/// Input byte offsets, as from rs_get_line_offset(): the C off_t, as
+/// the C library is built, without _FILE_OFFSET_BITS, so 32 bits on
+/// some 32 bit systems.
+#[allow(non_camel_case_types)]
+pub type off_t = libc::off_t;
+
+/// A caller supplied input routine, called in place of read(2); see
+/// rs_set_read_fn().
+pub type rs_read_fn =
+    Option<unsafe extern "C" fn(context: *mut c_void, buf: *mut c_void, count: usize) -> isize>;
+
+extern "C" {
+    pub fn rs_open(fd: c_int, bufsz: usize, delimiterbyte: c_char) -> *mut RAWSCAN;
+    pub fn rs_open_flags(fd: c_int, bufsz: usize, delimiterbyte: c_char, flags: c_uint) -> *mut RAWSCAN;
+    pub fn rs_open_with_buffer(
+        fd: c_int,
+        mem: *mut c_void,
+        len: usize,
+        delimiterbyte: c_char,
+    ) -> *mut RAWSCAN;
+    pub fn rs_open_memory(mem: *const c_void, len: usize, delimiterbyte: c_char) -> *mut RAWSCAN;
+    pub fn rs_close(rsp: *mut RAWSCAN);
+    pub fn rs_resize(rsp: *mut RAWSCAN, new_bufsz: usize) -> *mut RAWSCAN;
+    pub fn rs_set_read_fn(rsp: *mut RAWSCAN, readfn: rs_read_fn, context: *mut c_void);
+    pub fn rs_enable_pause(rsp: *mut RAWSCAN);
+    pub fn rs_disable_pause(rsp: *mut RAWSCAN);
+    pub fn rs_resume_from_pause(rsp: *mut RAWSCAN);
+    pub fn rs_getline(rsp: *mut RAWSCAN) -> RAWSCAN_RESULT;
+    pub fn rs_set_delimiterbyte(rsp: *mut RAWSCAN, delimiterbyte: c_char) -> c_int;
+    pub fn rs_get_delimiterbyte(rsp: *mut RAWSCAN) -> c_char;
+    pub fn rs_extend_record(rsp: *mut RAWSCAN) -> RAWSCAN_RESULT;
+    pub fn rs_unget(rsp: *mut RAWSCAN, offset: usize) -> c_int;
+    pub fn rs_set_paragraph_mode(rsp: *mut RAWSCAN, paragraph_mode: bool);
+    pub fn rs_get_paragraph_mode(rsp: *mut RAWSCAN) -> bool;
+    pub fn rs_set_min1stchunklen(rsp: *mut RAWSCAN, min1stchunklen: usize) -> c_int;
+    pub fn rs_get_min1stchunklen(rsp: *mut RAWSCAN) -> usize;
+    pub fn rs_get_buffered_bytes(rsp: *mut RAWSCAN) -> usize;
+    pub fn rs_get_bufsz(rsp: *mut RAWSCAN) -> usize;
+    pub fn rs_eof_seen(rsp: *mut RAWSCAN) -> bool;
+    pub fn rs_err_seen(rsp: *mut RAWSCAN) -> bool;
+    pub fn rs_in_longline(rsp: *mut RAWSCAN) -> bool;
+    pub fn rs_is_paused(rsp: *mut RAWSCAN) -> bool;
+    pub fn rs_get_line_offset(rsp: *mut RAWSCAN) -> off_t;
+    pub fn rs_get_chunk_offset(rsp: *mut RAWSCAN) -> off_t;
+    pub fn rs_get_line_number(rsp: *mut RAWSCAN) -> u64;
+    pub fn rs_get_delimiter_count(rsp: *mut RAWSCAN) -> u64;
+    pub fn rs_set_follow(rsp: *mut RAWSCAN, interval_ms: c_uint);
+    pub fn rs_get_follow(rsp: *mut RAWSCAN) -> c_uint;
+    pub fn rs_seek(rsp: *mut RAWSCAN, offset: off_t) -> off_t;
+    pub fn rs_tell(rsp: *mut RAWSCAN) -> off_t;
+    pub fn rs_fields_init(fsp: *mut RAWSCAN_FIELDS, rt: RAWSCAN_RESULT, sep: c_char, runs: bool);
+    pub fn rs_next_field(fsp: *mut RAWSCAN_FIELDS, fieldp: *mut RAWSCAN_FIELD) -> bool;
+    pub fn rs_field_count(rt: RAWSCAN_RESULT, sep: c_char, runs: bool) -> usize;
+    pub fn rs_nth_field(rt: RAWSCAN_RESULT, sep: c_char, runs: bool, n: usize, fieldp: *mut RAWSCAN_FIELD) -> bool;
+    pub fn rs_field_to_long(field: RAWSCAN_FIELD, valp: *mut c_long) -> c_int;
+    pub fn rs_field_to_double(field: RAWSCAN_FIELD, valp: *mut c_double) -> c_int;
+}
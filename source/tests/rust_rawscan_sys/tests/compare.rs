@@ -0,0 +1,1344 @@
+// Run the C rawscan reader in-process, and check that it returns
+// exactly the same sequence of results as the native Rust port in
+// the rawscan crate, over many small random inputs and buffers.
+
+#![allow(non_upper_case_globals)]
+
+use std::io::{self, Write};
+use std::mem;
+use std::os::raw::c_void;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::slice;
+
+use rawscan::{RawScan, ResultType, SliceScan};
+use rawscan_sys::*;
+
+type Results = Vec<(ResultType, Vec<u8>)>;
+
+fn result_type(type_: rs_result_type) -> ResultType {
+    match type_ {
+        rt_full_line => ResultType::FullLine,
+        rt_full_line_without_eol => ResultType::FullLineWithoutEol,
+        rt_start_longline => ResultType::StartLongline,
+        rt_within_longline => ResultType::WithinLongline,
+        rt_longline_ended => ResultType::LonglineEnded,
+        rt_paused => ResultType::Paused,
+        rt_eof => ResultType::Eof,
+        rt_would_block => ResultType::WouldBlock,
+        _ => ResultType::Err,
+    }
+}
+
+// Copy out the line or chunk in "rt", if it has one.
+
+unsafe fn line_of(rt: &RAWSCAN_RESULT, kind: ResultType) -> Vec<u8> {
+    match kind {
+        ResultType::FullLine
+        | ResultType::FullLineWithoutEol
+        | ResultType::StartLongline
+        | ResultType::WithinLongline => {
+            let begin = rt.u.line.begin as *const u8;
+            let len = rt.u.line.end.offset_from(rt.u.line.begin) as usize + 1;
+            slice::from_raw_parts(begin, len).to_vec()
+        }
+        _ => Vec::new(),
+    }
+}
+
+// With "own_memory", open the C stream with rs_open_with_buffer() in
+// memory we supply, rather than with rs_open().
+
+fn c_results(input: &[u8], bufsz: usize, min1st: usize, own_memory: bool) -> io::Result<Results> {
+    let (reader, mut writer) = io::pipe()?;
+    writer.write_all(input)?;
+    drop(writer);
+
+    let mut mem = vec![0u8; RS_BUFFER_SPACE(bufsz)];
+    let mut results = Vec::new();
+    unsafe {
+        let rsp = if own_memory {
+            rs_open_with_buffer(reader.as_raw_fd(), mem.as_mut_ptr().cast(), mem.len(), b'\n' as _)
+        } else {
+            rs_open(reader.as_raw_fd(), bufsz, b'\n' as _)
+        };
+        assert!(!rsp.is_null());
+        assert_eq!(rs_set_min1stchunklen(rsp, min1st), 0);
+        assert_eq!(rs_get_min1stchunklen(rsp), min1st);
+
+        loop {
+            let rt = rs_getline(rsp);
+            let kind = result_type(rt.type_);
+            results.push((kind, line_of(&rt, kind)));
+            if kind == ResultType::Eof || kind == ResultType::Err {
+                break;
+            }
+        }
+        rs_close(rsp);
+    }
+    Ok(results)
+}
+
+// Feed the C reader through rs_set_read_fn(), from a callback that
+// hands out at most "step" bytes per call, or the Rust reader through
+// the same, as a Read.
+
+struct Trickle<'a> {
+    data: &'a [u8],
+    step: usize,
+}
+
+impl<'a> io::Read for Trickle<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.data.len().min(buf.len()).min(self.step);
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Ok(n)
+    }
+}
+
+unsafe extern "C" fn trickle_read(context: *mut c_void, buf: *mut c_void, count: usize) -> isize {
+    let trickle = &mut *context.cast::<Trickle>();
+    let n = trickle.data.len().min(count).min(trickle.step);
+
+    ptr::copy_nonoverlapping(trickle.data.as_ptr(), buf.cast(), n);
+    trickle.data = &trickle.data[n..];
+    n as isize
+}
+
+// A Trickle that stalls, returning nothing, as at the end of a file
+// not yet appended to, before each read that returns something.
+
+struct Stalling<'a> {
+    trickle: Trickle<'a>,
+    stalled: bool,
+}
+
+impl<'a> io::Read for Stalling<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stalled = !self.stalled;
+        if self.stalled {
+            return Ok(0);
+        }
+        self.trickle.read(buf)
+    }
+}
+
+unsafe extern "C" fn stalling_read(context: *mut c_void, buf: *mut c_void, count: usize) -> isize {
+    let stalling = &mut *context.cast::<Stalling>();
+
+    stalling.stalled = !stalling.stalled;
+    if stalling.stalled {
+        return 0;
+    }
+    trickle_read((&mut stalling.trickle as *mut Trickle).cast(), buf, count)
+}
+
+// A Trickle whose reads fail, first as would a read of a non-blocking
+// fd with nothing ready, then as would one interrupted by a signal,
+// before each read that returns something.
+
+struct Balking<'a> {
+    trickle: Trickle<'a>,
+    balks: usize,
+}
+
+impl<'a> io::Read for Balking<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.balks += 1;
+        match self.balks % 3 {
+            1 => Err(io::ErrorKind::WouldBlock.into()),
+            2 => Err(io::ErrorKind::Interrupted.into()),
+            _ => self.trickle.read(buf),
+        }
+    }
+}
+
+unsafe extern "C" fn balking_read(context: *mut c_void, buf: *mut c_void, count: usize) -> isize {
+    let balking = &mut *context.cast::<Balking>();
+
+    balking.balks += 1;
+    match balking.balks % 3 {
+        1 => *libc::__errno_location() = libc::EAGAIN,
+        2 => *libc::__errno_location() = libc::EINTR,
+        _ => return trickle_read((&mut balking.trickle as *mut Trickle).cast(), buf, count),
+    }
+    -1
+}
+
+fn c_callback_results(input: &[u8], bufsz: usize, min1st: usize, step: usize) -> Results {
+    let mut trickle = Trickle { data: input, step };
+    let mut results = Vec::new();
+    unsafe {
+        let rsp = rs_open(-1, bufsz, b'\n' as _);
+        assert!(!rsp.is_null());
+        rs_set_read_fn(rsp, Some(trickle_read), (&mut trickle as *mut Trickle).cast());
+        assert_eq!(rs_set_min1stchunklen(rsp, min1st), 0);
+
+        loop {
+            let rt = rs_getline(rsp);
+            let kind = result_type(rt.type_);
+            results.push((kind, line_of(&rt, kind)));
+            if kind == ResultType::Eof || kind == ResultType::Err {
+                break;
+            }
+        }
+        rs_close(rsp);
+    }
+    results
+}
+
+// Scan "input" in place with a C rs_open_memory() stream, on a copy
+// in memory that we've made read-only, right up against an unmapped
+// page, so that any scanning past the end, or any write, would crash.
+
+fn c_memory_results(input: &[u8]) -> Results {
+    let len = input.len();
+
+    let mut results = Vec::new();
+    unsafe {
+        let pgsz = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+        let mapsz = (len / pgsz + 2) * pgsz;
+        let map = libc::mmap(
+            ptr::null_mut(),
+            mapsz,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+        assert_ne!(map, libc::MAP_FAILED);
+
+        let guard = map.cast::<u8>().add(mapsz - pgsz);
+        let mem = guard.sub(len);
+        ptr::copy_nonoverlapping(input.as_ptr(), mem, len);
+        assert_eq!(libc::mprotect(map, mapsz - pgsz, libc::PROT_READ), 0);
+        assert_eq!(libc::mprotect(guard.cast(), pgsz, libc::PROT_NONE), 0);
+
+        let rsp = rs_open_memory(mem.cast(), len, b'\n' as _);
+        assert!(!rsp.is_null());
+        loop {
+            let rt = rs_getline(rsp);
+            let kind = result_type(rt.type_);
+            results.push((kind, line_of(&rt, kind)));
+            if kind == ResultType::Eof || kind == ResultType::Err {
+                break;
+            }
+        }
+        rs_close(rsp);
+        assert_eq!(libc::munmap(map, mapsz), 0);
+    }
+    results
+}
+
+fn rust_results(input: &[u8], bufsz: usize, min1st: usize) -> Results {
+    let mut rs = RawScan::new(input, bufsz, b'\n');
+    rs.set_min1stchunklen(min1st).unwrap();
+
+    let mut results = Vec::new();
+    loop {
+        let rt = rs.getline();
+        let kind = rt.result_type();
+        results.push((kind, rt.line().map(<[u8]>::to_vec).unwrap_or_default()));
+        if kind == ResultType::Eof || kind == ResultType::Err {
+            return results;
+        }
+    }
+}
+
+#[test]
+fn result_layout_matches_rawscan_h() {
+    assert_eq!(mem::size_of::<RAWSCAN_RESULT>(), 3 * mem::size_of::<usize>());
+    assert_eq!(mem::align_of::<RAWSCAN_RESULT>(), mem::align_of::<usize>());
+}
+
+#[test]
+fn rs_open_with_buffer_rejects_short_memory() {
+    let mut mem = [0u8; RS_BUFFER_OVERHEAD];
+    let rsp = unsafe { rs_open_with_buffer(0, mem.as_mut_ptr().cast(), mem.len(), b'\n' as _) };
+
+    assert!(rsp.is_null());
+    assert_eq!(io::Error::last_os_error().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn c_and_rust_agree() -> io::Result<()> {
+    let mut seed = 7u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+
+    for nlines in 0..10 {
+        for maxlen in 0..20 {
+            let mut input = Vec::new();
+            for _ in 0..nlines {
+                input.extend((0..rand(maxlen + 1)).map(|_| b'a'));
+                input.push(b'\n');
+            }
+            if rand(2) == 0 {
+                input.pop();
+            }
+            for bufsz in 1..=16 {
+                let min1st = 1 + rand(bufsz);
+                let own_memory = rand(2) == 0;
+                assert_eq!(
+                    c_results(&input, bufsz, min1st, own_memory)?,
+                    rust_results(&input, bufsz, min1st),
+                    "input {:?} bufsz {} min1stchunklen {} own_memory {}",
+                    String::from_utf8_lossy(&input),
+                    bufsz,
+                    min1st,
+                    own_memory
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn c_read_fn_and_rust_agree() {
+    let mut seed = 3u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+
+    for _ in 0..500 {
+        let input: Vec<u8> = (0..rand(60)).map(|_| b"ab\n"[rand(3)]).collect();
+        let bufsz = 1 + rand(12);
+        let min1st = 1 + rand(bufsz);
+        let step = 1 + rand(5);
+        assert_eq!(
+            c_callback_results(&input, bufsz, min1st, step),
+            rust_results(&input, bufsz, min1st),
+            "input {:?} bufsz {} min1stchunklen {} step {}",
+            String::from_utf8_lossy(&input),
+            bufsz,
+            min1st,
+            step
+        );
+    }
+}
+
+#[test]
+fn c_memory_and_rust_slice_agree() {
+    let inputs: [&[u8]; 6] = [b"", b"\n", b"abc", b"abc\n", b"\n\nabc\ndef", b"one\ntwo\nthree\n"];
+
+    for input in inputs {
+        let mut ss = SliceScan::new(input, b'\n');
+        let mut expected = Vec::new();
+        loop {
+            let rt = ss.getline();
+            let kind = rt.result_type();
+            expected.push((kind, rt.line().map(<[u8]>::to_vec).unwrap_or_default()));
+            if kind == ResultType::Eof {
+                break;
+            }
+        }
+        assert_eq!(c_memory_results(input), expected, "input {:?}", String::from_utf8_lossy(input));
+        assert_eq!(expected, rust_results(input, input.len() + 1, input.len() + 1));
+    }
+}
+
+// Collect results, switching from newline to nul delimiters after
+// "switch_after" results.
+
+unsafe fn c_switching_results(rsp: *mut RAWSCAN, switch_after: usize) -> Results {
+    let mut results = Vec::new();
+    loop {
+        if results.len() == switch_after {
+            assert_eq!(rs_set_delimiterbyte(rsp, 0), 0);
+            assert_eq!(rs_get_delimiterbyte(rsp), 0);
+        }
+        let rt = rs_getline(rsp);
+        let kind = result_type(rt.type_);
+        results.push((kind, line_of(&rt, kind)));
+        if kind == ResultType::Eof || kind == ResultType::Err {
+            rs_close(rsp);
+            return results;
+        }
+    }
+}
+
+#[test]
+fn c_and_rust_agree_across_delimiter_change() -> io::Result<()> {
+    let mut seed = 13u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+
+    for _ in 0..300 {
+        let input: Vec<u8> = (0..rand(40)).map(|_| b"ab\n\0"[rand(4)]).collect();
+        let bufsz = 1 + rand(12);
+        let switch_after = rand(6);
+
+        let mut rs = RawScan::new(&input[..], bufsz, b'\n');
+        let mut expected = Vec::new();
+        loop {
+            if expected.len() == switch_after {
+                rs.set_delimiterbyte(0);
+            }
+            let rt = rs.getline();
+            let kind = rt.result_type();
+            expected.push((kind, rt.line().map(<[u8]>::to_vec).unwrap_or_default()));
+            if kind == ResultType::Eof {
+                break;
+            }
+        }
+
+        let (reader, mut writer) = io::pipe()?;
+        writer.write_all(&input)?;
+        drop(writer);
+        let mut mem = vec![0u8; RS_BUFFER_SPACE(bufsz)];
+        unsafe {
+            let rsp = rs_open(reader.as_raw_fd(), bufsz, b'\n' as _);
+            let from_fd = c_switching_results(rsp, switch_after);
+            assert_eq!(from_fd, expected, "input {:?} bufsz {} switch {}", input, bufsz, switch_after);
+
+            let rsp = rs_open_with_buffer(-1, mem.as_mut_ptr().cast(), mem.len(), b'\n' as _);
+            let mut trickle = Trickle { data: &input, step: bufsz };
+            rs_set_read_fn(rsp, Some(trickle_read), (&mut trickle as *mut Trickle).cast());
+            assert_eq!(c_switching_results(rsp, switch_after), expected);
+        }
+    }
+    Ok(())
+}
+
+// A script of getline (0), extend_record (1) and unget (2) calls, the
+// unget offsets being picked from the lengths of the lines returned.
+// Results are recorded as (Some(kind), line) for lines, and as
+// (None, [ok]) for ungets.
+
+type Steps = Vec<(Option<ResultType>, Vec<u8>)>;
+
+fn offset_for(choice: usize, steps: &Steps) -> usize {
+    let len = steps.iter().rev().find(|s| s.0.is_some()).map_or(0, |s| s.1.len());
+    choice % (len + 2)
+}
+
+unsafe fn c_script_results(rsp: *mut RAWSCAN, script: &[(usize, usize)]) -> Steps {
+    let mut steps = Vec::new();
+    for &(op, choice) in script {
+        let rt = match op {
+            0 => rs_getline(rsp),
+            1 => rs_extend_record(rsp),
+            _ => {
+                let ok = rs_unget(rsp, offset_for(choice, &steps)) == 0;
+                steps.push((None, vec![ok as u8]));
+                continue;
+            }
+        };
+        let kind = result_type(rt.type_);
+        steps.push((Some(kind), line_of(&rt, kind)));
+    }
+    rs_close(rsp);
+    steps
+}
+
+fn rust_script_results(input: &[u8], bufsz: usize, script: &[(usize, usize)]) -> Steps {
+    let mut rs = RawScan::new(input, bufsz, b'\n');
+    let mut steps = Vec::new();
+    for &(op, choice) in script {
+        let rt = match op {
+            0 => rs.getline(),
+            1 => rs.extend_record(),
+            _ => {
+                let ok = rs.unget(offset_for(choice, &steps)).is_ok();
+                steps.push((None, vec![ok as u8]));
+                continue;
+            }
+        };
+        let kind = rt.result_type();
+        steps.push((Some(kind), rt.line().map(<[u8]>::to_vec).unwrap_or_default()));
+    }
+    steps
+}
+
+fn slice_script_results(input: &[u8], script: &[(usize, usize)]) -> Steps {
+    let mut ss = SliceScan::new(input, b'\n');
+    let mut steps = Vec::new();
+    for &(op, choice) in script {
+        let rt = match op {
+            0 => ss.getline(),
+            1 => ss.extend_record(),
+            _ => {
+                let ok = ss.unget(offset_for(choice, &steps)).is_ok();
+                steps.push((None, vec![ok as u8]));
+                continue;
+            }
+        };
+        let kind = rt.result_type();
+        steps.push((Some(kind), rt.line().map(<[u8]>::to_vec).unwrap_or_default()));
+    }
+    steps
+}
+
+#[test]
+fn c_and_rust_agree_extending_records() -> io::Result<()> {
+    let mut seed = 17u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+
+    for _ in 0..500 {
+        let input: Vec<u8> = (0..rand(50)).map(|_| b"ab\n"[rand(3)]).collect();
+        let bufsz = 1 + rand(16);
+        let script: Vec<(usize, usize)> = (0..40).map(|_| (rand(5).min(2), rand(64))).collect();
+
+        let expected = rust_script_results(&input, bufsz, &script);
+
+        let (reader, mut writer) = io::pipe()?;
+        writer.write_all(&input)?;
+        drop(writer);
+        let from_fd = unsafe { c_script_results(rs_open(reader.as_raw_fd(), bufsz, b'\n' as _), &script) };
+        assert_eq!(
+            from_fd,
+            expected,
+            "input {:?} bufsz {} script {:?}",
+            String::from_utf8_lossy(&input),
+            bufsz,
+            script
+        );
+
+        // With a buffer big enough for all the input, the C and Rust
+        // in-memory scanners must agree with the buffered ones.
+        let big = input.len() + 1;
+        let expected = rust_script_results(&input, big, &script);
+        assert_eq!(slice_script_results(&input, &script), expected);
+        let mem = input.clone();
+        let from_memory = unsafe { c_script_results(rs_open_memory(mem.as_ptr().cast(), mem.len(), b'\n' as _), &script) };
+        assert_eq!(from_memory, expected, "input {:?} script {:?}", String::from_utf8_lossy(&input), script);
+    }
+    Ok(())
+}
+
+#[test]
+fn c_and_rust_agree_on_paragraphs() {
+    let mut seed = 19u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+
+    for _ in 0..2000 {
+        let input: Vec<u8> = (0..rand(50)).map(|_| b"ab\n\n\n"[rand(5)]).collect();
+        let bufsz = 1 + rand(12);
+        let min1st = 1 + rand(bufsz);
+        let step = 1 + rand(bufsz + 2);
+
+        // Both read the same few bytes at a time, so that chunks of
+        // long paragraphs break at the same places.
+        let mut rs = RawScan::new(Trickle { data: &input, step }, bufsz, b'\n');
+        rs.set_paragraph_mode(true);
+        rs.set_min1stchunklen(min1st).unwrap();
+        let mut expected = Vec::new();
+        loop {
+            let rt = rs.getline();
+            let kind = rt.result_type();
+            expected.push((kind, rt.line().map(<[u8]>::to_vec).unwrap_or_default()));
+            if kind == ResultType::Eof {
+                break;
+            }
+        }
+
+        let mut trickle = Trickle { data: &input, step };
+        let mut results = Vec::new();
+        unsafe {
+            let rsp = rs_open(-1, bufsz, b'\n' as _);
+            rs_set_read_fn(rsp, Some(trickle_read), (&mut trickle as *mut Trickle).cast());
+            rs_set_paragraph_mode(rsp, true);
+            assert!(rs_get_paragraph_mode(rsp));
+            assert_eq!(rs_set_min1stchunklen(rsp, min1st), 0);
+            loop {
+                let rt = rs_getline(rsp);
+                let kind = result_type(rt.type_);
+                results.push((kind, line_of(&rt, kind)));
+                if kind == ResultType::Eof || kind == ResultType::Err {
+                    break;
+                }
+            }
+            rs_close(rsp);
+        }
+        assert_eq!(
+            results,
+            expected,
+            "input {:?} bufsz {} min1stchunklen {} step {}",
+            String::from_utf8_lossy(&input),
+            bufsz,
+            min1st,
+            step
+        );
+    }
+}
+
+#[test]
+fn c_and_rust_agree_across_resizes() {
+    let mut seed = 23u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+
+    for _ in 0..1000 {
+        let input: Vec<u8> = (0..rand(60)).map(|_| b"abc\n"[rand(4)]).collect();
+        let bufsz = 1 + rand(12);
+        let step = 1 + rand(bufsz + 2);
+        let paragraph_mode = rand(4) == 0;
+        let script: Vec<usize> = (0..40).map(|_| if rand(3) == 0 { 1 + rand(20) } else { 0 }).collect();
+
+        // Both read the same few bytes at a time, between resizes to
+        // sizes 1 + rand(20).  Resizes are recorded as (None, [ok]).
+        let mut rs = RawScan::new(Trickle { data: &input, step }, bufsz, b'\n');
+        rs.set_paragraph_mode(paragraph_mode);
+        let mut expected: Steps = Vec::new();
+        for &new_bufsz in &script {
+            if new_bufsz > 0 {
+                expected.push((None, vec![rs.resize(new_bufsz).is_ok() as u8]));
+                continue;
+            }
+            let rt = rs.getline();
+            let kind = rt.result_type();
+            expected.push((Some(kind), rt.line().map(<[u8]>::to_vec).unwrap_or_default()));
+        }
+
+        let mut trickle = Trickle { data: &input, step };
+        let mut results: Steps = Vec::new();
+        unsafe {
+            let mut rsp = rs_open(-1, bufsz, b'\n' as _);
+            rs_set_read_fn(rsp, Some(trickle_read), (&mut trickle as *mut Trickle).cast());
+            rs_set_paragraph_mode(rsp, paragraph_mode);
+            for &new_bufsz in &script {
+                if new_bufsz > 0 {
+                    let nrsp = rs_resize(rsp, new_bufsz);
+                    results.push((None, vec![!nrsp.is_null() as u8]));
+                    if !nrsp.is_null() {
+                        rsp = nrsp;
+                    }
+                    continue;
+                }
+                let rt = rs_getline(rsp);
+                let kind = result_type(rt.type_);
+                results.push((Some(kind), line_of(&rt, kind)));
+            }
+            rs_close(rsp);
+        }
+        assert_eq!(
+            results,
+            expected,
+            "input {:?} bufsz {} step {} paragraph_mode {} script {:?}",
+            String::from_utf8_lossy(&input),
+            bufsz,
+            step,
+            paragraph_mode,
+            script
+        );
+    }
+}
+
+#[test]
+fn rs_resize_rejects_caller_memory() {
+    let mut mem = vec![0u8; RS_BUFFER_SPACE(16)];
+    let input = b"abc\n";
+    unsafe {
+        let rsp = rs_open_with_buffer(-1, mem.as_mut_ptr().cast(), mem.len(), b'\n' as _);
+        assert!(rs_resize(rsp, 64).is_null());
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+        rs_close(rsp);
+
+        let rsp = rs_open_memory(input.as_ptr().cast(), input.len(), b'\n' as _);
+        assert!(rs_resize(rsp, 64).is_null());
+        rs_close(rsp);
+    }
+}
+
+// After each getline, extend_record or unget, where the stream is in
+// its input: (line offset, chunk offset, line number, delimiter count).
+
+type Position = (u64, u64, u64, u64);
+
+#[test]
+fn c_and_rust_agree_on_positions() {
+    let mut seed = 31u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+
+    for _ in 0..1000 {
+        let input: Vec<u8> = (0..rand(60)).map(|_| b"ab\n\n"[rand(4)]).collect();
+        let bufsz = 1 + rand(12);
+        let step = 1 + rand(bufsz + 2);
+        let paragraph_mode = rand(4) == 0;
+        let script: Vec<(usize, usize)> = (0..40).map(|_| (rand(6).min(2), rand(64))).collect();
+
+        let mut rs = RawScan::new(Trickle { data: &input, step }, bufsz, b'\n');
+        rs.set_paragraph_mode(paragraph_mode);
+        let mut expected: Vec<(Option<ResultType>, Position)> = Vec::new();
+        let mut steps: Steps = Vec::new();
+        for &(op, choice) in &script {
+            let kind = match op {
+                0 => Some(rs.getline().result_type()),
+                1 => Some(rs.extend_record().result_type()),
+                _ => {
+                    let _ = rs.unget(offset_for(choice, &steps));
+                    None
+                }
+            };
+            steps.push((kind, vec![0; (rs.line_number() % 7) as usize]));
+            let position = (rs.line_offset(), rs.chunk_offset(), rs.line_number(), rs.delimiter_count());
+            expected.push((kind, position));
+        }
+
+        let mut trickle = Trickle { data: &input, step };
+        let mut results: Vec<(Option<ResultType>, Position)> = Vec::new();
+        let mut steps: Steps = Vec::new();
+        unsafe {
+            let rsp = rs_open(-1, bufsz, b'\n' as _);
+            rs_set_read_fn(rsp, Some(trickle_read), (&mut trickle as *mut Trickle).cast());
+            rs_set_paragraph_mode(rsp, paragraph_mode);
+            for &(op, choice) in &script {
+                let kind = match op {
+                    0 => Some(result_type(rs_getline(rsp).type_)),
+                    1 => Some(result_type(rs_extend_record(rsp).type_)),
+                    _ => {
+                        rs_unget(rsp, offset_for(choice, &steps));
+                        None
+                    }
+                };
+                steps.push((kind, vec![0; (rs_get_line_number(rsp) % 7) as usize]));
+                let position = (
+                    rs_get_line_offset(rsp) as u64,
+                    rs_get_chunk_offset(rsp) as u64,
+                    rs_get_line_number(rsp),
+                    rs_get_delimiter_count(rsp),
+                );
+                results.push((kind, position));
+            }
+            rs_close(rsp);
+        }
+        assert_eq!(
+            results,
+            expected,
+            "input {:?} bufsz {} step {} paragraph_mode {} script {:?}",
+            String::from_utf8_lossy(&input),
+            bufsz,
+            step,
+            paragraph_mode,
+            script
+        );
+    }
+}
+
+// Getlines with rs_seek()s mixed in, to earlier rs_tell() offsets or
+// anywhere at all, reading a regular file in C, and a Cursor in Rust.
+// After each step: its result, tell(), line offset and line number.
+
+type Seeked = Vec<(Option<(ResultType, Vec<u8>)>, u64, u64, u64)>;
+
+#[test]
+fn c_and_rust_agree_across_seeks() -> io::Result<()> {
+    let path = std::env::temp_dir().join(format!("rawscan-seek-{}", std::process::id()));
+    let mut seed = 19u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+
+    for _ in 0..300 {
+        let input: Vec<u8> = (0..rand(60)).map(|_| b"ab\n"[rand(3)]).collect();
+        let bufsz = 1 + rand(12);
+        let script: Vec<(usize, usize)> = (0..40).map(|_| (rand(8), rand(64))).collect();
+        std::fs::write(&path, &input)?;
+
+        // Op 0 seeks to a random offset, op 1 to an earlier tell().
+        let target = |op: usize, choice: usize, tells: &[u64]| match op {
+            0 => Some((choice % (input.len() + 1)) as u64),
+            1 => Some(tells[choice % tells.len()]),
+            _ => None,
+        };
+
+        let mut rs = RawScan::new(io::Cursor::new(&input), bufsz, b'\n');
+        let mut expected: Seeked = Vec::new();
+        let mut tells = vec![0];
+        for &(op, choice) in &script {
+            let step = match target(op, choice, &tells) {
+                Some(offset) => {
+                    assert_eq!(rs.seek(offset)?, offset);
+                    None
+                }
+                None => {
+                    let result = rs.getline();
+                    Some((result.result_type(), result.line().unwrap_or_default().to_vec()))
+                }
+            };
+            tells.push(rs.tell());
+            expected.push((step, rs.tell(), rs.line_offset(), rs.line_number()));
+        }
+
+        let file = std::fs::File::open(&path)?;
+        let mut results: Seeked = Vec::new();
+        let mut tells = vec![0];
+        unsafe {
+            let rsp = rs_open(file.as_raw_fd(), bufsz, b'\n' as _);
+            for &(op, choice) in &script {
+                let step = match target(op, choice, &tells) {
+                    Some(offset) => {
+                        assert_eq!(rs_seek(rsp, offset as off_t), offset as off_t);
+                        None
+                    }
+                    None => {
+                        let rt = rs_getline(rsp);
+                        let kind = result_type(rt.type_);
+                        Some((kind, line_of(&rt, kind)))
+                    }
+                };
+                tells.push(rs_tell(rsp) as u64);
+                results.push((
+                    step,
+                    rs_tell(rsp) as u64,
+                    rs_get_line_offset(rsp) as u64,
+                    rs_get_line_number(rsp),
+                ));
+            }
+            rs_close(rsp);
+        }
+        assert_eq!(
+            results,
+            expected,
+            "input {:?} bufsz {} script {:?}",
+            String::from_utf8_lossy(&input),
+            bufsz,
+            script
+        );
+    }
+    std::fs::remove_file(&path)
+}
+
+#[test]
+fn seek_fails_without_a_file_offset() -> io::Result<()> {
+    let (reader, _writer) = io::pipe()?;
+    unsafe {
+        let rsp = rs_open(reader.as_raw_fd(), 8, b'\n' as _);
+        assert_eq!(rs_seek(rsp, 0), -1);
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ESPIPE));
+        rs_close(rsp);
+
+        let input = b"abc\ndef\n";
+        let rsp = rs_open_memory(input.as_ptr().cast(), input.len(), b'\n' as _);
+        assert_eq!(rs_seek(rsp, 9), -1);
+        assert_eq!(rs_seek(rsp, 4), 4);
+        let rt = rs_getline(rsp);
+        assert_eq!(line_of(&rt, result_type(rt.type_)), b"def\n");
+        assert_eq!((rs_tell(rsp), rs_get_line_number(rsp)), (8, 1));
+        rs_close(rsp);
+    }
+    Ok(())
+}
+
+// In follow mode, reads of nothing part way through the input are
+// waited out, so both return just what they would have without them,
+// up to the end of the input, which is waited on until follow mode
+// is turned off.
+
+#[test]
+fn c_and_rust_agree_following() {
+    let mut seed = 20u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+
+    for _ in 0..40 {
+        let mut input: Vec<u8> = (0..rand(40)).map(|_| b"ab\n"[rand(3)]).collect();
+        input.push(b'\n');
+        let bufsz = 1 + rand(12);
+        let step = 3 + rand(6);
+        let context = format!("input {:?} bufsz {} step {}", String::from_utf8_lossy(&input), bufsz, step);
+
+        let mut expected = rust_results(&input, bufsz, bufsz);
+        assert_eq!(expected.pop().map(|(kind, _)| kind), Some(ResultType::Eof));
+
+        let stalling = Stalling { trickle: Trickle { data: &input, step }, stalled: false };
+        let mut rs = RawScan::new(stalling, bufsz, b'\n');
+        rs.set_follow(Some(std::time::Duration::from_millis(1)));
+        let got: Results = expected
+            .iter()
+            .map(|_| {
+                let result = rs.getline();
+                (result.result_type(), result.line().unwrap_or_default().to_vec())
+            })
+            .collect();
+        assert_eq!(got, expected, "rust {}", context);
+        rs.set_follow(None);
+        assert_eq!(rs.getline().result_type(), ResultType::Eof);
+
+        let mut stalling = Stalling { trickle: Trickle { data: &input, step }, stalled: false };
+        unsafe {
+            let rsp = rs_open(-1, bufsz, b'\n' as _);
+            rs_set_read_fn(rsp, Some(stalling_read), (&mut stalling as *mut Stalling).cast());
+            rs_set_follow(rsp, 1);
+            assert_eq!(rs_get_follow(rsp), 1);
+            let got: Results = expected
+                .iter()
+                .map(|_| {
+                    let rt = rs_getline(rsp);
+                    let kind = result_type(rt.type_);
+                    (kind, line_of(&rt, kind))
+                })
+                .collect();
+            assert_eq!(got, expected, "c {}", context);
+            rs_set_follow(rsp, 0);
+            assert_eq!(result_type(rs_getline(rsp).type_), ResultType::Eof);
+            rs_close(rsp);
+        }
+    }
+}
+
+#[test]
+fn c_and_rust_agree_not_blocking() {
+    let mut seed = 21u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+
+    for _ in 0..300 {
+        let input: Vec<u8> = (0..rand(40)).map(|_| b"ab\n"[rand(3)]).collect();
+        let bufsz = 1 + rand(12);
+        let step = 1 + rand(8);
+        let context = format!("input {:?} bufsz {} step {}", String::from_utf8_lossy(&input), bufsz, step);
+
+        let expected = c_callback_results(&input, bufsz, bufsz, step);
+        let mut would_blocks = 0;
+
+        let balking = Balking { trickle: Trickle { data: &input, step }, balks: 0 };
+        let mut rs = RawScan::new(balking, bufsz, b'\n');
+        let mut got = Results::new();
+        loop {
+            let result = rs.getline();
+            let kind = result.result_type();
+            if kind == ResultType::WouldBlock {
+                would_blocks += 1;
+                continue;
+            }
+            got.push((kind, result.line().unwrap_or_default().to_vec()));
+            if kind == ResultType::Eof {
+                break;
+            }
+        }
+        assert_eq!(got, expected, "rust {}", context);
+
+        let mut balking = Balking { trickle: Trickle { data: &input, step }, balks: 0 };
+        let mut got = Results::new();
+        unsafe {
+            let rsp = rs_open(-1, bufsz, b'\n' as _);
+            rs_set_read_fn(rsp, Some(balking_read), (&mut balking as *mut Balking).cast());
+            loop {
+                let rt = rs_getline(rsp);
+                let kind = result_type(rt.type_);
+                if kind == ResultType::WouldBlock {
+                    would_blocks -= 1;
+                    continue;
+                }
+                got.push((kind, line_of(&rt, kind)));
+                if kind == ResultType::Eof {
+                    break;
+                }
+            }
+            rs_close(rsp);
+        }
+        assert_eq!(got, expected, "c {}", context);
+        assert_eq!(would_blocks, 0, "as many rt_would_block's {}", context);
+    }
+}
+
+#[test]
+fn c_would_block_while_extending() {
+    let mut balking = Balking { trickle: Trickle { data: b"ab\ncd\n", step: 3 }, balks: 0 };
+    unsafe {
+        let rsp = rs_open(-1, 8, b'\n' as _);
+        rs_set_read_fn(rsp, Some(balking_read), (&mut balking as *mut Balking).cast());
+        let next = |extend: bool| {
+            let rt = if extend { rs_extend_record(rsp) } else { rs_getline(rsp) };
+            let kind = result_type(rt.type_);
+            (kind, line_of(&rt, kind))
+        };
+        assert_eq!(next(false), (ResultType::WouldBlock, Vec::new()));
+        assert_eq!(next(false), (ResultType::FullLine, b"ab\n".to_vec()));
+        assert_eq!(next(true), (ResultType::WouldBlock, Vec::new()));
+        assert_eq!(rs_get_line_offset(rsp), 0, "record still the last line returned");
+        assert_eq!(next(true), (ResultType::FullLine, b"ab\ncd\n".to_vec()));
+        assert_eq!(next(false).0, ResultType::WouldBlock);
+        assert_eq!(next(false).0, ResultType::Eof);
+        rs_close(rsp);
+    }
+}
+
+// After each getline, the stream state: (buffered bytes, bufsz, eof
+// seen, err seen, in longline, paused).
+
+type State = (usize, usize, bool, bool, bool, bool);
+
+#[test]
+fn c_and_rust_agree_on_stream_state() {
+    let mut seed = 29u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+
+    for _ in 0..1000 {
+        let input: Vec<u8> = (0..rand(50)).map(|_| b"abc\n"[rand(4)]).collect();
+        let bufsz = 1 + rand(12);
+        let step = 1 + rand(bufsz + 2);
+        let pause = rand(2) == 0;
+
+        let mut rs = RawScan::new(Trickle { data: &input, step }, bufsz, b'\n');
+        if pause {
+            rs.enable_pause();
+        }
+        let mut expected: Vec<(ResultType, Vec<u8>, State)> = Vec::new();
+        loop {
+            let rt = rs.getline();
+            let kind = rt.result_type();
+            let line = rt.line().map(<[u8]>::to_vec).unwrap_or_default();
+            let state = (rs.buffered_bytes(), rs.bufsz(), rs.eof_seen(), rs.err_seen(), rs.in_longline(), rs.is_paused());
+            expected.push((kind, line, state));
+            match kind {
+                ResultType::Paused => rs.resume_from_pause(),
+                ResultType::Eof => break,
+                _ => (),
+            }
+        }
+
+        let mut trickle = Trickle { data: &input, step };
+        let mut results = Vec::new();
+        unsafe {
+            let rsp = rs_open(-1, bufsz, b'\n' as _);
+            rs_set_read_fn(rsp, Some(trickle_read), (&mut trickle as *mut Trickle).cast());
+            if pause {
+                rs_enable_pause(rsp);
+            }
+            loop {
+                let rt = rs_getline(rsp);
+                let kind = result_type(rt.type_);
+                let state = (
+                    rs_get_buffered_bytes(rsp),
+                    rs_get_bufsz(rsp),
+                    rs_eof_seen(rsp),
+                    rs_err_seen(rsp),
+                    rs_in_longline(rsp),
+                    rs_is_paused(rsp),
+                );
+                results.push((kind, line_of(&rt, kind), state));
+                match kind {
+                    ResultType::Paused => rs_resume_from_pause(rsp),
+                    ResultType::Eof | ResultType::Err => break,
+                    _ => (),
+                }
+            }
+            rs_close(rsp);
+        }
+        assert_eq!(
+            results,
+            expected,
+            "input {:?} bufsz {} step {} pause {}",
+            String::from_utf8_lossy(&input),
+            bufsz,
+            step,
+            pause
+        );
+    }
+}
+
+// Reserve a PROT_NONE guard page, with a hole of "mapsz" bytes just
+// below it, so that a stream's mmap() of that size will likely land in
+// the hole, right up against the guard page.  Returns the guard page.
+
+unsafe fn guard_above_hole(mapsz: usize, pgsz: usize) -> *mut u8 {
+    let region = libc::mmap(
+        ptr::null_mut(),
+        mapsz + pgsz,
+        libc::PROT_NONE,
+        libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+        -1,
+        0,
+    );
+    assert_ne!(region, libc::MAP_FAILED);
+    assert_eq!(libc::munmap(region, mapsz), 0);
+    region.cast::<u8>().add(mapsz)
+}
+
+// With RS_SENTINEL_IN_BUFFER, the sentinel delimiterbyte and its nul
+// are the last two bytes of the stream's mapping.  Where the stream
+// lands just below a guard page, rawmemchr() getting past the sentinel
+// would fault, so running many random inputs, buffer sizes, delimiter
+// changes and resizes, and getting the same results as the Rust port,
+// shows that rawmemchr() always stops at or before the sentinel.
+
+#[test]
+fn sentinel_in_buffer_stops_rawmemchr() {
+    let pgsz = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let mut seed = 31u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+    let mut guarded = 0;
+
+    for _ in 0..400 {
+        let bufsz = match rand(3) {
+            0 => 1 + rand(12),
+            1 => pgsz - 3 + rand(4),
+            _ => 2 * pgsz - 2,
+        };
+        let step = bufsz + rand(3);
+        let mut input = vec![b'a'; bufsz + 1];
+        input.extend((0..rand(3 * bufsz)).map(|_| b"ab\n\0"[rand(4)]));
+
+        // Script ops: 0 getline, 1 switch delimiterbyte, 2 resize.
+        let script: Vec<(usize, usize)> = (0..4 * bufsz.min(64))
+            .map(|_| match rand(10) {
+                0 => (1, rand(2)),
+                1 => (2, 1 + rand(2 * bufsz)),
+                _ => (0, 0),
+            })
+            .collect();
+
+        let mut rs = RawScan::new(Trickle { data: &input, step }, bufsz, b'\n');
+        let mut expected: Steps = Vec::new();
+        for &(op, arg) in &script {
+            match op {
+                0 => {
+                    let rt = rs.getline();
+                    let kind = rt.result_type();
+                    expected.push((Some(kind), rt.line().map(<[u8]>::to_vec).unwrap_or_default()));
+                }
+                1 => rs.set_delimiterbyte(b"\n\0"[arg]),
+                _ => expected.push((None, vec![rs.resize(arg).is_ok() as u8])),
+            }
+        }
+
+        let mapsz = pgsz + (bufsz + 2).div_ceil(pgsz) * pgsz;
+        let mut trickle = Trickle { data: &input, step };
+        let mut results: Steps = Vec::new();
+        unsafe {
+            let guard = guard_above_hole(mapsz, pgsz);
+            let mut rsp = rs_open_flags(-1, bufsz, b'\n' as _, RS_SENTINEL_IN_BUFFER);
+            assert!(!rsp.is_null());
+            rs_set_read_fn(rsp, Some(trickle_read), (&mut trickle as *mut Trickle).cast());
+
+            for (i, &(op, arg)) in script.iter().enumerate() {
+                match op {
+                    0 => {
+                        let rt = rs_getline(rsp);
+                        let kind = result_type(rt.type_);
+                        if i == 0 {
+                            // The first chunk fills the buffer, so ends
+                            // just below the sentinel.
+                            assert_eq!(kind, ResultType::StartLongline);
+                            let buftop = rt.u.line.end.add(1) as *const u8;
+                            assert_eq!(*buftop, b'\n');
+                            assert_eq!(*buftop.add(1), 0);
+                            if buftop.add(2) == guard {
+                                guarded += 1;
+                            }
+                        }
+                        results.push((Some(kind), line_of(&rt, kind)));
+                    }
+                    1 => assert_eq!(rs_set_delimiterbyte(rsp, b"\n\0"[arg] as _), 0),
+                    _ => {
+                        let nrsp = rs_resize(rsp, arg);
+                        results.push((None, vec![!nrsp.is_null() as u8]));
+                        if !nrsp.is_null() {
+                            rsp = nrsp;
+                        }
+                    }
+                }
+            }
+            rs_close(rsp);
+            assert_eq!(libc::munmap(guard.cast(), pgsz), 0);
+        }
+        assert_eq!(
+            results,
+            expected,
+            "input {:?} bufsz {} step {} script {:?}",
+            String::from_utf8_lossy(&input),
+            bufsz,
+            step,
+            script
+        );
+    }
+    assert!(guarded > 100, "only {} of 400 streams landed below a guard page", guarded);
+}
+
+#[test]
+fn rs_open_flags_rejects_unknown_flags() {
+    unsafe {
+        assert!(rs_open_flags(-1, 16, b'\n' as _, 0x2).is_null());
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+    }
+}
+
+// A RAWSCAN_RESULT of the given kind, for the line in "text".
+
+fn result_of(kind: rs_result_type, text: &[u8]) -> RAWSCAN_RESULT {
+    let begin = text.as_ptr().cast::<std::os::raw::c_char>();
+    let end = begin.wrapping_add(text.len()).wrapping_sub(1);
+    RAWSCAN_RESULT { type_: kind, u: RAWSCAN_RESULT_union { line: RAWSCAN_RESULT_line { begin, end } } }
+}
+
+unsafe fn field_bytes<'a>(field: &RAWSCAN_FIELD) -> &'a [u8] {
+    slice::from_raw_parts(field.begin.cast(), field.len)
+}
+
+#[test]
+fn c_and_rust_agree_on_fields() {
+    let mut seed = 37u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+
+    for _ in 0..5000 {
+        let mut text: Vec<u8> = (0..rand(20)).map(|_| b"ab, \t"[rand(5)]).collect();
+        let (kind, rkind) = match rand(3) {
+            0 => {
+                text.push(b'\n');
+                (rt_full_line, ResultType::FullLine)
+            }
+            1 => (rt_full_line_without_eol, ResultType::FullLineWithoutEol),
+            _ => (rt_within_longline, ResultType::WithinLongline),
+        };
+        if text.is_empty() {
+            continue;
+        }
+        let sep = b", \t"[rand(3)];
+        let runs = rand(2) == 0;
+
+        let rust = match rkind {
+            ResultType::FullLine => rawscan::RawScanResult::FullLine(&text),
+            ResultType::FullLineWithoutEol => rawscan::RawScanResult::FullLineWithoutEol(&text),
+            _ => rawscan::RawScanResult::WithinLongline(&text),
+        };
+        let expected: Vec<&[u8]> = rust.fields(sep, runs).collect();
+
+        let rt = result_of(kind, &text);
+        let mut got = Vec::new();
+        unsafe {
+            let mut fields = mem::zeroed::<RAWSCAN_FIELDS>();
+            let mut field = mem::zeroed::<RAWSCAN_FIELD>();
+            rs_fields_init(&mut fields, rt, sep as _, runs);
+            while rs_next_field(&mut fields, &mut field) {
+                got.push(field_bytes(&field));
+            }
+            assert_eq!(rs_field_count(rt, sep as _, runs), expected.len());
+            let n = rand(expected.len() + 2);
+            let found = rs_nth_field(rt, sep as _, runs, n, &mut field);
+            assert_eq!(found.then(|| field_bytes(&field)), expected.get(n).copied());
+        }
+        assert_eq!(got, expected, "text {:?} sep {:?} runs {}", String::from_utf8_lossy(&text), sep as char, runs);
+    }
+
+    // Results without lines have no fields.
+    unsafe {
+        let rt = RAWSCAN_RESULT { type_: rt_eof, u: mem::zeroed() };
+        assert_eq!(rs_field_count(rt, b',' as _, false), 0);
+    }
+}
+
+#[test]
+fn c_and_rust_agree_on_numeric_fields() {
+    let mut seed = 41u64;
+    let mut rand = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n as u64) as usize
+    };
+    let mut numbers = vec![
+        b"9223372036854775807".to_vec(),
+        b"-9223372036854775808".to_vec(),
+        b"9223372036854775808".to_vec(),
+        b"-9223372036854775809".to_vec(),
+        b"99999999999999999999".to_vec(),
+    ];
+    for _ in 0..20000 {
+        numbers.push((0..rand(9)).map(|_| b"0123456789+-.e"[rand(14).min(rand(14))]).collect());
+    }
+
+    for text in &numbers {
+        let field = RAWSCAN_FIELD { begin: text.as_ptr().cast(), len: text.len() };
+        let context = String::from_utf8_lossy(text);
+        unsafe {
+            let mut long = 0;
+            let ok = rs_field_to_long(field, &mut long) == 0;
+            assert_eq!(ok.then_some(long), rawscan::parse_field::<i64>(text), "{}", context);
+
+            let mut double = 0.0;
+            let ok = rs_field_to_double(field, &mut double) == 0;
+            match rawscan::parse_decimal(text) {
+                Some(x) if x.is_finite() => assert!(ok && double == x, "{} {} {}", context, double, x),
+                Some(_) => assert!(!ok, "{} too large", context),
+                None => assert!(!ok, "{}", context),
+            }
+        }
+    }
+}
+
+// Fields that the 64 byte copy, strtod() alone, or f64::from_str()
+// alone would get wrong: long enough that only the digits dropped
+// decide the rounding, too large or small for any exponent to fit,
+// and the hexadecimal floats, "inf" and "nan" that only some take.
+
+#[test]
+fn c_and_rust_agree_on_decimal_fields() {
+    let zeros = "0".repeat(1000);
+    let nines = "9".repeat(1000);
+    let fields = [
+        // 2^53 + 1, halfway between doubles, rounds to even, unless
+        // some later digit, however far on, is nonzero.
+        "9007199254740993".to_string(),
+        format!("9007199254740993.{}1", zeros),
+        format!("9007199254740993{}e-1000", zeros),
+        format!("0.{}1e1001", zeros),
+        format!("1{}", zeros),
+        format!("-{}", nines),
+        format!(".{}", nines),
+        format!("{}e-1300", nines),
+        "2.4703282292062327e-324".to_string(),
+        "2.4703282292062328e-324".to_string(),
+        "1.7976931348623157e308".to_string(),
+        "1.7976931348623159e308".to_string(),
+        "1e99999999999999999999".to_string(),
+        "-1e-99999999999999999999".to_string(),
+        "0e99999999999999999999".to_string(),
+        "-0".to_string(),
+        "+.5".to_string(),
+        "7.".to_string(),
+        "1E+2".to_string(),
+    ];
+    let not_numbers = [
+        "", ".", "+", "e5", "1e", "1e+", "1.2.3", " 1", "1 ", "1,5", "inf", "-inf", "infinity", "nan",
+        "0x10", "0x1p3",
+    ];
+
+    assert_eq!(rawscan::parse_decimal(fields[0].as_bytes()), Some(9007199254740992.0));
+    assert_eq!(rawscan::parse_decimal(fields[1].as_bytes()), Some(9007199254740994.0));
+    assert_eq!(rawscan::parse_decimal(fields[3].as_bytes()), Some(1.0));
+
+    for text in fields.iter().map(String::as_str).chain(not_numbers) {
+        let field = RAWSCAN_FIELD { begin: text.as_ptr().cast(), len: text.len() };
+        let context = &text[..text.len().min(40)];
+        let mut double = 0.0;
+        let ok = unsafe { rs_field_to_double(field, &mut double) } == 0;
+        let errno = io::Error::last_os_error().raw_os_error();
+        match rawscan::parse_decimal(text.as_bytes()) {
+            Some(x) if x.is_infinite() => assert!(!ok && errno == Some(libc::ERANGE), "{} too large", context),
+            Some(x) => assert!(ok && double.to_bits() == x.to_bits(), "{} {} {}", context, double, x),
+            None => assert!(!ok && errno == Some(libc::EINVAL), "{}", context),
+        }
+    }
+}